@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::error::Error;
+use std::fs;
+use serde::de::DeserializeOwned;
+use serde_derive::{Serialize, Deserialize};
+use crate::ffi_wrapper::virtual_hooks::VirtualType;
+use crate::ffi_wrapper::fields::FieldType;
+
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+#[serde(deny_unknown_fields)]
+pub struct HotReloadConfig {
+  #[serde(default)]
+  pub enabled: bool,
+  // In seconds
+  #[serde(default = "default_poll_interval")]
+  pub poll_interval: f32,
+}
+
+fn default_poll_interval() -> f32 {
+  1.0
+}
+
+impl Default for HotReloadConfig {
+  fn default() -> Self {
+    HotReloadConfig {
+      enabled: false,
+      poll_interval: default_poll_interval(),
+    }
+  }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Config {
+  #[serde(default)]
+  pub hot_reload: HotReloadConfig,
+}
+
+impl Config {
+  /// Loads `Luna.toml`. A missing file simply means everything is left at
+  /// its default.
+  pub fn load(file_path: &Path) -> Result<Self, Box<dyn Error>> {
+    if !file_path.exists() {
+      return Ok(Config::default());
+    }
+
+    let contents = fs::read_to_string(file_path)?;
+    Ok(toml::from_str(&contents)?)
+  }
+}
+
+/// A virtual function of the game's entity classes, as listed in the mod's
+/// `VirtualHooks/<game dir>.toml`. The vtable index differs between the
+/// Windows and Linux builds of a mod.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+#[serde(deny_unknown_fields)]
+pub struct VirtualFunctionConfig {
+  pub windows: usize,
+  pub linux: usize,
+  #[serde(default)]
+  pub args: Vec<VirtualType>,
+  #[serde(default = "default_return_type", rename = "Return")]
+  pub return_type: VirtualType,
+}
+
+fn default_return_type() -> VirtualType {
+  VirtualType::Void
+}
+
+impl VirtualFunctionConfig {
+  /// The function's index in the vtable on this platform.
+  pub fn offset(&self) -> usize {
+    if cfg!(windows) { self.windows } else { self.linux }
+  }
+}
+
+/// Loads a mod's virtual function table, by function name. A missing file
+/// means no functions can be hooked.
+pub fn load_virtual_functions(
+  file_path: &Path,
+) -> Result<HashMap<String, VirtualFunctionConfig>, Box<dyn Error>> {
+  load_game_table(file_path)
+}
+
+/// A field of the game's entity classes, as listed in the mod's
+/// `PrivateData/<game dir>.toml`. Offsets are in bytes from the start of the
+/// entity's private data, the Linux build of a mod is usually off by a few
+/// bytes.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+#[serde(deny_unknown_fields)]
+pub struct PrivateDataFieldConfig {
+  #[serde(rename = "Type")]
+  pub field_type: FieldType,
+  pub offset: usize,
+  #[serde(default)]
+  pub linux_diff: isize,
+  // Number of elements for arrays, 1 is a plain field
+  #[serde(default = "default_count")]
+  pub count: usize,
+}
+
+fn default_count() -> usize {
+  1
+}
+
+impl PrivateDataFieldConfig {
+  /// The field's offset on this platform.
+  pub fn offset(&self) -> usize {
+    match cfg!(windows) {
+      true => self.offset,
+      // Checked when the fields are loaded
+      false => self.linux_offset().unwrap(),
+    }
+  }
+
+  fn linux_offset(&self) -> Option<usize> {
+    self.offset.checked_add_signed(self.linux_diff)
+  }
+}
+
+/// Loads a mod's private data fields, by field name. A missing file means
+/// there are none.
+pub fn load_private_data(
+  file_path: &Path,
+) -> Result<HashMap<String, PrivateDataFieldConfig>, Box<dyn Error>> {
+  check_private_data(load_game_table(file_path)?)
+}
+
+// Fields that would start before the private data are refused, on every
+// platform so that mistakes show up wherever the file is tested.
+fn check_private_data(
+  fields: HashMap<String, PrivateDataFieldConfig>,
+) -> Result<HashMap<String, PrivateDataFieldConfig>, Box<dyn Error>> {
+  if let Some((name, _)) = fields.iter().find(|(_, field)| field.linux_offset().is_none()) {
+    return Err(format!("The Linux offset of field \"{}\" is negative", name).into());
+  }
+  Ok(fields)
+}
+
+fn load_game_table<T: DeserializeOwned>(
+  file_path: &Path,
+) -> Result<HashMap<String, T>, Box<dyn Error>> {
+  if !file_path.exists() {
+    return Ok(HashMap::new());
+  }
+
+  let contents = fs::read_to_string(file_path)?;
+  Ok(toml::from_str(&contents)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_virtual_functions() {
+    let functions: HashMap<String, VirtualFunctionConfig> = toml::from_str(r#"
+      [TakeDamage]
+      Windows = 12
+      Linux = 14
+      Args = ["Entity", "Entity", "Float", "Int"]
+      Return = "Int"
+
+      [Think]
+      Windows = 40
+      Linux = 42
+    "#).unwrap();
+
+    let take_damage = &functions["TakeDamage"];
+    assert_eq!(take_damage.offset(), if cfg!(windows) { 12 } else { 14 });
+    assert_eq!(take_damage.args, vec![
+      VirtualType::Entity,
+      VirtualType::Entity,
+      VirtualType::Float,
+      VirtualType::Int,
+    ]);
+    assert_eq!(take_damage.return_type, VirtualType::Int);
+
+    let think = &functions["Think"];
+    assert!(think.args.is_empty());
+    assert_eq!(think.return_type, VirtualType::Void);
+  }
+
+  #[test]
+  fn rejects_unknown_virtual_function_keys() {
+    let result = toml::from_str::<HashMap<String, VirtualFunctionConfig>>(r#"
+      [Think]
+      Windows = 40
+      Linux = 42
+      Offset = 3
+    "#);
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn parses_private_data_fields() {
+    let fields: HashMap<String, PrivateDataFieldConfig> = toml::from_str(r#"
+      [m_iAccount]
+      Type = "Int"
+      Offset = 460
+      LinuxDiff = 20
+
+      [m_rgAmmo]
+      Type = "Int"
+      Offset = 284
+      LinuxDiff = -4
+      Count = 32
+
+      [m_fIsVIP]
+      Type = "Bool"
+      Offset = 100
+    "#).unwrap();
+
+    let account = &fields["m_iAccount"];
+    assert_eq!(account.field_type, FieldType::Int);
+    assert_eq!(account.offset(), if cfg!(windows) { 460 } else { 480 });
+    assert_eq!(account.count, 1);
+
+    let ammo = &fields["m_rgAmmo"];
+    assert_eq!(ammo.offset(), if cfg!(windows) { 284 } else { 280 });
+    assert_eq!(ammo.count, 32);
+
+    let vip = &fields["m_fIsVIP"];
+    assert_eq!(vip.field_type, FieldType::Bool);
+    assert_eq!(vip.offset(), 100);
+  }
+
+  #[test]
+  fn rejects_negative_linux_offsets() {
+    let fields: HashMap<String, PrivateDataFieldConfig> = toml::from_str(r#"
+      [m_iHealth]
+      Type = "Int"
+      Offset = 4
+      LinuxDiff = -8
+    "#).unwrap();
+
+    let error = check_private_data(fields).err().unwrap();
+    assert_eq!(error.to_string(), "The Linux offset of field \"m_iHealth\" is negative");
+  }
+
+  #[test]
+  fn rejects_unknown_field_types() {
+    for field_type in &["Pointer", "Qboolean"] {
+      let result = toml::from_str::<HashMap<String, PrivateDataFieldConfig>>(&format!(
+        "[m_field]\nType = \"{}\"\nOffset = 4\n", field_type,
+      ));
+      assert!(result.is_err(), "{}", field_type);
+    }
+  }
+}
@@ -1,89 +1,201 @@
 pub mod hl_lua_bridge;
+pub mod dll_hooks;
+pub mod vector;
+pub mod player;
+pub mod virtual_hooks;
+pub mod private_data;
+pub mod fields;
 
 use std::ffi::{CString, CStr};
-use std::os::raw::{c_char, c_int};
+use std::os::raw::{c_char, c_int, c_float};
 use std::path::PathBuf;
 use crate::module;
+use crate::meta_api;
 use crate::plugin_info::PLUGIN_INFO;
+use crate::meta_ffi::constant::REJECT_REASON_SIZE;
 use crate::meta_ffi::globals::{
   ENGINE_FUNCTIONS,
+  GAME_DLL_FUNCTIONS,
+  GLOBAL_VARS,
   META_UTIL_FUNCS,
+  META_GLOBALS,
+};
+use crate::meta_ffi::util::{
+  prev_meta_result,
+  get_meta_orig_ret,
+  get_meta_override_ret,
 };
 use crate::meta_ffi::types::{
   Edict,
   EntVars,
   EngineStringHandle,
+  EngineVector3,
+  MetaResult,
 };
-use self::hl_lua_bridge::{EntityHandle};
+use self::hl_lua_bridge::EntityHandle;
+use self::player::PlayerHandle;
+use self::dll_hooks::{DllHooks, HookResult};
+use self::virtual_hooks::{VirtualCall, VirtualValue};
 
 // TODO: Redo this module, organize things better
 
+// Hooks can run inside each other, so only shared references to the context
+// are ever handed out.
 static mut MODULE_CONTEXT: Option<Box<dyn MetaContext>> = None;
 
-pub trait MetaContext {
-  fn client_connect(&mut self, _entity: EntityHandle) { }
-  fn client_put_in_server(&mut self, _entity: EntityHandle) { }
-  fn client_disconnect(&mut self, _entity: EntityHandle) { }
-  fn client_put_in_server_post(&mut self, _entity: EntityHandle) { }
-  fn client_disconnect_post(&mut self, _entity: EntityHandle) { }
+// The context, if Luna is running. Only to be used from the main thread.
+fn module_context() -> Option<&'static dyn MetaContext> {
+  unsafe { (*std::ptr::addr_of!(MODULE_CONTEXT)).as_deref() }
+}
+
+pub trait MetaContext: DllHooks {
+  fn client_connect(&self, _player: PlayerHandle, _name: &str, _address: &str) -> ConnectResponse {
+    ConnectResponse::Accept(MetaResult::Ignored)
+  }
+  fn client_put_in_server(&self, _player: PlayerHandle) -> MetaResult { MetaResult::Ignored }
+  fn client_disconnect(&self, _player: PlayerHandle) -> MetaResult { MetaResult::Ignored }
+  fn client_put_in_server_post(&self, _player: PlayerHandle) -> MetaResult { MetaResult::Ignored }
+  fn client_disconnect_post(&self, _player: PlayerHandle) -> MetaResult { MetaResult::Ignored }
+  fn client_command(&self, _player: PlayerHandle, _args: &[String]) -> MetaResult { MetaResult::Ignored }
+  fn start_frame(&self) { }
+  fn server_command(&self, _args: &[String]) { }
+  fn on_free_ent_private_data(&self, _entity: EntityHandle) { }
+  /// Runs before (`post` is `false`) and after a hooked virtual function.
+  fn virtual_hook(&self, _call: &VirtualCall, _post: bool) -> HookResult<VirtualValue> {
+    (MetaResult::Ignored, None)
+  }
 }
 
 
 
+pub enum ConnectResponse {
+  Accept(MetaResult),
+  /// Refuses the connection, the client is shown the reason.
+  Reject(String),
+}
+
 pub unsafe fn game_init() {
   let ctx: Box<dyn MetaContext> = module::module_init();
   MODULE_CONTEXT = Some(ctx);
 }
 
+/// Shuts Luna down. Also runs when Luna is unloaded while the game keeps
+/// going, so it may run twice.
 pub unsafe fn game_shutdown() {
-  let ctx = MODULE_CONTEXT.take().unwrap();
-  module::module_shutdown(ctx);
+  if let Some(ctx) = (*std::ptr::addr_of_mut!(MODULE_CONTEXT)).take() {
+    module::module_shutdown(ctx);
+  }
+  // The game's vtables outlive Luna
+  virtual_hooks::restore_all();
 }
 
 pub unsafe fn client_connect(
   entity: *mut Edict,
-  _name: *const c_char,
-  _address: *const c_char,
-  _reject_reason: *mut c_char,
-) -> c_int {
-  if let Some(ctx) = MODULE_CONTEXT.as_mut() {
-    // TODO: Possibly make this a Player handle instead of an Edict handle
-    let entity_handle = EntityHandle::new(&*entity);
-    ctx.client_connect(entity_handle);
+  name: *const c_char,
+  address: *const c_char,
+  reject_reason: *mut c_char,
+) -> (MetaResult, c_int) {
+  if let Some(ctx) = module_context() {
+    let name = CStr::from_ptr(name).to_string_lossy();
+    let address = CStr::from_ptr(address).to_string_lossy();
+    let player = player::player_connecting(&*entity, &address);
+
+    match ctx.client_connect(player, &name, &address) {
+      ConnectResponse::Accept(result) => return (result, 1),
+      ConnectResponse::Reject(reason) => {
+        write_reject_reason(reject_reason, &reason);
+        player::player_left(&*entity);
+        return (MetaResult::Supercede, 0);
+      }
+    }
   }
 
-  1
+  (MetaResult::Ignored, 1)
 }
 
-pub unsafe fn client_put_in_server(entity: *mut Edict) {
-  if let Some(ctx) = MODULE_CONTEXT.as_mut() {
-    // TODO: Possibly make this a Player handle instead of an Edict handle
-    let entity_handle = EntityHandle::new(&*entity);
-    ctx.client_put_in_server(entity_handle);
+unsafe fn write_reject_reason(buffer: *mut c_char, reason: &str) {
+  // Leave room for the null and don't cut a character in half
+  let mut len = reason.len().min(REJECT_REASON_SIZE - 1);
+  while !reason.is_char_boundary(len) {
+    len -= 1;
   }
+
+  let reason = CString::new(reason[..len].replace('\0', "")).unwrap();
+  let bytes = reason.as_bytes_with_nul();
+  std::ptr::copy_nonoverlapping(bytes.as_ptr() as *const c_char, buffer, bytes.len());
 }
 
-pub unsafe fn client_put_in_server_post(entity: *mut Edict) {
-  if let Some(ctx) = MODULE_CONTEXT.as_mut() {
-    // TODO: Possibly make this a Player handle instead of an Edict handle
-    let entity_handle = EntityHandle::new(&*entity);
-    ctx.client_put_in_server_post(entity_handle);
+pub unsafe fn client_put_in_server(entity: *mut Edict) -> MetaResult {
+  if let Some(ctx) = module_context() {
+    if let Some(player) = player::player_entered(&*entity) {
+      return ctx.client_put_in_server(player);
+    }
   }
+
+  MetaResult::Ignored
 }
 
-pub unsafe fn client_disconnect(entity: *mut Edict) {
-  if let Some(ctx) = MODULE_CONTEXT.as_mut() {
-    // TODO: Possibly make this a Player handle instead of an Edict handle
-    let entity_handle = EntityHandle::new(&*entity);
-    ctx.client_disconnect(entity_handle);
+pub unsafe fn client_put_in_server_post(entity: *mut Edict) -> MetaResult {
+  if let Some(ctx) = module_context() {
+    if let Some(player) = PlayerHandle::from_edict(&*entity) {
+      return ctx.client_put_in_server_post(player);
+    }
   }
+
+  MetaResult::Ignored
 }
 
-pub unsafe fn client_disconnect_post(entity: *mut Edict) {
-  if let Some(ctx) = MODULE_CONTEXT.as_mut() {
-    // TODO: Possibly make this a Player handle instead of an Edict handle
-    let entity_handle = EntityHandle::new(&*entity);
-    ctx.client_disconnect_post(entity_handle);
+pub unsafe fn client_disconnect(entity: *mut Edict) -> MetaResult {
+  if let Some(ctx) = module_context() {
+    if let Some(player) = PlayerHandle::from_edict(&*entity) {
+      return ctx.client_disconnect(player);
+    }
+  }
+
+  MetaResult::Ignored
+}
+
+pub unsafe fn client_disconnect_post(entity: *mut Edict) -> MetaResult {
+  if let Some(ctx) = module_context() {
+    // Still connected while the listeners run
+    let result = match PlayerHandle::from_edict(&*entity) {
+      Some(player) => ctx.client_disconnect_post(player),
+      None => MetaResult::Ignored,
+    };
+    player::player_left(&*entity);
+    return result;
+  }
+
+  MetaResult::Ignored
+}
+
+pub unsafe fn client_command(entity: *mut Edict) -> MetaResult {
+  if let Some(ctx) = module_context() {
+    if let Some(player) = PlayerHandle::from_edict(&*entity) {
+      return ctx.client_command(player, &command_args());
+    }
+  }
+
+  MetaResult::Ignored
+}
+
+pub unsafe fn on_free_ent_private_data(entity: *mut Edict) {
+  if let Some(ctx) = module_context() {
+    if !entity.is_null() {
+      ctx.on_free_ent_private_data(EntityHandle::new(&*entity));
+    }
+  }
+}
+
+pub unsafe fn start_frame() {
+  if let Some(ctx) = module_context() {
+    ctx.start_frame();
+  }
+}
+
+pub unsafe fn server_command() {
+  if let Some(ctx) = module_context() {
+    ctx.server_command(&command_args());
   }
 }
 
@@ -93,7 +205,7 @@ pub unsafe fn client_disconnect_post(entity: *mut Edict) {
 // Let's just make sure we actually call these in the main thread.
 // Our main Lua state is always going to execute in the main thread anyway.
 
-pub fn _server_print(message: impl AsRef<str>) {
+pub fn server_print(message: impl AsRef<str>) {
   if let Ok(msg) = CString::new(message.as_ref()) {
     unsafe {
       ((*ENGINE_FUNCTIONS).server_print)(msg.as_ptr());
@@ -101,6 +213,85 @@ pub fn _server_print(message: impl AsRef<str>) {
   }
 }
 
+/// Registers a server console command. Every command is dispatched to
+/// `MetaContext::server_command`.
+pub fn add_server_command(name: impl AsRef<str>) {
+  // The engine keeps the name around for as long as it runs, so it's leaked
+  if let Ok(name) = CString::new(name.as_ref()) {
+    unsafe {
+      ((*ENGINE_FUNCTIONS).add_server_command)(
+        name.into_raw(),
+        meta_api::server_command,
+      );
+    }
+  }
+}
+
+/// Whether the engine or the game has registered a console variable called
+/// `name`.
+pub fn is_cvar(name: impl AsRef<str>) -> bool {
+  match CString::new(name.as_ref()) {
+    Ok(name) => unsafe { !((*ENGINE_FUNCTIONS).cvar_get_pointer)(name.as_ptr()).is_null() },
+    Err(_) => false,
+  }
+}
+
+/// Arguments of the console command that is currently being executed,
+/// including the command name itself.
+pub fn command_args() -> Vec<String> {
+  unsafe {
+    let argc = ((*ENGINE_FUNCTIONS).cmd_argc)();
+    (0..argc)
+      .map(|i| CStr::from_ptr(((*ENGINE_FUNCTIONS).cmd_argv)(i)))
+      .map(|arg| arg.to_string_lossy().into_owned())
+      .collect()
+  }
+}
+
+/// Everything after the name of the console command that is currently being
+/// executed, as typed.
+pub fn command_arg_string() -> String {
+  unsafe {
+    let args = ((*ENGINE_FUNCTIONS).cmd_args)();
+    match args.is_null() {
+      true => String::new(),
+      false => CStr::from_ptr(args).to_string_lossy().into_owned(),
+    }
+  }
+}
+
+/// Result that the plugins before Luna returned from the current hook.
+pub fn previous_meta_result() -> MetaResult {
+  unsafe { prev_meta_result() }
+}
+
+/// Return value of the game function in the current post hook. `None` if
+/// there is none.
+pub fn meta_orig_return() -> Option<c_int> {
+  unsafe {
+    match (*META_GLOBALS).orig_ret.is_null() {
+      true => None,
+      false => Some(get_meta_orig_ret()),
+    }
+  }
+}
+
+/// Return value another plugin overrode the game function's with. `None` if
+/// there is none.
+pub fn meta_override_return() -> Option<c_int> {
+  unsafe {
+    match (*META_GLOBALS).override_ret.is_null() {
+      true => None,
+      false => Some(get_meta_override_ret()),
+    }
+  }
+}
+
+/// Seconds since the current map started.
+pub fn engine_time() -> f32 {
+  unsafe { ((*ENGINE_FUNCTIONS).time)() }
+}
+
 pub fn string_from_handle(handle: EngineStringHandle) -> String {
   unsafe { CStr::from_ptr(((*ENGINE_FUNCTIONS).sz_from_index)(handle.0)) }
     .to_str()
@@ -115,33 +306,238 @@ pub fn handle_from_string(s: impl AsRef<str>) -> EngineStringHandle {
   })
 }
 
+/// A copy of `s` that the engine owns. It stays valid until the map
+/// changes.
+pub fn engine_string(s: &str) -> *const c_char {
+  let handle = handle_from_string(s);
+  unsafe { ((*ENGINE_FUNCTIONS).sz_from_index)(handle.0) }
+}
+
+/// Index of `edict` in the engine's entity list, players are 1 to
+/// `max_clients()`.
+pub fn index_of_edict(edict: &Edict) -> c_int {
+  unsafe { ((*ENGINE_FUNCTIONS).index_of_edict)(edict) }
+}
+
+/// The entity at `index`, `None` if the slot isn't in use.
+pub fn edict_of_index<'a>(index: c_int) -> Option<&'a Edict> {
+  unsafe {
+    ((*ENGINE_FUNCTIONS).p_entity_of_ent_index)(index)
+      .as_ref()
+      .filter(|edict| !edict.is_free())
+  }
+}
+
+/// Whether a map is loaded. Entities can't be created before.
+pub fn is_map_running() -> bool {
+  unsafe { GLOBAL_VARS.as_ref().is_some_and(|globals| globals.mapname.0 != 0) }
+}
+
+/// Name of the mod's directory, like `valve` or `cstrike`.
+pub fn game_dir() -> String {
+  let mut buffer = [0 as c_char; 256];
+  unsafe {
+    ((*ENGINE_FUNCTIONS).get_game_dir)(buffer.as_mut_ptr());
+    CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned()
+  }
+}
+
+/// Number of player slots on the server.
+pub fn max_clients() -> c_int {
+  unsafe { GLOBAL_VARS.as_ref().map_or(0, |globals| globals.max_clients) }
+}
+
+pub fn player_user_id(edict: &Edict) -> c_int {
+  unsafe {
+    ((*ENGINE_FUNCTIONS).get_player_user_id)(edict as *const Edict as *mut Edict)
+  }
+}
+
+/// The player's SteamID, `None` if the engine doesn't know it.
+pub fn player_auth_id(edict: &Edict) -> Option<String> {
+  unsafe {
+    let auth_id = ((*ENGINE_FUNCTIONS).get_player_auth_id)(
+      edict as *const Edict as *mut Edict
+    );
+    match auth_id.is_null() {
+      true => None,
+      false => Some(CStr::from_ptr(auth_id).to_string_lossy().into_owned()),
+    }
+  }
+}
+
+/// The player's ping in milliseconds and packet loss in percent.
+pub fn player_stats(edict: &Edict) -> (c_int, c_int) {
+  let (mut ping, mut loss) = (0, 0);
+  unsafe { ((*ENGINE_FUNCTIONS).get_player_stats)(edict, &mut ping, &mut loss) };
+  (ping, loss)
+}
+
+/// Creates an entity of the game's class `classname`, `None` if the game
+/// doesn't know the class. It still has to be spawned.
+pub fn create_named_entity<'a>(classname: &str) -> Option<&'a Edict> {
+  let classname = handle_from_string(classname);
+  unsafe { ((*ENGINE_FUNCTIONS).create_named_entity)(classname.0).as_ref() }
+}
+
+/// Creates an entity and has the game set it up as its class `classname`
+/// through Metamod. `None` if the game doesn't know the class.
+pub fn create_game_entity<'a>(classname: &str) -> Option<&'a Edict> {
+  let classname = CString::new(classname).ok()?;
+  unsafe {
+    let edict = ((*ENGINE_FUNCTIONS).create_entity)();
+    let entvars = ((*ENGINE_FUNCTIONS).get_vars_of_ent)(edict);
+    if ((*META_UTIL_FUNCS).call_game_entity)(&PLUGIN_INFO, classname.as_ptr(), entvars) == 0 {
+      ((*ENGINE_FUNCTIONS).remove_entity)(edict);
+      return None;
+    }
+    edict.as_ref()
+  }
+}
+
+/// Has the game spawn `edict`. Returns `false` if the game refused, the
+/// entity is removed then.
+pub fn spawn_entity(edict: &Edict) -> bool {
+  let edict = edict as *const Edict as *mut Edict;
+  unsafe {
+    // Calling the game directly skips Metamod, so Luna's own hook is run
+    // here. Otherwise Lua entity classes wouldn't spawn.
+    let result = match dll_hooks::spawn(edict) {
+      (MetaResult::Supercede, value) => value.unwrap_or(0),
+      _ => ((*(*GAME_DLL_FUNCTIONS).dllapi_table).spawn)(edict),
+    };
+
+    if result == -1 {
+      ((*ENGINE_FUNCTIONS).remove_entity)(edict);
+      return false;
+    }
+  }
+  true
+}
+
+/// Runs the game's own `Spawn` for `edict`, without Luna's hook.
+pub fn game_spawn(edict: &Edict) -> c_int {
+  unsafe {
+    ((*(*GAME_DLL_FUNCTIONS).dllapi_table).spawn)(edict as *const Edict as *mut Edict)
+  }
+}
+
+pub fn set_classname(edict: &Edict, classname: &str) {
+  let classname = handle_from_string(classname);
+  unsafe { (*entvars_of_edict_mut(edict)).classname = classname };
+}
+
+/// Makes the engine call the game's `Think` for `edict` at `time`.
+pub fn set_next_think(edict: &Edict, time: c_float) {
+  unsafe { (*entvars_of_edict_mut(edict)).nextthink = time };
+}
+
+pub fn remove_entity(edict: &Edict) {
+  unsafe {
+    ((*ENGINE_FUNCTIONS).remove_entity)(edict as *const Edict as *mut Edict)
+  }
+}
+
+pub fn set_origin(edict: &Edict, origin: EngineVector3) {
+  unsafe {
+    ((*ENGINE_FUNCTIONS).set_origin)(
+      edict as *const Edict as *mut Edict,
+      &origin as *const EngineVector3 as *const c_float,
+    )
+  }
+}
+
+pub fn set_size(edict: &Edict, mins: EngineVector3, maxs: EngineVector3) {
+  unsafe {
+    ((*ENGINE_FUNCTIONS).set_size)(
+      edict as *const Edict as *mut Edict,
+      &mins as *const EngineVector3 as *const c_float,
+      &maxs as *const EngineVector3 as *const c_float,
+    )
+  }
+}
+
+pub fn set_model(edict: &Edict, model: &str) {
+  // The engine keeps pointing to the name, so it's stored as a game string
+  let model = handle_from_string(model);
+  unsafe {
+    ((*ENGINE_FUNCTIONS).set_model)(
+      edict as *const Edict as *mut Edict,
+      ((*ENGINE_FUNCTIONS).sz_from_index)(model.0),
+    )
+  }
+}
+
+/// Moves `edict` down onto the floor. Returns 1 if it landed, 0 if it's
+/// stuck and -1 if there is no floor below it.
+pub fn drop_to_floor(edict: &Edict) -> c_int {
+  unsafe {
+    ((*ENGINE_FUNCTIONS).drop_to_floor)(edict as *const Edict as *mut Edict)
+  }
+}
+
+/// The next entity after `start` whose string entvar `field` equals `value`.
+/// A null `start` searches from the beginning of the entity list. The
+/// engine returns the world when there are no more entities, so the world
+/// is never found.
+pub fn find_entity_by_string<'a>(
+  start: *mut Edict,
+  field: &str,
+  value: &str,
+) -> Option<&'a Edict> {
+  let field = CString::new(field).ok()?;
+  let value = CString::new(value).ok()?;
+  unsafe {
+    ((*ENGINE_FUNCTIONS).find_entity_by_string)(start, field.as_ptr(), value.as_ptr())
+      .as_ref()
+      .filter(|edict| !edict.is_free() && index_of_edict(edict) != 0)
+  }
+}
+
+/// The next entity after `start` that's within `radius` of `origin`.
+pub fn find_entity_in_sphere<'a>(
+  start: *mut Edict,
+  origin: EngineVector3,
+  radius: c_float,
+) -> Option<&'a Edict> {
+  unsafe {
+    ((*ENGINE_FUNCTIONS).find_entity_in_sphere)(
+      start,
+      &origin as *const EngineVector3 as *const c_float,
+      radius,
+    )
+      .as_ref()
+      .filter(|edict| !edict.is_free() && index_of_edict(edict) != 0)
+  }
+}
+
 pub fn entvars_of_edict(edict: &Edict) -> &EntVars {
   unsafe {
     &*((*ENGINE_FUNCTIONS).get_vars_of_ent)(edict as *const Edict as *mut Edict)
   }
 }
 
+unsafe fn entvars_of_edict_mut(edict: &Edict) -> *mut EntVars {
+  ((*ENGINE_FUNCTIONS).get_vars_of_ent)(edict as *const Edict as *mut Edict)
+}
+
+// Logging does nothing until Metamod has handed over its functions, e.g. in
+// tests.
 pub fn log_console(message: impl AsRef<str>) {
-  if let Ok(msg) = CString::new(message.as_ref()) {
-    unsafe {
-      ((*META_UTIL_FUNCS).log_console)(&PLUGIN_INFO, msg.as_ptr());
-    }
+  if let (Ok(msg), Some(funcs)) = (CString::new(message.as_ref()), unsafe { META_UTIL_FUNCS.as_ref() }) {
+    unsafe { (funcs.log_console)(&PLUGIN_INFO, msg.as_ptr()) };
   }
 }
 
 pub fn log_message(message: impl AsRef<str>) {
-  if let Ok(msg) = CString::new(message.as_ref()) {
-    unsafe {
-      ((*META_UTIL_FUNCS).log_message)(&PLUGIN_INFO, msg.as_ptr());
-    }
+  if let (Ok(msg), Some(funcs)) = (CString::new(message.as_ref()), unsafe { META_UTIL_FUNCS.as_ref() }) {
+    unsafe { (funcs.log_message)(&PLUGIN_INFO, msg.as_ptr()) };
   }
 }
 
 pub fn log_error(message: impl AsRef<str>) {
-  if let Ok(msg) = CString::new(message.as_ref()) {
-    unsafe {
-      ((*META_UTIL_FUNCS).log_error)(&PLUGIN_INFO, msg.as_ptr());
-    }
+  if let (Ok(msg), Some(funcs)) = (CString::new(message.as_ref()), unsafe { META_UTIL_FUNCS.as_ref() }) {
+    unsafe { (funcs.log_error)(&PLUGIN_INFO, msg.as_ptr()) };
   }
 }
 
@@ -0,0 +1,210 @@
+// Generic pre and post hooks for the `DLLFunctions` that don't have their
+// own path through `MetaContext`. Adding a Lua event for one of them takes
+// overriding its method in `DllHooks` and adding it to `HOOKED`.
+
+use std::os::raw::{c_char, c_void, c_int, c_uint, c_float, c_uchar as c_byte};
+use crate::meta_ffi::types::{
+  MetaResult,
+  Edict,
+  EntityState,
+  KeyValueData,
+  SaveRestoreData,
+  TypeDescription,
+  Customization,
+  PlayerMove,
+  ClientData,
+  WeaponData,
+  UserCmd,
+  NetAdr,
+};
+use super::module_context;
+
+/// The hooks that are overridden in `DllHooks`. Only these are put in
+/// Metamod's tables, the game calls some of the others many times a frame.
+pub const HOOKED: &[&str] = &[
+  "spawn",
+  "think",
+  "use_",
+  "touch",
+  "blocked",
+  "key_value",
+  "server_activate",
+];
+
+/// What a hook hands back to Metamod. The value is only used if the result
+/// is `Override` or `Supercede`.
+pub type HookResult<T> = (MetaResult, Option<T>);
+
+/// Invokes `$callback!` with the list of generic hooks, so that every layer
+/// is generated from the same list. Each entry is
+/// `pre_name, post_name: (arguments) -> return type;`.
+macro_rules! for_each_dll_hook {
+  ($callback:ident) => {
+    $callback! {
+      spawn, spawn_post: (entity: *mut Edict) -> c_int;
+      think, think_post: (entity: *mut Edict) -> ();
+      use_, use_post: (used: *mut Edict, other: *mut Edict) -> ();
+      touch, touch_post: (touched: *mut Edict, other: *mut Edict) -> ();
+      blocked, blocked_post: (blocked: *mut Edict, other: *mut Edict) -> ();
+      key_value, key_value_post: (
+        entity: *mut Edict,
+        data: *mut KeyValueData,
+      ) -> ();
+      save, save_post: (
+        entity: *mut Edict,
+        save_data: *mut SaveRestoreData,
+      ) -> ();
+      restore, restore_post: (
+        entity: *mut Edict,
+        save_data: *mut SaveRestoreData,
+        global_entity: c_int,
+      ) -> c_int;
+      set_abs_box, set_abs_box_post: (entity: *mut Edict) -> ();
+      save_write_fields, save_write_fields_post: (
+        save_data: *mut SaveRestoreData,
+        name: *const c_char,
+        base_data: *mut c_void,
+        fields: *mut TypeDescription,
+        field_count: c_int,
+      ) -> ();
+      save_read_fields, save_read_fields_post: (
+        save_data: *mut SaveRestoreData,
+        name: *const c_char,
+        base_data: *mut c_void,
+        fields: *mut TypeDescription,
+        field_count: c_int,
+      ) -> ();
+      save_global_state, save_global_state_post: (
+        save_data: *mut SaveRestoreData,
+      ) -> ();
+      restore_global_state, restore_global_state_post: (
+        save_data: *mut SaveRestoreData,
+      ) -> ();
+      reset_global_state, reset_global_state_post: () -> ();
+      client_kill, client_kill_post: (entity: *mut Edict) -> ();
+      client_user_info_changed, client_user_info_changed_post: (
+        entity: *mut Edict,
+        info_buffer: *mut c_char,
+      ) -> ();
+      server_activate, server_activate_post: (
+        edict_list: *mut Edict,
+        edict_count: c_int,
+        max_clients: c_int,
+      ) -> ();
+      server_deactivate, server_deactivate_post: () -> ();
+      player_pre_think, player_pre_think_post: (entity: *mut Edict) -> ();
+      player_post_think, player_post_think_post: (entity: *mut Edict) -> ();
+      parms_new_level, parms_new_level_post: () -> ();
+      parms_change_level, parms_change_level_post: () -> ();
+      get_game_description, get_game_description_post: () -> *const c_char;
+      player_customization, player_customization_post: (
+        entity: *mut Edict,
+        customization: *mut Customization,
+      ) -> ();
+      spectator_connect, spectator_connect_post: (entity: *mut Edict) -> ();
+      spectator_disconnect, spectator_disconnect_post: (entity: *mut Edict) -> ();
+      spectator_think, spectator_think_post: (entity: *mut Edict) -> ();
+      sys_error, sys_error_post: (error: *const c_char) -> ();
+      pm_move, pm_move_post: (player_move: *mut PlayerMove, server: c_int) -> ();
+      pm_init, pm_init_post: (player_move: *mut PlayerMove) -> ();
+      pm_find_texture_type, pm_find_texture_type_post: (
+        name: *mut c_char,
+      ) -> c_char;
+      setup_visibility, setup_visibility_post: (
+        view_entity: *mut Edict,
+        client: *mut Edict,
+        pvs: *mut *mut c_byte,
+        pas: *mut *mut c_byte,
+      ) -> ();
+      update_client_data, update_client_data_post: (
+        entity: *const Edict,
+        send_weapons: c_int,
+        client_data: *mut ClientData,
+      ) -> ();
+      add_to_full_pack, add_to_full_pack_post: (
+        state: *mut EntityState,
+        index: c_int,
+        entity: *mut Edict,
+        host: *mut Edict,
+        host_flags: c_int,
+        player: c_int,
+        set: *mut c_byte,
+      ) -> c_int;
+      create_baseline, create_baseline_post: (
+        player: c_int,
+        index: c_int,
+        baseline: *mut EntityState,
+        entity: *mut Edict,
+        player_model_index: c_int,
+        player_mins: *mut c_float,
+        player_maxs: *mut c_float,
+      ) -> ();
+      register_encoders, register_encoders_post: () -> ();
+      get_weapon_data, get_weapon_data_post: (
+        player: *mut Edict,
+        info: *mut WeaponData,
+      ) -> c_int;
+      cmd_start, cmd_start_post: (
+        player: *const Edict,
+        cmd: *const UserCmd,
+        random_seed: c_uint,
+      ) -> ();
+      cmd_end, cmd_end_post: (player: *const Edict) -> ();
+      connectionless_packet, connectionless_packet_post: (
+        from: *const NetAdr,
+        args: *const c_char,
+        response_buffer: *mut c_char,
+        response_buffer_size: *mut c_int,
+      ) -> c_int;
+      get_hull_bounds, get_hull_bounds_post: (
+        hull_number: c_int,
+        mins: *mut c_float,
+        maxs: *mut c_float,
+      ) -> c_int;
+      create_instanced_baselines, create_instanced_baselines_post: () -> ();
+      inconsistent_file, inconsistent_file_post: (
+        player: *const Edict,
+        filename: *const c_char,
+        disconnect_message: *mut c_char,
+      ) -> c_int;
+      allow_lag_compensation, allow_lag_compensation_post: () -> c_int;
+    }
+  };
+}
+pub(crate) use for_each_dll_hook;
+
+macro_rules! define_dispatch {
+  ($($pre:ident, $post:ident: ($($arg:ident: $ty:ty),* $(,)?) -> $ret:ty;)*) => {
+    /// Every generic hook, ignored unless overridden.
+    #[allow(clippy::too_many_arguments)]
+    pub trait DllHooks {
+      $(
+        fn $pre(&self, $(_: $ty),*) -> HookResult<$ret> {
+          (MetaResult::Ignored, None)
+        }
+
+        fn $post(&self, $(_: $ty),*) -> HookResult<$ret> {
+          (MetaResult::Ignored, None)
+        }
+      )*
+    }
+
+    $(
+      pub unsafe fn $pre($($arg: $ty),*) -> HookResult<$ret> {
+        match module_context() {
+          Some(ctx) => ctx.$pre($($arg),*),
+          None => (MetaResult::Ignored, None),
+        }
+      }
+
+      pub unsafe fn $post($($arg: $ty),*) -> HookResult<$ret> {
+        match module_context() {
+          Some(ctx) => ctx.$post($($arg),*),
+          None => (MetaResult::Ignored, None),
+        }
+      }
+    )*
+  };
+}
+
+for_each_dll_hook!(define_dispatch);
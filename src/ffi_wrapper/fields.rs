@@ -0,0 +1,222 @@
+// Reading and writing the game's memory from Lua. Entvars, globals and the
+// private data of entities all go through here, they only differ in where
+// their fields are.
+
+use std::convert::TryFrom;
+use std::os::raw::{c_int, c_float, c_short, c_uchar, c_void};
+use std::ptr;
+use serde_derive::{Serialize, Deserialize};
+use crate::meta_ffi::types::{Edict, EngineStringHandle, EngineVector3};
+use super::hl_lua_bridge::EntityHandle;
+use super::virtual_hooks::entity_of_object;
+
+/// Types of the fields plugins can access.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum FieldType {
+  Int,
+  Float,
+  /// C++ `bool`, a single byte
+  Bool,
+  /// `qboolean`, an `int`
+  #[serde(skip)]
+  Qboolean,
+  Byte,
+  Short,
+  /// `string_t`
+  String,
+  Vector,
+  /// `CBaseEntity *`
+  Entity,
+  /// `edict_t *`
+  Edict,
+}
+
+impl FieldType {
+  pub fn size(self) -> usize {
+    use std::mem::size_of;
+
+    match self {
+      FieldType::Int | FieldType::Qboolean => size_of::<c_int>(),
+      FieldType::Float => size_of::<c_float>(),
+      FieldType::Bool | FieldType::Byte => size_of::<c_uchar>(),
+      FieldType::Short => size_of::<c_short>(),
+      FieldType::String => size_of::<EngineStringHandle>(),
+      FieldType::Vector => size_of::<EngineVector3>(),
+      FieldType::Entity | FieldType::Edict => size_of::<*mut c_void>(),
+    }
+  }
+}
+
+// A Lua value converted for a field, ready to be written.
+enum FieldValue {
+  Int(c_int),
+  Float(c_float),
+  Byte(c_uchar),
+  Short(c_short),
+  String(EngineStringHandle),
+  Vector(EngineVector3),
+  Pointer(*mut c_void),
+}
+
+// Converts `value` for a field of type `field_type`. Entity fields can be set
+// to `nil`.
+fn field_value<'lua>(
+  ctx: rlua::Context<'lua>,
+  field_type: FieldType,
+  value: rlua::Value<'lua>,
+) -> rlua::Result<FieldValue> {
+  use rlua::FromLua;
+
+  let out_of_range = |value: i64, min: i64, max: i64| rlua::Error::RuntimeError(
+    format!("Value {} out of range, expected {} to {}", value, min, max),
+  );
+  let entity = |value| -> rlua::Result<Option<&Edict>> {
+    match Option::<EntityHandle>::from_lua(value, ctx)? {
+      Some(entity) => entity.get()
+        .map(Some)
+        .ok_or_else(|| rlua::Error::RuntimeError("Invalid entity".into())),
+      None => Ok(None),
+    }
+  };
+
+  Ok(match field_type {
+    FieldType::Int => FieldValue::Int(c_int::from_lua(value, ctx)?),
+    FieldType::Float => FieldValue::Float(c_float::from_lua(value, ctx)?),
+    FieldType::Bool => FieldValue::Byte(bool::from_lua(value, ctx)? as c_uchar),
+    FieldType::Qboolean => FieldValue::Int(bool::from_lua(value, ctx)? as c_int),
+    FieldType::Byte => {
+      let byte = i64::from_lua(value, ctx)?;
+      FieldValue::Byte(c_uchar::try_from(byte).map_err(|_| out_of_range(byte, 0, 255))?)
+    }
+    FieldType::Short => {
+      let short = i64::from_lua(value, ctx)?;
+      FieldValue::Short(c_short::try_from(short)
+        .map_err(|_| out_of_range(short, c_short::MIN.into(), c_short::MAX.into()))?)
+    }
+    FieldType::String => FieldValue::String(EngineStringHandle::from_lua(value, ctx)?),
+    FieldType::Vector => FieldValue::Vector(EngineVector3::from_lua(value, ctx)?),
+    FieldType::Entity => FieldValue::Pointer(
+      entity(value)?.map_or(ptr::null_mut(), Edict::private_data),
+    ),
+    FieldType::Edict => FieldValue::Pointer(
+      entity(value)?.map_or(ptr::null_mut(), |edict| edict as *const Edict as *mut c_void),
+    ),
+  })
+}
+
+// Writes `value` to the field at `address`.
+unsafe fn write_value(address: usize, value: FieldValue) {
+  match value {
+    FieldValue::Int(value) => ptr::write_unaligned(address as *mut c_int, value),
+    FieldValue::Float(value) => ptr::write_unaligned(address as *mut c_float, value),
+    FieldValue::Byte(value) => ptr::write_unaligned(address as *mut c_uchar, value),
+    FieldValue::Short(value) => ptr::write_unaligned(address as *mut c_short, value),
+    FieldValue::String(value) => ptr::write_unaligned(address as *mut EngineStringHandle, value),
+    FieldValue::Vector(value) => ptr::write_unaligned(address as *mut EngineVector3, value),
+    FieldValue::Pointer(value) => ptr::write_unaligned(address as *mut *mut c_void, value),
+  }
+}
+
+/// Reads the field of type `field_type` at `address`. Entity fields that
+/// don't point anywhere are read as an invalid entity.
+pub unsafe fn read_field<'lua>(
+  ctx: rlua::Context<'lua>,
+  address: usize,
+  field_type: FieldType,
+) -> rlua::Result<rlua::Value<'lua>> {
+  use rlua::ToLua;
+
+  match field_type {
+    FieldType::Int => ptr::read_unaligned(address as *const c_int).to_lua(ctx),
+    FieldType::Float => ptr::read_unaligned(address as *const c_float).to_lua(ctx),
+    FieldType::Bool => (ptr::read_unaligned(address as *const c_uchar) != 0).to_lua(ctx),
+    FieldType::Qboolean => (ptr::read_unaligned(address as *const c_int) != 0).to_lua(ctx),
+    FieldType::Byte => ptr::read_unaligned(address as *const c_uchar).to_lua(ctx),
+    FieldType::Short => ptr::read_unaligned(address as *const c_short).to_lua(ctx),
+    FieldType::String => ptr::read_unaligned(address as *const EngineStringHandle).to_lua(ctx),
+    FieldType::Vector => ptr::read_unaligned(address as *const EngineVector3).to_lua(ctx),
+    FieldType::Entity => {
+      entity_of_object(ptr::read_unaligned(address as *const *mut c_void))
+        .unwrap_or_default()
+        .to_lua(ctx)
+    }
+    FieldType::Edict => {
+      ptr::read_unaligned(address as *const *mut Edict)
+        .as_ref()
+        .map_or_else(EntityHandle::default, EntityHandle::new)
+        .to_lua(ctx)
+    }
+  }
+}
+
+/// Writes `value` to the field of type `field_type` at `address`.
+pub unsafe fn write_field<'lua>(
+  ctx: rlua::Context<'lua>,
+  address: usize,
+  field_type: FieldType,
+  value: rlua::Value<'lua>,
+) -> rlua::Result<()> {
+  write_value(address, field_value(ctx, field_type, value)?);
+  Ok(())
+}
+
+/// Writes the elements of `values` to the fields at `addresses`, which are
+/// all of type `field_type`. Nothing is written unless every element can be.
+pub unsafe fn write_array<'lua>(
+  ctx: rlua::Context<'lua>,
+  addresses: &[usize],
+  field_type: FieldType,
+  values: rlua::Table<'lua>,
+) -> rlua::Result<()> {
+  let values = (1..=addresses.len())
+    .map(|i| field_value(ctx, field_type, values.get(i)?))
+    .collect::<rlua::Result<Vec<_>>>()?;
+  for (&address, value) in addresses.iter().zip(values) {
+    write_value(address, value);
+  }
+  Ok(())
+}
+
+/// Where the elements of an array of fields are, see `FieldArrayHandle`.
+pub trait FieldArray: Clone + Send + 'static {
+  /// Addresses of the elements, in order, and their type.
+  fn elements(&self) -> rlua::Result<(Vec<usize>, FieldType)>;
+  /// What the array is called in error messages.
+  fn describe(&self) -> String;
+}
+
+/// An array of fields, like the `iuser` entvars or the ammo in a player's
+/// private data. Indexed from 1.
+#[derive(Clone)]
+pub struct FieldArrayHandle<T: FieldArray>(pub T);
+
+impl<T: FieldArray> FieldArrayHandle<T> {
+  // Address and type of the element `index`, counting from 1.
+  fn element(&self, index: i64) -> rlua::Result<(usize, FieldType)> {
+    let (addresses, field_type) = self.0.elements()?;
+    match usize::try_from(index).ok().and_then(|index| addresses.get(index.wrapping_sub(1))) {
+      Some(&address) => Ok((address, field_type)),
+      None => Err(rlua::Error::RuntimeError(
+        format!("Index {} out of range for {}", index, self.0.describe()),
+      )),
+    }
+  }
+}
+
+impl<T: FieldArray> rlua::UserData for FieldArrayHandle<T> {
+  fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(m: &mut M) {
+    m.add_meta_method(rlua::MetaMethod::Index, |ctx, this: &Self, index: i64| {
+      let (address, field_type) = this.element(index)?;
+      unsafe { read_field(ctx, address, field_type) }
+    });
+
+    m.add_meta_method(rlua::MetaMethod::NewIndex, |ctx, this: &Self, (index, value): (i64, rlua::Value)| {
+      let (address, field_type) = this.element(index)?;
+      unsafe { write_field(ctx, address, field_type, value) }
+    });
+
+    m.add_meta_method(rlua::MetaMethod::Len, |_, this: &Self, ()| {
+      Ok(this.0.elements()?.0.len())
+    });
+  }
+}
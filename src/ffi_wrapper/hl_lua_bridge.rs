@@ -1,11 +1,29 @@
 use std::collections::HashMap;
-use std::os::raw::{c_int, c_float};
+use std::os::raw::c_int;
+use std::mem::offset_of;
+use crate::meta_ffi::globals::GLOBAL_VARS;
 use crate::meta_ffi::types::{
   Edict,
   EntVars,
+  GlobalVars,
   EngineStringHandle,
+  EngineVector3,
+};
+use super::fields::{read_field, write_field, write_array, FieldArray, FieldArrayHandle, FieldType};
+use super::player::PlayerUserData;
+use super::{
+  entvars_of_edict,
+  string_from_handle,
+  handle_from_string,
+  index_of_edict,
+  max_clients,
+  spawn_entity,
+  remove_entity,
+  set_origin,
+  set_size,
+  set_model,
+  drop_to_floor,
 };
-use super::{entvars_of_edict, string_from_handle, handle_from_string};
 
 #[derive(Clone)]
 pub struct EntityHandle {
@@ -41,6 +59,32 @@ impl EntityHandle {
   pub fn is_valid(&self) -> bool {
     self.get().is_some()
   }
+
+  /// The edict this handle was made for, even if it has been freed since.
+  pub fn edict_ptr(&self) -> *mut Edict {
+    self.edict
+  }
+
+  pub fn serial_number(&self) -> c_int {
+    self.serial_number
+  }
+
+  /// Identifies the entity in Lua tables. Once the engine reuses the edict
+  /// the key changes with the serial number. `None` for null handles.
+  pub fn key(&self) -> Option<i64> {
+    let edict = unsafe { self.edict.as_ref()? };
+    Some((i64::from(index_of_edict(edict)) << 32) | i64::from(self.serial_number as u32))
+  }
+
+  fn get_valid<'a>(&self) -> rlua::Result<&'a Edict> {
+    self.get().ok_or_else(|| rlua::Error::RuntimeError("Invalid entity".into()))
+  }
+}
+
+impl PartialEq for EntityHandle {
+  fn eq(&self, other: &Self) -> bool {
+    self.edict == other.edict && self.serial_number == other.serial_number
+  }
 }
 
 impl Default for EntityHandle {
@@ -52,18 +96,138 @@ impl Default for EntityHandle {
   }
 }
 
-impl rlua::UserData for EntityHandle {
+// Weak table in the registry that holds the userdata of every entity handle
+// that's alive in Lua
+const ENTITY_HANDLES_KEY: &str = "luna_entity_handles";
+
+/// Lua userdata for `EntityHandle`. `EntityHandle`s are interned when they
+/// are passed to Lua, the same entity is always the same Lua value, so it
+/// can be compared with `==` and used as a table key.
+#[derive(Clone)]
+pub struct EntityUserData(pub EntityHandle);
+
+/// Looks `key` up in the weak registry table `table_name`, so that the same
+/// thing is always the same Lua value. `create` makes the value if it isn't
+/// alive in Lua anymore.
+pub(super) fn intern<'lua, K: rlua::ToLua<'lua>>(
+  ctx: rlua::Context<'lua>,
+  table_name: &str,
+  key: K,
+  create: impl FnOnce() -> rlua::Result<rlua::Value<'lua>>,
+) -> rlua::Result<rlua::Value<'lua>> {
+  let handles = match ctx.named_registry_value::<_, Option<rlua::Table>>(table_name)? {
+    Some(handles) => handles,
+    None => {
+      let handles = ctx.create_table()?;
+      let mt = ctx.create_table()?;
+      mt.raw_set("__mode", "v")?;
+      handles.set_metatable(Some(mt));
+      ctx.set_named_registry_value(table_name, handles.clone())?;
+      handles
+    }
+  };
+
+  let key = key.to_lua(ctx)?;
+  match handles.raw_get::<_, rlua::Value>(key.clone())? {
+    rlua::Nil => {
+      let handle = create()?;
+      handles.raw_set(key, handle.clone())?;
+      Ok(handle)
+    }
+    handle => Ok(handle),
+  }
+}
+
+impl<'lua> rlua::ToLua<'lua> for EntityHandle {
+  fn to_lua(self, ctx: rlua::Context<'lua>) -> rlua::Result<rlua::Value<'lua>> {
+    // Only entities that exist have an identity
+    match self.key() {
+      Some(key) => intern(ctx, ENTITY_HANDLES_KEY, key, || EntityUserData(self).to_lua(ctx)),
+      None => EntityUserData(self).to_lua(ctx),
+    }
+  }
+}
+
+impl<'lua> rlua::FromLua<'lua> for EntityHandle {
+  fn from_lua(value: rlua::Value<'lua>, _: rlua::Context<'lua>) -> rlua::Result<Self> {
+    match value {
+      rlua::Value::UserData(ud) => {
+        if let Ok(entity) = ud.borrow::<EntityUserData>() {
+          return Ok(entity.0.clone());
+        }
+        // Players can be used wherever entities can
+        Ok(ud.borrow::<PlayerUserData>()?.0.entity_handle())
+      }
+      _ => Err(rlua::Error::FromLuaConversionError {
+        from: "value",
+        to: "Entity",
+        message: Some("expected an entity".to_string()),
+      }),
+    }
+  }
+}
+
+impl rlua::UserData for EntityUserData {
   fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(m: &mut M) {
-    m.add_method("EntVars", |_, this: &Self, ()| {
+    m.add_method("EntVars", |_, EntityUserData(this), ()| {
       match this.is_valid() {
         true => Ok(EntVarsHandle::new(this.clone())),
         false => Err(rlua::Error::RuntimeError("Invalid entity".into())),
       }
     });
+
+    m.add_method("IsValid", |_, EntityUserData(this), ()| Ok(this.is_valid()));
+    m.add_method("GetIndex", |_, EntityUserData(this), ()| {
+      this.get_valid().map(index_of_edict)
+    });
+    m.add_method("GetSerial", |_, EntityUserData(this), ()| {
+      Ok(this.serial_number())
+    });
+    m.add_method("Spawn", |_, EntityUserData(this), ()| {
+      this.get_valid().map(spawn_entity)
+    });
+    m.add_method("Remove", |_, EntityUserData(this), ()| {
+      let edict = this.get_valid()?;
+      // The engine owns the world and the player slots
+      if index_of_edict(edict) <= max_clients() {
+        return Err(rlua::Error::RuntimeError(
+          "The world and players can't be removed".into(),
+        ));
+      }
+      remove_entity(edict);
+      Ok(())
+    });
+    m.add_method("SetOrigin", |_, EntityUserData(this), origin: EngineVector3| {
+      this.get_valid().map(|edict| set_origin(edict, origin))
+    });
+    m.add_method("SetSize", |_, EntityUserData(this), (mins, maxs): (EngineVector3, EngineVector3)| {
+      this.get_valid().map(|edict| set_size(edict, mins, maxs))
+    });
+    m.add_method("SetModel", |_, EntityUserData(this), model: String| {
+      this.get_valid().map(|edict| set_model(edict, &model))
+    });
+    m.add_method("DropToFloor", |_, EntityUserData(this), ()| {
+      this.get_valid().map(drop_to_floor)
+    });
+
+    m.add_meta_method(rlua::MetaMethod::Eq, |_, EntityUserData(this), other: rlua::AnyUserData| {
+      Ok(other.borrow::<EntityUserData>().is_ok_and(|other| *this == other.0))
+    });
+    m.add_meta_method(rlua::MetaMethod::ToString, |_, EntityUserData(this), ()| {
+      Ok(match this.get() {
+        Some(edict) => format!(
+          "Entity({}, \"{}\")",
+          index_of_edict(edict),
+          string_from_handle(entvars_of_edict(edict).classname),
+        ),
+        None => "Entity(invalid)".to_string(),
+      })
+    });
   }
 }
 
 unsafe impl Send for EntityHandle { }
+unsafe impl Send for EntityUserData { }
 
 #[derive(Clone)]
 pub struct EntVarsHandle {
@@ -78,184 +242,207 @@ impl EntVarsHandle {
   }
 }
 
-enum EntVarType {
-  Int,
-  Float,
-  Bool,
-  Byte,
-  String,
-  Vector3,
-  EdictPtr,
-}
-
 lazy_static! {
-  static ref ENTVARS: HashMap<&'static str, (usize, EntVarType)> = {
+  static ref ENTVARS: HashMap<&'static str, (usize, FieldType)> = {
     let mut map = HashMap::new();
-    map.insert("classname", (offset_of!(EntVars, classname), EntVarType::String));
-    map.insert("globalname", (offset_of!(EntVars, globalname), EntVarType::String));
-    map.insert("origin", (offset_of!(EntVars, origin), EntVarType::Vector3));
-    map.insert("oldorigin", (offset_of!(EntVars, oldorigin), EntVarType::Vector3));
-    map.insert("velocity", (offset_of!(EntVars, velocity), EntVarType::Vector3));
-    map.insert("basevelocity", (offset_of!(EntVars, basevelocity), EntVarType::Vector3));
-    map.insert("clbasevelocity", (offset_of!(EntVars, clbasevelocity), EntVarType::Vector3));
-    map.insert("movedir", (offset_of!(EntVars, movedir), EntVarType::Vector3));
-    map.insert("angles", (offset_of!(EntVars, angles), EntVarType::Vector3));
-    map.insert("avelocity", (offset_of!(EntVars, avelocity), EntVarType::Vector3));
-    map.insert("punchangle", (offset_of!(EntVars, punchangle), EntVarType::Vector3));
-    map.insert("v_angle", (offset_of!(EntVars, v_angle), EntVarType::Vector3));
-    map.insert("endpos", (offset_of!(EntVars, endpos), EntVarType::Vector3));
-    map.insert("startpos", (offset_of!(EntVars, startpos), EntVarType::Vector3));
-    map.insert("impacttime", (offset_of!(EntVars, impacttime), EntVarType::Float));
-    map.insert("starttime", (offset_of!(EntVars, starttime), EntVarType::Float));
-    map.insert("fixangle", (offset_of!(EntVars, fixangle), EntVarType::Int));
-    map.insert("idealpitch", (offset_of!(EntVars, idealpitch), EntVarType::Float));
-    map.insert("pitch_speed", (offset_of!(EntVars, pitch_speed), EntVarType::Float));
-    map.insert("ideal_yaw", (offset_of!(EntVars, ideal_yaw), EntVarType::Float));
-    map.insert("yaw_speed", (offset_of!(EntVars, yaw_speed), EntVarType::Float));
-    map.insert("modelindex", (offset_of!(EntVars, modelindex), EntVarType::Int));
-    map.insert("model", (offset_of!(EntVars, model), EntVarType::String));
-    map.insert("viewmodel", (offset_of!(EntVars, viewmodel), EntVarType::Int));
-    map.insert("weaponmodel", (offset_of!(EntVars, weaponmodel), EntVarType::Int));
-    map.insert("absmin", (offset_of!(EntVars, absmin), EntVarType::Vector3));
-    map.insert("absmax", (offset_of!(EntVars, absmax), EntVarType::Vector3));
-    map.insert("mins", (offset_of!(EntVars, mins), EntVarType::Vector3));
-    map.insert("maxs", (offset_of!(EntVars, maxs), EntVarType::Vector3));
-    map.insert("size", (offset_of!(EntVars, size), EntVarType::Vector3));
-    map.insert("ltime", (offset_of!(EntVars, ltime), EntVarType::Float));
-    map.insert("nextthink", (offset_of!(EntVars, nextthink), EntVarType::Float));
-    map.insert("movetype", (offset_of!(EntVars, movetype), EntVarType::Int));
-    map.insert("solid", (offset_of!(EntVars, solid), EntVarType::Int));
-    map.insert("skin", (offset_of!(EntVars, skin), EntVarType::Int));
-    map.insert("body", (offset_of!(EntVars, body), EntVarType::Int));
-    map.insert("effects", (offset_of!(EntVars, effects), EntVarType::Int));
-    map.insert("gravity", (offset_of!(EntVars, gravity), EntVarType::Float));
-    map.insert("friction", (offset_of!(EntVars, friction), EntVarType::Float));
-    map.insert("light_level", (offset_of!(EntVars, light_level), EntVarType::Int));
-    map.insert("sequence", (offset_of!(EntVars, sequence), EntVarType::Int));
-    map.insert("gaitsequence", (offset_of!(EntVars, gaitsequence), EntVarType::Int));
-    map.insert("frame", (offset_of!(EntVars, frame), EntVarType::Float));
-    map.insert("animtime", (offset_of!(EntVars, animtime), EntVarType::Float));
-    map.insert("framerate", (offset_of!(EntVars, framerate), EntVarType::Float));
-    map.insert("controller0", (offset_of!(EntVars, controller[0]), EntVarType::Byte));
-    map.insert("controller1", (offset_of!(EntVars, controller[1]), EntVarType::Byte));
-    map.insert("controller2", (offset_of!(EntVars, controller[2]), EntVarType::Byte));
-    map.insert("controller3", (offset_of!(EntVars, controller[3]), EntVarType::Byte));
-    map.insert("blending0", (offset_of!(EntVars, blending[0]), EntVarType::Byte));
-    map.insert("blending1", (offset_of!(EntVars, blending[1]), EntVarType::Byte));
-    map.insert("scale", (offset_of!(EntVars, scale), EntVarType::Float));
-    map.insert("rendermode", (offset_of!(EntVars, rendermode), EntVarType::Int));
-    map.insert("renderamount", (offset_of!(EntVars, renderamount), EntVarType::Float));
-    map.insert("rendercolor", (offset_of!(EntVars, rendercolor), EntVarType::Vector3));
-    map.insert("renderfx", (offset_of!(EntVars, renderfx), EntVarType::Int));
-    map.insert("health", (offset_of!(EntVars, health), EntVarType::Float));
-    map.insert("frags", (offset_of!(EntVars, frags), EntVarType::Float));
-    map.insert("weapons", (offset_of!(EntVars, weapons), EntVarType::Int));
-    map.insert("takedamage", (offset_of!(EntVars, takedamage), EntVarType::Float));
-    map.insert("deadflag", (offset_of!(EntVars, deadflag), EntVarType::Int));
-    map.insert("view_ofs", (offset_of!(EntVars, view_ofs), EntVarType::Vector3));
-    map.insert("button", (offset_of!(EntVars, button), EntVarType::Int));
-    map.insert("impulse", (offset_of!(EntVars, impulse), EntVarType::Int));
-    map.insert("chain", (offset_of!(EntVars, chain), EntVarType::EdictPtr));
-    map.insert("dmg_inflictor", (offset_of!(EntVars, dmg_inflictor), EntVarType::EdictPtr));
-    map.insert("enemy", (offset_of!(EntVars, enemy), EntVarType::EdictPtr));
-    map.insert("aiment", (offset_of!(EntVars, aiment), EntVarType::EdictPtr));
-    map.insert("owner", (offset_of!(EntVars, owner), EntVarType::EdictPtr));
-    map.insert("groundentity", (offset_of!(EntVars, groundentity), EntVarType::EdictPtr));
-    map.insert("spawnflags", (offset_of!(EntVars, spawnflags), EntVarType::Int));
-    map.insert("flags", (offset_of!(EntVars, flags), EntVarType::Int));
-    map.insert("colormap", (offset_of!(EntVars, colormap), EntVarType::Int));
-    map.insert("team", (offset_of!(EntVars, team), EntVarType::Int));
-    map.insert("max_health", (offset_of!(EntVars, max_health), EntVarType::Float));
-    map.insert("teleport_time", (offset_of!(EntVars, teleport_time), EntVarType::Float));
-    map.insert("armortype", (offset_of!(EntVars, armortype), EntVarType::Float));
-    map.insert("armorvalue", (offset_of!(EntVars, armorvalue), EntVarType::Float));
-    map.insert("waterlevel", (offset_of!(EntVars, waterlevel), EntVarType::Int));
-    map.insert("watertype", (offset_of!(EntVars, watertype), EntVarType::Int));
-    map.insert("target", (offset_of!(EntVars, target), EntVarType::String));
-    map.insert("targetname", (offset_of!(EntVars, targetname), EntVarType::String));
-    map.insert("netname", (offset_of!(EntVars, netname), EntVarType::String));
-    map.insert("message", (offset_of!(EntVars, message), EntVarType::String));
-    map.insert("dmg_take", (offset_of!(EntVars, dmg_take), EntVarType::Float));
-    map.insert("dmg_save", (offset_of!(EntVars, dmg_save), EntVarType::Float));
-    map.insert("dmg", (offset_of!(EntVars, dmg), EntVarType::Float));
-    map.insert("dmgtime", (offset_of!(EntVars, dmgtime), EntVarType::Float));
-    map.insert("noise", (offset_of!(EntVars, noise), EntVarType::String));
-    map.insert("noise1", (offset_of!(EntVars, noise1), EntVarType::String));
-    map.insert("noise2", (offset_of!(EntVars, noise2), EntVarType::String));
-    map.insert("noise3", (offset_of!(EntVars, noise3), EntVarType::String));
-    map.insert("speed", (offset_of!(EntVars, speed), EntVarType::Float));
-    map.insert("air_finished", (offset_of!(EntVars, air_finished), EntVarType::Float));
-    map.insert("pain_finished", (offset_of!(EntVars, pain_finished), EntVarType::Float));
-    map.insert("radsuit_finished", (offset_of!(EntVars, radsuit_finished), EntVarType::Float));
-    map.insert("containing_entity", (offset_of!(EntVars, containing_entity), EntVarType::EdictPtr));
-    map.insert("playerclass", (offset_of!(EntVars, playerclass), EntVarType::Int));
-    map.insert("maxspeed", (offset_of!(EntVars, maxspeed), EntVarType::Float));
-    map.insert("fov", (offset_of!(EntVars, fov), EntVarType::Float));
-    map.insert("weaponanim", (offset_of!(EntVars, weaponanim), EntVarType::Int));
-    map.insert("pushmsec", (offset_of!(EntVars, pushmsec), EntVarType::Int));
-    map.insert("bInDuck", (offset_of!(EntVars, in_duck), EntVarType::Bool));
-    map.insert("flTimeStepSound", (offset_of!(EntVars, time_step_sound), EntVarType::Int));
-    map.insert("flSwimTime", (offset_of!(EntVars, swim_time), EntVarType::Int));
-    map.insert("flDuckTime", (offset_of!(EntVars, duck_time), EntVarType::Int));
-    map.insert("iStepLeft", (offset_of!(EntVars, step_left), EntVarType::Int));
-    map.insert("flFallVelocity", (offset_of!(EntVars, fall_velocity), EntVarType::Float));
-    map.insert("gamestate", (offset_of!(EntVars, gamestate), EntVarType::Int));
-    map.insert("oldbuttons", (offset_of!(EntVars, oldbuttons), EntVarType::Int));
-    map.insert("groupinfo", (offset_of!(EntVars, groupinfo), EntVarType::Int));
-    map.insert("iuser1", (offset_of!(EntVars, iuser1), EntVarType::Int));
-    map.insert("iuser2", (offset_of!(EntVars, iuser2), EntVarType::Int));
-    map.insert("iuser3", (offset_of!(EntVars, iuser3), EntVarType::Int));
-    map.insert("iuser4", (offset_of!(EntVars, iuser4), EntVarType::Int));
-    map.insert("fuser1", (offset_of!(EntVars, fuser1), EntVarType::Float));
-    map.insert("fuser2", (offset_of!(EntVars, fuser2), EntVarType::Float));
-    map.insert("fuser3", (offset_of!(EntVars, fuser3), EntVarType::Float));
-    map.insert("fuser4", (offset_of!(EntVars, fuser4), EntVarType::Float));
-    map.insert("vuser1", (offset_of!(EntVars, vuser1), EntVarType::Vector3));
-    map.insert("vuser2", (offset_of!(EntVars, vuser2), EntVarType::Vector3));
-    map.insert("vuser3", (offset_of!(EntVars, vuser3), EntVarType::Vector3));
-    map.insert("vuser4", (offset_of!(EntVars, vuser4), EntVarType::Vector3));
-    map.insert("euser1", (offset_of!(EntVars, euser1), EntVarType::EdictPtr));
-    map.insert("euser2", (offset_of!(EntVars, euser2), EntVarType::EdictPtr));
-    map.insert("euser3", (offset_of!(EntVars, euser3), EntVarType::EdictPtr));
-    map.insert("euser4", (offset_of!(EntVars, euser4), EntVarType::EdictPtr));
+    map.insert("classname", (offset_of!(EntVars, classname), FieldType::String));
+    map.insert("globalname", (offset_of!(EntVars, globalname), FieldType::String));
+    map.insert("origin", (offset_of!(EntVars, origin), FieldType::Vector));
+    map.insert("oldorigin", (offset_of!(EntVars, oldorigin), FieldType::Vector));
+    map.insert("velocity", (offset_of!(EntVars, velocity), FieldType::Vector));
+    map.insert("basevelocity", (offset_of!(EntVars, basevelocity), FieldType::Vector));
+    map.insert("clbasevelocity", (offset_of!(EntVars, clbasevelocity), FieldType::Vector));
+    map.insert("movedir", (offset_of!(EntVars, movedir), FieldType::Vector));
+    map.insert("angles", (offset_of!(EntVars, angles), FieldType::Vector));
+    map.insert("avelocity", (offset_of!(EntVars, avelocity), FieldType::Vector));
+    map.insert("punchangle", (offset_of!(EntVars, punchangle), FieldType::Vector));
+    map.insert("v_angle", (offset_of!(EntVars, v_angle), FieldType::Vector));
+    map.insert("endpos", (offset_of!(EntVars, endpos), FieldType::Vector));
+    map.insert("startpos", (offset_of!(EntVars, startpos), FieldType::Vector));
+    map.insert("impacttime", (offset_of!(EntVars, impacttime), FieldType::Float));
+    map.insert("starttime", (offset_of!(EntVars, starttime), FieldType::Float));
+    map.insert("fixangle", (offset_of!(EntVars, fixangle), FieldType::Int));
+    map.insert("idealpitch", (offset_of!(EntVars, idealpitch), FieldType::Float));
+    map.insert("pitch_speed", (offset_of!(EntVars, pitch_speed), FieldType::Float));
+    map.insert("ideal_yaw", (offset_of!(EntVars, ideal_yaw), FieldType::Float));
+    map.insert("yaw_speed", (offset_of!(EntVars, yaw_speed), FieldType::Float));
+    map.insert("modelindex", (offset_of!(EntVars, modelindex), FieldType::Int));
+    map.insert("model", (offset_of!(EntVars, model), FieldType::String));
+    map.insert("viewmodel", (offset_of!(EntVars, viewmodel), FieldType::Int));
+    map.insert("weaponmodel", (offset_of!(EntVars, weaponmodel), FieldType::Int));
+    map.insert("absmin", (offset_of!(EntVars, absmin), FieldType::Vector));
+    map.insert("absmax", (offset_of!(EntVars, absmax), FieldType::Vector));
+    map.insert("mins", (offset_of!(EntVars, mins), FieldType::Vector));
+    map.insert("maxs", (offset_of!(EntVars, maxs), FieldType::Vector));
+    map.insert("size", (offset_of!(EntVars, size), FieldType::Vector));
+    map.insert("ltime", (offset_of!(EntVars, ltime), FieldType::Float));
+    map.insert("nextthink", (offset_of!(EntVars, nextthink), FieldType::Float));
+    map.insert("movetype", (offset_of!(EntVars, movetype), FieldType::Int));
+    map.insert("solid", (offset_of!(EntVars, solid), FieldType::Int));
+    map.insert("skin", (offset_of!(EntVars, skin), FieldType::Int));
+    map.insert("body", (offset_of!(EntVars, body), FieldType::Int));
+    map.insert("effects", (offset_of!(EntVars, effects), FieldType::Int));
+    map.insert("gravity", (offset_of!(EntVars, gravity), FieldType::Float));
+    map.insert("friction", (offset_of!(EntVars, friction), FieldType::Float));
+    map.insert("light_level", (offset_of!(EntVars, light_level), FieldType::Int));
+    map.insert("sequence", (offset_of!(EntVars, sequence), FieldType::Int));
+    map.insert("gaitsequence", (offset_of!(EntVars, gaitsequence), FieldType::Int));
+    map.insert("frame", (offset_of!(EntVars, frame), FieldType::Float));
+    map.insert("animtime", (offset_of!(EntVars, animtime), FieldType::Float));
+    map.insert("framerate", (offset_of!(EntVars, framerate), FieldType::Float));
+    map.insert("controller0", (offset_of!(EntVars, controller), FieldType::Byte));
+    map.insert("controller1", (offset_of!(EntVars, controller) + 1, FieldType::Byte));
+    map.insert("controller2", (offset_of!(EntVars, controller) + 2, FieldType::Byte));
+    map.insert("controller3", (offset_of!(EntVars, controller) + 3, FieldType::Byte));
+    map.insert("blending0", (offset_of!(EntVars, blending), FieldType::Byte));
+    map.insert("blending1", (offset_of!(EntVars, blending) + 1, FieldType::Byte));
+    map.insert("scale", (offset_of!(EntVars, scale), FieldType::Float));
+    map.insert("rendermode", (offset_of!(EntVars, rendermode), FieldType::Int));
+    map.insert("renderamount", (offset_of!(EntVars, renderamount), FieldType::Float));
+    map.insert("rendercolor", (offset_of!(EntVars, rendercolor), FieldType::Vector));
+    map.insert("renderfx", (offset_of!(EntVars, renderfx), FieldType::Int));
+    map.insert("health", (offset_of!(EntVars, health), FieldType::Float));
+    map.insert("frags", (offset_of!(EntVars, frags), FieldType::Float));
+    map.insert("weapons", (offset_of!(EntVars, weapons), FieldType::Int));
+    map.insert("takedamage", (offset_of!(EntVars, takedamage), FieldType::Float));
+    map.insert("deadflag", (offset_of!(EntVars, deadflag), FieldType::Int));
+    map.insert("view_ofs", (offset_of!(EntVars, view_ofs), FieldType::Vector));
+    map.insert("button", (offset_of!(EntVars, button), FieldType::Int));
+    map.insert("impulse", (offset_of!(EntVars, impulse), FieldType::Int));
+    map.insert("chain", (offset_of!(EntVars, chain), FieldType::Edict));
+    map.insert("dmg_inflictor", (offset_of!(EntVars, dmg_inflictor), FieldType::Edict));
+    map.insert("enemy", (offset_of!(EntVars, enemy), FieldType::Edict));
+    map.insert("aiment", (offset_of!(EntVars, aiment), FieldType::Edict));
+    map.insert("owner", (offset_of!(EntVars, owner), FieldType::Edict));
+    map.insert("groundentity", (offset_of!(EntVars, groundentity), FieldType::Edict));
+    map.insert("spawnflags", (offset_of!(EntVars, spawnflags), FieldType::Int));
+    map.insert("flags", (offset_of!(EntVars, flags), FieldType::Int));
+    map.insert("colormap", (offset_of!(EntVars, colormap), FieldType::Int));
+    map.insert("team", (offset_of!(EntVars, team), FieldType::Int));
+    map.insert("max_health", (offset_of!(EntVars, max_health), FieldType::Float));
+    map.insert("teleport_time", (offset_of!(EntVars, teleport_time), FieldType::Float));
+    map.insert("armortype", (offset_of!(EntVars, armortype), FieldType::Float));
+    map.insert("armorvalue", (offset_of!(EntVars, armorvalue), FieldType::Float));
+    map.insert("waterlevel", (offset_of!(EntVars, waterlevel), FieldType::Int));
+    map.insert("watertype", (offset_of!(EntVars, watertype), FieldType::Int));
+    map.insert("target", (offset_of!(EntVars, target), FieldType::String));
+    map.insert("targetname", (offset_of!(EntVars, targetname), FieldType::String));
+    map.insert("netname", (offset_of!(EntVars, netname), FieldType::String));
+    map.insert("message", (offset_of!(EntVars, message), FieldType::String));
+    map.insert("dmg_take", (offset_of!(EntVars, dmg_take), FieldType::Float));
+    map.insert("dmg_save", (offset_of!(EntVars, dmg_save), FieldType::Float));
+    map.insert("dmg", (offset_of!(EntVars, dmg), FieldType::Float));
+    map.insert("dmgtime", (offset_of!(EntVars, dmgtime), FieldType::Float));
+    map.insert("noise1", (offset_of!(EntVars, noise1), FieldType::String));
+    map.insert("noise2", (offset_of!(EntVars, noise2), FieldType::String));
+    map.insert("noise3", (offset_of!(EntVars, noise3), FieldType::String));
+    map.insert("speed", (offset_of!(EntVars, speed), FieldType::Float));
+    map.insert("air_finished", (offset_of!(EntVars, air_finished), FieldType::Float));
+    map.insert("pain_finished", (offset_of!(EntVars, pain_finished), FieldType::Float));
+    map.insert("radsuit_finished", (offset_of!(EntVars, radsuit_finished), FieldType::Float));
+    map.insert("containing_entity", (offset_of!(EntVars, containing_entity), FieldType::Edict));
+    map.insert("playerclass", (offset_of!(EntVars, playerclass), FieldType::Int));
+    map.insert("maxspeed", (offset_of!(EntVars, maxspeed), FieldType::Float));
+    map.insert("fov", (offset_of!(EntVars, fov), FieldType::Float));
+    map.insert("weaponanim", (offset_of!(EntVars, weaponanim), FieldType::Int));
+    map.insert("pushmsec", (offset_of!(EntVars, pushmsec), FieldType::Int));
+    map.insert("bInDuck", (offset_of!(EntVars, in_duck), FieldType::Qboolean));
+    map.insert("flTimeStepSound", (offset_of!(EntVars, time_step_sound), FieldType::Int));
+    map.insert("flSwimTime", (offset_of!(EntVars, swim_time), FieldType::Int));
+    map.insert("flDuckTime", (offset_of!(EntVars, duck_time), FieldType::Int));
+    map.insert("iStepLeft", (offset_of!(EntVars, step_left), FieldType::Int));
+    map.insert("flFallVelocity", (offset_of!(EntVars, fall_velocity), FieldType::Float));
+    map.insert("gamestate", (offset_of!(EntVars, gamestate), FieldType::Int));
+    map.insert("oldbuttons", (offset_of!(EntVars, oldbuttons), FieldType::Int));
+    map.insert("groupinfo", (offset_of!(EntVars, groupinfo), FieldType::Int));
+    map.insert("iuser1", (offset_of!(EntVars, iuser1), FieldType::Int));
+    map.insert("iuser2", (offset_of!(EntVars, iuser2), FieldType::Int));
+    map.insert("iuser3", (offset_of!(EntVars, iuser3), FieldType::Int));
+    map.insert("iuser4", (offset_of!(EntVars, iuser4), FieldType::Int));
+    map.insert("fuser1", (offset_of!(EntVars, fuser1), FieldType::Float));
+    map.insert("fuser2", (offset_of!(EntVars, fuser2), FieldType::Float));
+    map.insert("fuser3", (offset_of!(EntVars, fuser3), FieldType::Float));
+    map.insert("fuser4", (offset_of!(EntVars, fuser4), FieldType::Float));
+    map.insert("vuser1", (offset_of!(EntVars, vuser1), FieldType::Vector));
+    map.insert("vuser2", (offset_of!(EntVars, vuser2), FieldType::Vector));
+    map.insert("vuser3", (offset_of!(EntVars, vuser3), FieldType::Vector));
+    map.insert("vuser4", (offset_of!(EntVars, vuser4), FieldType::Vector));
+    map.insert("euser1", (offset_of!(EntVars, euser1), FieldType::Edict));
+    map.insert("euser2", (offset_of!(EntVars, euser2), FieldType::Edict));
+    map.insert("euser3", (offset_of!(EntVars, euser3), FieldType::Edict));
+    map.insert("euser4", (offset_of!(EntVars, euser4), FieldType::Edict));
+    map
+  };
+
+  // Fields that belong together, indexed from 1 like any Lua array
+  static ref ENTVAR_ARRAYS: HashMap<&'static str, (Vec<usize>, FieldType)> = {
+    let mut map = HashMap::new();
+    map.insert("controller", (vec![
+      offset_of!(EntVars, controller),
+      offset_of!(EntVars, controller) + 1,
+      offset_of!(EntVars, controller) + 2,
+      offset_of!(EntVars, controller) + 3,
+    ], FieldType::Byte));
+    map.insert("blending", (vec![
+      offset_of!(EntVars, blending),
+      offset_of!(EntVars, blending) + 1,
+    ], FieldType::Byte));
+    // The first one is `noise` itself, it's only reachable as `noise[1]`
+    map.insert("noise", (vec![
+      offset_of!(EntVars, noise),
+      offset_of!(EntVars, noise1),
+      offset_of!(EntVars, noise2),
+      offset_of!(EntVars, noise3),
+    ], FieldType::String));
+    map.insert("iuser", (vec![
+      offset_of!(EntVars, iuser1),
+      offset_of!(EntVars, iuser2),
+      offset_of!(EntVars, iuser3),
+      offset_of!(EntVars, iuser4),
+    ], FieldType::Int));
+    map.insert("fuser", (vec![
+      offset_of!(EntVars, fuser1),
+      offset_of!(EntVars, fuser2),
+      offset_of!(EntVars, fuser3),
+      offset_of!(EntVars, fuser4),
+    ], FieldType::Float));
+    map.insert("vuser", (vec![
+      offset_of!(EntVars, vuser1),
+      offset_of!(EntVars, vuser2),
+      offset_of!(EntVars, vuser3),
+      offset_of!(EntVars, vuser4),
+    ], FieldType::Vector));
+    map.insert("euser", (vec![
+      offset_of!(EntVars, euser1),
+      offset_of!(EntVars, euser2),
+      offset_of!(EntVars, euser3),
+      offset_of!(EntVars, euser4),
+    ], FieldType::Edict));
     map
   };
 }
 
+// Address of the entvar at `offset` in the entvars of `edict`.
+fn entvar_address(edict: &Edict, offset: usize) -> usize {
+  entvars_of_edict(edict) as *const EntVars as usize + offset
+}
+
 impl rlua::UserData for EntVarsHandle {
   fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(m: &mut M) {
     m.add_meta_method(rlua::MetaMethod::Index, |ctx, this: &Self, key: String| {
-      use rlua::ToLua;
-
       match this.entity_handle.get() {
         None => Err(rlua::Error::RuntimeError("Invalid entity".into())),
         Some(edict) => {
-          match ENTVARS.get::<&str>(&key.as_str()) {
-            Some((offset, ev_type)) => {
-              let entvars = entvars_of_edict(edict);
-              let ev_offset = entvars as *const EntVars as usize + offset;
-
-              match ev_type {
-                EntVarType::Int => unsafe {
-                  (*(ev_offset as *const c_int)).to_lua(ctx)
-                }
-                EntVarType::Float => unsafe {
-                  (*(ev_offset as *const c_float)).to_lua(ctx)
-                }
-                EntVarType::Bool => unsafe {
-                  (*(ev_offset as *const c_int) != 0).to_lua(ctx)
-                }
-                EntVarType::String => unsafe {
-                  (*(ev_offset as *const EngineStringHandle)).to_lua(ctx)
-                }
-                EntVarType::EdictPtr => unsafe {
-                  (ev_offset as *const Edict)
-                    .as_ref()
-                    .map_or_else(EntityHandle::default, EntityHandle::new)
-                    .to_lua(ctx)
-                }
-                _ => unimplemented!("Unsupported type"),
-              }
+          if let Some((offset, field_type)) = ENTVARS.get(key.as_str()) {
+            return unsafe { read_field(ctx, entvar_address(edict, *offset), *field_type) };
+          }
+
+          match ENTVAR_ARRAYS.get_key_value(key.as_str()) {
+            Some((name, _)) => {
+              Ok(rlua::Value::UserData(ctx.create_userdata(FieldArrayHandle(EntVarArray {
+                entity_handle: this.entity_handle.clone(),
+                name,
+              }))?))
             }
             None => {
               Err(rlua::Error::RuntimeError(
@@ -268,43 +455,27 @@ impl rlua::UserData for EntVarsHandle {
     });
 
     m.add_meta_method(rlua::MetaMethod::NewIndex, |ctx, this: &Self, (key, value): (String, rlua::Value)| {
-      use rlua::FromLua;
-
       match this.entity_handle.get() {
         None => Err(rlua::Error::RuntimeError("Invalid entity".into())),
         Some(edict) => {
-          match ENTVARS.get::<&str>(&key.as_str()) {
-            Some((offset, ev_type)) => {
-              let entvars = entvars_of_edict(edict);
-              let ev_offset = entvars as *const EntVars as usize + offset;
-
-              match ev_type {
-                EntVarType::Int => unsafe {
-                  (*(ev_offset as *mut c_int)) = c_int::from_lua(value, ctx)?;
-                }
-                EntVarType::Float => unsafe {
-                  (*(ev_offset as *mut c_float)) = c_float::from_lua(value, ctx)?;
-                }
-                EntVarType::Bool => unsafe {
-                  (*(ev_offset as *mut c_int)) = bool::from_lua(value, ctx)? as c_int;
-                }
-                EntVarType::String => unsafe {
-                  (*(ev_offset as *mut EngineStringHandle)) = EngineStringHandle::from_lua(value, ctx)?;
-                }
-                EntVarType::EdictPtr => unsafe {
-                  *(ev_offset as *mut *mut Edict) = EntityHandle::from_lua(value, ctx)?
-                    .get()
-                    .map(|r| r as *const Edict as *mut Edict)
-                    .ok_or_else(
-                      || rlua::Error::RuntimeError("Invalid entity".into())
-                    )?;
-                }
-                _ => unimplemented!("Unsupported type"),
-              };
-
-              Ok(())
+          if let Some((offset, field_type)) = ENTVARS.get(key.as_str()) {
+            return unsafe { write_field(ctx, entvar_address(edict, *offset), *field_type, value) };
+          }
+
+          // Arrays can be assigned as a whole from a table
+          match (ENTVAR_ARRAYS.get(key.as_str()), value) {
+            (Some((offsets, field_type)), rlua::Value::Table(values)) => {
+              let addresses: Vec<usize> = offsets.iter()
+                .map(|offset| entvar_address(edict, *offset))
+                .collect();
+              unsafe { write_array(ctx, &addresses, *field_type, values) }
             }
-            None => {
+            (Some(_), _) => {
+              Err(rlua::Error::RuntimeError(
+                format!("Entvar \"{}\" can only be assigned a table", key),
+              ))
+            }
+            (None, _) => {
               Err(rlua::Error::RuntimeError(
                 format!("Invalid entvar \"{}\"", key),
               ))
@@ -316,8 +487,102 @@ impl rlua::UserData for EntVarsHandle {
   }
 }
 
+/// One of the grouped entvars in `ENTVAR_ARRAYS`, like `iuser` or
+/// `controller`.
+#[derive(Clone)]
+pub struct EntVarArray {
+  entity_handle: EntityHandle,
+  name: &'static str,
+}
+
+impl FieldArray for EntVarArray {
+  fn elements(&self) -> rlua::Result<(Vec<usize>, FieldType)> {
+    let edict = self.entity_handle.get()
+      .ok_or_else(|| rlua::Error::RuntimeError("Invalid entity".into()))?;
+    let (offsets, field_type) = &ENTVAR_ARRAYS[self.name];
+    Ok((offsets.iter().map(|offset| entvar_address(edict, *offset)).collect(), *field_type))
+  }
+
+  fn describe(&self) -> String {
+    format!("entvar \"{}\"", self.name)
+  }
+}
+
+unsafe impl Send for EntVarArray { }
+
 unsafe impl Send for EntVarsHandle { }
 
+lazy_static! {
+  // The flag says whether plugins may write to the global
+  static ref GLOBALVARS: HashMap<&'static str, (usize, FieldType, bool)> = {
+    let mut map = HashMap::new();
+    map.insert("time", (offset_of!(GlobalVars, time), FieldType::Float, false));
+    map.insert("frametime", (offset_of!(GlobalVars, frametime), FieldType::Float, false));
+    map.insert("force_retouch", (offset_of!(GlobalVars, force_retouch), FieldType::Float, true));
+    map.insert("mapname", (offset_of!(GlobalVars, mapname), FieldType::String, false));
+    map.insert("startspot", (offset_of!(GlobalVars, startspot), FieldType::String, false));
+    map.insert("deathmatch", (offset_of!(GlobalVars, deathmatch), FieldType::Float, false));
+    map.insert("coop", (offset_of!(GlobalVars, coop), FieldType::Float, false));
+    map.insert("teamplay", (offset_of!(GlobalVars, teamplay), FieldType::Float, false));
+    map.insert("serverflags", (offset_of!(GlobalVars, serverflags), FieldType::Float, true));
+    map.insert("found_secrets", (offset_of!(GlobalVars, found_secrets), FieldType::Float, true));
+    map.insert("v_forward", (offset_of!(GlobalVars, v_forward), FieldType::Vector, false));
+    map.insert("v_up", (offset_of!(GlobalVars, v_up), FieldType::Vector, false));
+    map.insert("v_right", (offset_of!(GlobalVars, v_right), FieldType::Vector, false));
+    map.insert("trace_allsolid", (offset_of!(GlobalVars, trace_allsolid), FieldType::Float, false));
+    map.insert("trace_startsolid", (offset_of!(GlobalVars, trace_startsolid), FieldType::Float, false));
+    map.insert("trace_fraction", (offset_of!(GlobalVars, trace_fraction), FieldType::Float, false));
+    map.insert("trace_endpos", (offset_of!(GlobalVars, trace_endpos), FieldType::Vector, false));
+    map.insert("trace_plane_normal", (offset_of!(GlobalVars, trace_plane_normal), FieldType::Vector, false));
+    map.insert("trace_plane_dist", (offset_of!(GlobalVars, trace_plane_dist), FieldType::Float, false));
+    map.insert("trace_ent", (offset_of!(GlobalVars, trace_ent), FieldType::Edict, false));
+    map.insert("trace_inopen", (offset_of!(GlobalVars, trace_inopen), FieldType::Float, false));
+    map.insert("trace_inwater", (offset_of!(GlobalVars, trace_inwater), FieldType::Float, false));
+    map.insert("trace_hitgroup", (offset_of!(GlobalVars, trace_hitgroup), FieldType::Int, false));
+    map.insert("trace_flags", (offset_of!(GlobalVars, trace_flags), FieldType::Int, false));
+    map.insert("msg_entity", (offset_of!(GlobalVars, msg_entity), FieldType::Int, true));
+    map.insert("cdAudioTrack", (offset_of!(GlobalVars, cd_audio_track), FieldType::Int, false));
+    map.insert("maxClients", (offset_of!(GlobalVars, max_clients), FieldType::Int, false));
+    map.insert("maxEntities", (offset_of!(GlobalVars, max_entities), FieldType::Int, false));
+    map.insert("vecLandmarkOffset", (offset_of!(GlobalVars, landmark_offset), FieldType::Vector, false));
+    map
+  };
+}
+
+/// Reads the `globalvars_t` field `key`.
+pub fn get_global_var<'lua>(
+  ctx: rlua::Context<'lua>,
+  key: &str,
+) -> rlua::Result<rlua::Value<'lua>> {
+  match GLOBALVARS.get(key) {
+    Some((offset, gv_type, _)) => unsafe {
+      read_field(ctx, GLOBAL_VARS as usize + offset, *gv_type)
+    },
+    None => Err(rlua::Error::RuntimeError(
+      format!("Invalid global \"{}\"", key),
+    )),
+  }
+}
+
+/// Writes the `globalvars_t` field `key`, if plugins are allowed to.
+pub fn set_global_var<'lua>(
+  ctx: rlua::Context<'lua>,
+  key: &str,
+  value: rlua::Value<'lua>,
+) -> rlua::Result<()> {
+  match GLOBALVARS.get(key) {
+    Some((offset, gv_type, true)) => unsafe {
+      write_field(ctx, GLOBAL_VARS as usize + offset, *gv_type, value)
+    },
+    Some(_) => Err(rlua::Error::RuntimeError(
+      format!("Global \"{}\" is read-only", key),
+    )),
+    None => Err(rlua::Error::RuntimeError(
+      format!("Invalid global \"{}\"", key),
+    )),
+  }
+}
+
 impl<'lua> rlua::ToLua<'lua> for EngineStringHandle {
   fn to_lua(self, ctx: rlua::Context) -> rlua::Result<rlua::Value> {
     ctx
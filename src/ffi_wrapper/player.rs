@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::ffi::{CString, CStr};
+use std::os::raw::c_int;
+use std::sync::Mutex;
+use crate::meta_ffi::constant::{FL_CLIENT, FL_FAKECLIENT};
+use crate::meta_ffi::globals::ENGINE_FUNCTIONS;
+use crate::meta_ffi::types::Edict;
+use super::hl_lua_bridge::{intern, EntityHandle, EntVarsHandle};
+use super::{
+  edict_of_index,
+  entvars_of_edict,
+  index_of_edict,
+  max_clients,
+  player_auth_id,
+  player_stats,
+  player_user_id,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConnectionState {
+  Disconnected,
+  Connecting,
+  InGame,
+}
+
+impl ConnectionState {
+  fn name(self) -> &'static str {
+    match self {
+      ConnectionState::Disconnected => "Disconnected",
+      ConnectionState::Connecting => "Connecting",
+      ConnectionState::InGame => "InGame",
+    }
+  }
+}
+
+struct Slot {
+  state: ConnectionState,
+  // Tells apart the players that used the same slot one after another
+  connection: u64,
+  address: Option<String>,
+}
+
+struct Slots {
+  slots: HashMap<c_int, Slot>,
+  next_connection: u64,
+}
+
+lazy_static! {
+  static ref SLOTS: Mutex<Slots> = Mutex::new(Slots {
+    slots: HashMap::new(),
+    next_connection: 1,
+  });
+}
+
+/// A player that connected to the server. Unlike an `EntityHandle` it stops
+/// being valid once the player disconnects, even if someone else takes over
+/// the slot.
+#[derive(Clone)]
+pub struct PlayerHandle {
+  entity_handle: EntityHandle,
+  index: c_int,
+  connection: u64,
+}
+
+impl PlayerHandle {
+  /// The player currently in the slot of `edict`. Players that were on the
+  /// server before Luna was loaded are picked up here too.
+  pub fn from_edict(edict: &Edict) -> Option<Self> {
+    let index = index_of_edict(edict);
+    if index < 1 || index > max_clients() {
+      return None;
+    }
+
+    let mut slots = SLOTS.lock().unwrap();
+    let Slots { slots, next_connection } = &mut *slots;
+    let connection = match slots.get(&index) {
+      Some(slot) if slot.state == ConnectionState::Disconnected => return None,
+      Some(slot) => slot.connection,
+      // Nothing is known about the slot, Luna was loaded after they joined
+      None => {
+        if edict.is_free() || entvars_of_edict(edict).flags & (FL_CLIENT | FL_FAKECLIENT) == 0 {
+          return None;
+        }
+
+        let connection = *next_connection;
+        *next_connection += 1;
+        slots.insert(index, Slot {
+          state: ConnectionState::InGame,
+          connection,
+          address: None,
+        });
+        connection
+      }
+    };
+
+    Some(PlayerHandle {
+      entity_handle: EntityHandle::new(edict),
+      index,
+      connection,
+    })
+  }
+
+  /// The player in the slot `index`, counting from 1.
+  pub fn from_index(index: c_int) -> Option<Self> {
+    if index < 1 || index > max_clients() {
+      return None;
+    }
+
+    edict_of_index(index).and_then(PlayerHandle::from_edict)
+  }
+
+  pub fn index(&self) -> c_int {
+    self.index
+  }
+
+  pub fn state(&self) -> ConnectionState {
+    match SLOTS.lock().unwrap().slots.get(&self.index) {
+      Some(slot) if slot.connection == self.connection => slot.state,
+      _ => ConnectionState::Disconnected,
+    }
+  }
+
+  pub fn is_connected(&self) -> bool {
+    self.state() != ConnectionState::Disconnected
+  }
+
+  pub fn get<'a>(&self) -> Option<&'a Edict> {
+    match self.is_connected() {
+      true => self.entity_handle.get(),
+      false => None,
+    }
+  }
+
+  pub fn entity_handle(&self) -> EntityHandle {
+    self.entity_handle.clone()
+  }
+
+  /// The address the player connected from, `None` if they were on the
+  /// server before Luna was loaded.
+  pub fn address(&self) -> Option<String> {
+    match SLOTS.lock().unwrap().slots.get(&self.index) {
+      Some(slot) if slot.connection == self.connection => slot.address.clone(),
+      _ => None,
+    }
+  }
+
+  pub fn name(&self) -> Option<String> {
+    self.get().map(|edict| info_key(edict, "name"))
+  }
+
+  pub fn is_bot(&self) -> bool {
+    self.get()
+      .map(|edict| entvars_of_edict(edict).flags & FL_FAKECLIENT != 0)
+      .unwrap_or(false)
+  }
+}
+
+// Value of `key` in the player's userinfo.
+fn info_key(edict: &Edict, key: &str) -> String {
+  let key = CString::new(key).unwrap_or_default();
+  unsafe {
+    let buffer = ((*ENGINE_FUNCTIONS).get_info_key_buffer)(
+      edict as *const Edict as *mut Edict
+    );
+    let value = ((*ENGINE_FUNCTIONS).info_key_value)(buffer, key.as_ptr());
+    match value.is_null() {
+      true => String::new(),
+      false => CStr::from_ptr(value).to_string_lossy().into_owned(),
+    }
+  }
+}
+
+/// Starts a new connection in the slot of `edict`.
+pub fn player_connecting(edict: &Edict, address: &str) -> PlayerHandle {
+  let index = index_of_edict(edict);
+  let mut slots = SLOTS.lock().unwrap();
+  let connection = slots.next_connection;
+  slots.next_connection += 1;
+  slots.slots.insert(index, Slot {
+    state: ConnectionState::Connecting,
+    connection,
+    address: Some(address.to_string()),
+  });
+
+  PlayerHandle {
+    entity_handle: EntityHandle::new(edict),
+    index,
+    connection,
+  }
+}
+
+/// Marks the player in the slot of `edict` as in the game.
+pub fn player_entered(edict: &Edict) -> Option<PlayerHandle> {
+  let player = PlayerHandle::from_edict(edict)?;
+  if let Some(slot) = SLOTS.lock().unwrap().slots.get_mut(&player.index) {
+    slot.state = ConnectionState::InGame;
+  }
+  Some(player)
+}
+
+/// Marks the player in the slot of `edict` as gone. Handles to them stop
+/// being valid.
+pub fn player_left(edict: &Edict) {
+  let index = index_of_edict(edict);
+  if let Some(slot) = SLOTS.lock().unwrap().slots.get_mut(&index) {
+    slot.state = ConnectionState::Disconnected;
+  }
+}
+
+/// Every player that is connecting or in the game, by slot.
+pub fn connected_players() -> Vec<PlayerHandle> {
+  (1..=max_clients())
+    .filter_map(PlayerHandle::from_index)
+    .collect()
+}
+
+fn not_connected() -> rlua::Error {
+  rlua::Error::RuntimeError("Player is not connected".into())
+}
+
+// Weak table in the registry that holds the userdata of every player handle
+// that's alive in Lua
+const PLAYER_HANDLES_KEY: &str = "luna_player_handles";
+
+/// Lua userdata for `PlayerHandle`. Like entities, players are interned when
+/// they are passed to Lua, so the same connection is always the same Lua
+/// value.
+#[derive(Clone)]
+pub struct PlayerUserData(pub PlayerHandle);
+
+impl<'lua> rlua::ToLua<'lua> for PlayerHandle {
+  fn to_lua(self, ctx: rlua::Context<'lua>) -> rlua::Result<rlua::Value<'lua>> {
+    let key = format!("{}:{}", self.index, self.connection);
+    intern(ctx, PLAYER_HANDLES_KEY, key, || PlayerUserData(self).to_lua(ctx))
+  }
+}
+
+impl<'lua> rlua::FromLua<'lua> for PlayerHandle {
+  fn from_lua(value: rlua::Value<'lua>, _: rlua::Context<'lua>) -> rlua::Result<Self> {
+    match value {
+      rlua::Value::UserData(ud) => Ok(ud.borrow::<PlayerUserData>()?.0.clone()),
+      _ => Err(rlua::Error::FromLuaConversionError {
+        from: "value",
+        to: "Player",
+        message: Some("expected a player".to_string()),
+      }),
+    }
+  }
+}
+
+impl rlua::UserData for PlayerUserData {
+  fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(m: &mut M) {
+    m.add_method("Index", |_, PlayerUserData(this), ()| Ok(this.index()));
+    m.add_method("State", |_, PlayerUserData(this), ()| Ok(this.state().name()));
+    m.add_method("IsConnected", |_, PlayerUserData(this), ()| Ok(this.is_connected()));
+    m.add_method("IsInGame", |_, PlayerUserData(this), ()| {
+      Ok(this.state() == ConnectionState::InGame)
+    });
+    m.add_method("IsBot", |_, PlayerUserData(this), ()| Ok(this.is_bot()));
+    m.add_method("Address", |_, PlayerUserData(this), ()| Ok(this.address()));
+
+    m.add_method("UserId", |_, PlayerUserData(this), ()| {
+      this.get().map(player_user_id).ok_or_else(not_connected)
+    });
+    m.add_method("Name", |_, PlayerUserData(this), ()| {
+      this.name().ok_or_else(not_connected)
+    });
+    m.add_method("AuthId", |_, PlayerUserData(this), ()| {
+      this.get().map(player_auth_id).ok_or_else(not_connected)
+    });
+    m.add_method("Ping", |_, PlayerUserData(this), ()| {
+      this.get().map(|edict| player_stats(edict).0).ok_or_else(not_connected)
+    });
+    m.add_method("PacketLoss", |_, PlayerUserData(this), ()| {
+      this.get().map(|edict| player_stats(edict).1).ok_or_else(not_connected)
+    });
+
+    m.add_method("Entity", |_, PlayerUserData(this), ()| {
+      this.get().map(|_| this.entity_handle()).ok_or_else(not_connected)
+    });
+    m.add_method("EntVars", |_, PlayerUserData(this), ()| {
+      this.get()
+        .map(|_| EntVarsHandle::new(this.entity_handle()))
+        .ok_or_else(not_connected)
+    });
+
+    m.add_meta_method(rlua::MetaMethod::ToString, |_, PlayerUserData(this), ()| {
+      Ok(format!("Player({}, \"{}\")", this.index, this.name().unwrap_or_default()))
+    });
+  }
+}
+
+unsafe impl Send for PlayerHandle { }
+unsafe impl Send for PlayerUserData { }
@@ -0,0 +1,134 @@
+// Fields of the game's C++ entity classes, which live in the private data the
+// game allocates for every entity. The fields and where they are differ
+// between mods, so they are listed per mod in `PrivateData/<game dir>.toml`.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use crate::config::PrivateDataFieldConfig;
+use super::fields::{read_field, write_field, write_array, FieldArray, FieldArrayHandle, FieldType};
+use super::hl_lua_bridge::EntityHandle;
+
+#[derive(Clone, Copy)]
+struct PrivateDataField {
+  offset: usize,
+  field_type: FieldType,
+  count: usize,
+}
+
+impl PrivateDataField {
+  // Addresses of the field's elements in the private data at `base`.
+  fn addresses(&self, base: usize) -> Vec<usize> {
+    (0..self.count)
+      .map(|i| base + self.offset + i * self.field_type.size())
+      .collect()
+  }
+}
+
+lazy_static! {
+  static ref FIELDS: Mutex<HashMap<String, PrivateDataField>> = Mutex::new(HashMap::new());
+}
+
+/// Sets the fields plugins can access.
+pub fn set_fields(fields: HashMap<String, PrivateDataFieldConfig>) {
+  *FIELDS.lock().unwrap() = fields.into_iter()
+    .map(|(name, config)| (name, PrivateDataField {
+      offset: config.offset(),
+      field_type: config.field_type,
+      count: config.count,
+    }))
+    .collect();
+}
+
+/// Whether `name` is in the mod's table.
+pub fn is_field(name: &str) -> bool {
+  FIELDS.lock().unwrap().contains_key(name)
+}
+
+fn field(name: &str) -> rlua::Result<PrivateDataField> {
+  FIELDS.lock().unwrap().get(name).copied().ok_or_else(|| {
+    rlua::Error::RuntimeError(format!("Invalid private data field \"{}\"", name))
+  })
+}
+
+// Start of the private data of the entity. Entities the game hasn't set up
+// have none.
+fn private_data_of(entity: &EntityHandle) -> rlua::Result<usize> {
+  let edict = entity.get()
+    .ok_or_else(|| rlua::Error::RuntimeError("Invalid entity".into()))?;
+  match edict.private_data() as usize {
+    0 => Err(rlua::Error::RuntimeError("Entity has no private data".into())),
+    address => Ok(address),
+  }
+}
+
+/// The private data of an entity. Fields are read and written by name, like
+/// entvars. Arrays are indexed from 1.
+#[derive(Clone)]
+pub struct PrivateDataHandle {
+  entity_handle: EntityHandle,
+}
+
+impl PrivateDataHandle {
+  pub fn new(entity_handle: EntityHandle) -> Self {
+    PrivateDataHandle {
+      entity_handle,
+    }
+  }
+}
+
+impl rlua::UserData for PrivateDataHandle {
+  fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(m: &mut M) {
+    m.add_meta_method(rlua::MetaMethod::Index, |ctx, this: &Self, key: String| {
+      let base = private_data_of(&this.entity_handle)?;
+      let field = field(&key)?;
+      match field.count {
+        1 => unsafe { read_field(ctx, base + field.offset, field.field_type) },
+        _ => Ok(rlua::Value::UserData(ctx.create_userdata(FieldArrayHandle(PrivateDataArray {
+          entity_handle: this.entity_handle.clone(),
+          name: key,
+        }))?)),
+      }
+    });
+
+    m.add_meta_method(rlua::MetaMethod::NewIndex, |ctx, this: &Self, (key, value): (String, rlua::Value)| {
+      let base = private_data_of(&this.entity_handle)?;
+      let field = field(&key)?;
+      if field.count == 1 {
+        return unsafe { write_field(ctx, base + field.offset, field.field_type, value) };
+      }
+
+      // Arrays can be assigned as a whole from a table
+      match value {
+        rlua::Value::Table(values) => unsafe {
+          write_array(ctx, &field.addresses(base), field.field_type, values)
+        },
+        _ => Err(rlua::Error::RuntimeError(
+          format!("Private data field \"{}\" can only be assigned a table", key),
+        )),
+      }
+    });
+  }
+}
+
+unsafe impl Send for PrivateDataHandle { }
+
+/// A private data field with a `Count`, like the ammo of a player.
+#[derive(Clone)]
+pub struct PrivateDataArray {
+  entity_handle: EntityHandle,
+  name: String,
+}
+
+impl FieldArray for PrivateDataArray {
+  fn elements(&self) -> rlua::Result<(Vec<usize>, FieldType)> {
+    let base = private_data_of(&self.entity_handle)?;
+    let field = field(&self.name)?;
+    Ok((field.addresses(base), field.field_type))
+  }
+
+  fn describe(&self) -> String {
+    format!("private data field \"{}\"", self.name)
+  }
+}
+
+unsafe impl Send for PrivateDataArray { }
@@ -0,0 +1,257 @@
+use std::os::raw::c_float;
+use crate::meta_ffi::types::EngineVector3;
+
+/// Lua userdata for `EngineVector3`. Plugins get these when reading vector
+/// entvars and create them through `Luna/Math.Vector`. Vectors are values
+/// like numbers, a vector read from an entvar is a copy. So components
+/// can't be assigned, `ev.origin.x = 1` wouldn't move anything.
+#[derive(Clone, Copy)]
+pub struct Vector(pub EngineVector3);
+
+impl Vector {
+  pub fn new(x: c_float, y: c_float, z: c_float) -> Self {
+    Vector(EngineVector3(x, y, z))
+  }
+
+  fn map(self, f: impl Fn(c_float) -> c_float) -> Self {
+    let EngineVector3(x, y, z) = self.0;
+    Vector::new(f(x), f(y), f(z))
+  }
+
+  fn zip(self, other: Self, f: impl Fn(c_float, c_float) -> c_float) -> Self {
+    let (EngineVector3(x1, y1, z1), EngineVector3(x2, y2, z2)) = (self.0, other.0);
+    Vector::new(f(x1, x2), f(y1, y2), f(z1, z2))
+  }
+
+  pub fn dot(self, other: Self) -> c_float {
+    let (EngineVector3(x1, y1, z1), EngineVector3(x2, y2, z2)) = (self.0, other.0);
+    x1 * x2 + y1 * y2 + z1 * z2
+  }
+
+  pub fn cross(self, other: Self) -> Self {
+    let (EngineVector3(x1, y1, z1), EngineVector3(x2, y2, z2)) = (self.0, other.0);
+    Vector::new(y1 * z2 - z1 * y2, z1 * x2 - x1 * z2, x1 * y2 - y1 * x2)
+  }
+
+  pub fn length(self) -> c_float {
+    self.dot(self).sqrt()
+  }
+
+  /// The zero vector stays zero.
+  pub fn normalize(self) -> Self {
+    let len = self.length();
+    if len == 0.0 { self } else { self.map(|c| c / len) }
+  }
+
+  /// Pitch and yaw pointing along this vector, same as the engine's
+  /// `VecToAngles`.
+  pub fn to_angles(self) -> Self {
+    let EngineVector3(x, y, z) = self.0;
+    if x == 0.0 && y == 0.0 {
+      let pitch = if z > 0.0 { 90.0 } else { 270.0 };
+      return Vector::new(pitch, 0.0, 0.0);
+    }
+
+    let wrap = |angle: c_float| if angle < 0.0 { angle + 360.0 } else { angle };
+    let yaw = wrap(y.atan2(x).to_degrees());
+    let pitch = wrap(z.atan2((x * x + y * y).sqrt()).to_degrees());
+    Vector::new(pitch, yaw, 0.0)
+  }
+
+  /// Treats this vector as pitch, yaw and roll and returns the forward,
+  /// right and up vectors, same as the engine's `AngleVectors`.
+  pub fn angle_vectors(self) -> (Self, Self, Self) {
+    let EngineVector3(pitch, yaw, roll) = self.map(c_float::to_radians).0;
+    let (sp, cp) = pitch.sin_cos();
+    let (sy, cy) = yaw.sin_cos();
+    let (sr, cr) = roll.sin_cos();
+
+    let forward = Vector::new(cp * cy, cp * sy, -sp);
+    let right = Vector::new(
+      -sr * sp * cy + cr * sy,
+      -sr * sp * sy - cr * cy,
+      -sr * cp,
+    );
+    let up = Vector::new(
+      cr * sp * cy + sr * sy,
+      cr * sp * sy - sr * cy,
+      cr * cp,
+    );
+
+    (forward, right, up)
+  }
+}
+
+impl rlua::UserData for Vector {
+  fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(m: &mut M) {
+    m.add_method("Length", |_, this: &Self, ()| Ok(this.length()));
+    m.add_method("Normalize", |_, this: &Self, ()| Ok(this.normalize()));
+    m.add_method("Dot", |_, this: &Self, other: EngineVector3| {
+      Ok(this.dot(Vector(other)))
+    });
+    m.add_method("Cross", |_, this: &Self, other: EngineVector3| {
+      Ok(this.cross(Vector(other)))
+    });
+    m.add_method("ToAngles", |_, this: &Self, ()| Ok(this.to_angles()));
+    m.add_method("AngleVectors", |_, this: &Self, ()| Ok(this.angle_vectors()));
+    m.add_method("Unpack", |_, this: &Self, ()| {
+      let EngineVector3(x, y, z) = this.0;
+      Ok((x, y, z))
+    });
+
+    m.add_meta_method(rlua::MetaMethod::Index, |_, this: &Self, key: String| {
+      let EngineVector3(x, y, z) = this.0;
+      match key.as_str() {
+        "x" => Ok(x),
+        "y" => Ok(y),
+        "z" => Ok(z),
+        _ => Err(rlua::Error::RuntimeError(
+          format!("Invalid vector component \"{}\"", key),
+        )),
+      }
+    });
+    m.add_meta_method(rlua::MetaMethod::NewIndex, |_, _: &Self, (key, _): (String, rlua::Value)| {
+      Err::<(), _>(rlua::Error::RuntimeError(format!(
+        "Can't assign \"{}\" of a vector, vectors can't be changed in place",
+        key,
+      )))
+    });
+
+    m.add_meta_function(rlua::MetaMethod::Add, |_, (l, r): (EngineVector3, EngineVector3)| {
+      Ok(Vector(l).zip(Vector(r), |l, r| l + r))
+    });
+    m.add_meta_function(rlua::MetaMethod::Sub, |_, (l, r): (EngineVector3, EngineVector3)| {
+      Ok(Vector(l).zip(Vector(r), |l, r| l - r))
+    });
+    m.add_meta_function(rlua::MetaMethod::Mul, |ctx, (l, r): (rlua::Value, rlua::Value)| {
+      use rlua::FromLua;
+
+      // Either side may be the scalar
+      match (c_float::from_lua(l.clone(), ctx), c_float::from_lua(r.clone(), ctx)) {
+        (Ok(scalar), _) => Ok(Vector(EngineVector3::from_lua(r, ctx)?).map(|c| c * scalar)),
+        (_, Ok(scalar)) => Ok(Vector(EngineVector3::from_lua(l, ctx)?).map(|c| c * scalar)),
+        _ => Err(rlua::Error::RuntimeError(
+          "Vectors can only be multiplied by numbers".into(),
+        )),
+      }
+    });
+    m.add_meta_method(rlua::MetaMethod::Div, |_, this: &Self, scalar: c_float| {
+      Ok(this.map(|c| c / scalar))
+    });
+    m.add_meta_method(rlua::MetaMethod::Unm, |_, this: &Self, ()| {
+      Ok(this.map(|c| -c))
+    });
+    m.add_meta_function(rlua::MetaMethod::Eq, |_, (l, r): (EngineVector3, EngineVector3)| {
+      Ok(l.0 == r.0 && l.1 == r.1 && l.2 == r.2)
+    });
+    m.add_meta_method(rlua::MetaMethod::ToString, |_, this: &Self, ()| {
+      let EngineVector3(x, y, z) = this.0;
+      Ok(format!("Vector({}, {}, {})", x, y, z))
+    });
+  }
+}
+
+impl<'lua> rlua::ToLua<'lua> for EngineVector3 {
+  fn to_lua(self, ctx: rlua::Context<'lua>) -> rlua::Result<rlua::Value<'lua>> {
+    Vector(self).to_lua(ctx)
+  }
+}
+
+// Accepts `Vector`s as well as `{ x = 1, y = 2, z = 3 }` and `{ 1, 2, 3 }`
+// tables.
+impl<'lua> rlua::FromLua<'lua> for EngineVector3 {
+  fn from_lua(value: rlua::Value<'lua>, _: rlua::Context<'lua>) -> rlua::Result<Self> {
+    match value {
+      rlua::Value::UserData(ud) => Ok(ud.borrow::<Vector>()?.0),
+      rlua::Value::Table(table) => {
+        let component = |key: &str, idx: i64| -> rlua::Result<c_float> {
+          match table.get::<_, Option<c_float>>(key)? {
+            Some(c) => Ok(c),
+            None => table.get(idx),
+          }
+        };
+        Ok(EngineVector3(component("x", 1)?, component("y", 2)?, component("z", 3)?))
+      }
+      _ => Err(rlua::Error::FromLuaConversionError {
+        from: "value",
+        to: "Vector",
+        message: Some("expected a Vector or a table".to_string()),
+      }),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_near(actual: Vector, expected: (c_float, c_float, c_float)) {
+    let EngineVector3(x, y, z) = actual.0;
+    let close = |a: c_float, b: c_float| (a - b).abs() < 1e-4;
+    assert!(
+      close(x, expected.0) && close(y, expected.1) && close(z, expected.2),
+      "({}, {}, {}) isn't {:?}", x, y, z, expected,
+    );
+  }
+
+  #[test]
+  fn normalize_keeps_the_direction() {
+    assert_near(Vector::new(3.0, 4.0, 0.0).normalize(), (0.6, 0.8, 0.0));
+    assert_near(Vector::new(0.0, 0.0, -2.0).normalize(), (0.0, 0.0, -1.0));
+    assert_near(Vector::new(0.0, 0.0, 0.0).normalize(), (0.0, 0.0, 0.0));
+  }
+
+  #[test]
+  fn dot_and_cross() {
+    assert_eq!(Vector::new(1.0, 2.0, 3.0).dot(Vector::new(4.0, -5.0, 6.0)), 12.0);
+    assert_eq!(Vector::new(1.0, 0.0, 0.0).dot(Vector::new(0.0, 1.0, 0.0)), 0.0);
+
+    assert_near(Vector::new(1.0, 0.0, 0.0).cross(Vector::new(0.0, 1.0, 0.0)), (0.0, 0.0, 1.0));
+    assert_near(Vector::new(0.0, 1.0, 0.0).cross(Vector::new(1.0, 0.0, 0.0)), (0.0, 0.0, -1.0));
+    assert_near(Vector::new(1.0, 2.0, 3.0).cross(Vector::new(4.0, 5.0, 6.0)), (-3.0, 6.0, -3.0));
+  }
+
+  #[test]
+  fn to_angles_matches_the_engine() {
+    assert_near(Vector::new(1.0, 0.0, 0.0).to_angles(), (0.0, 0.0, 0.0));
+    assert_near(Vector::new(0.0, 1.0, 0.0).to_angles(), (0.0, 90.0, 0.0));
+    assert_near(Vector::new(-1.0, 0.0, 0.0).to_angles(), (0.0, 180.0, 0.0));
+    assert_near(Vector::new(0.0, -1.0, 0.0).to_angles(), (0.0, 270.0, 0.0));
+    assert_near(Vector::new(1.0, 0.0, 1.0).to_angles(), (45.0, 0.0, 0.0));
+    assert_near(Vector::new(0.0, 0.0, 1.0).to_angles(), (90.0, 0.0, 0.0));
+    assert_near(Vector::new(0.0, 0.0, -1.0).to_angles(), (270.0, 0.0, 0.0));
+  }
+
+  #[test]
+  fn angle_vectors_matches_the_engine() {
+    let (forward, right, up) = Vector::new(0.0, 0.0, 0.0).angle_vectors();
+    assert_near(forward, (1.0, 0.0, 0.0));
+    assert_near(right, (0.0, -1.0, 0.0));
+    assert_near(up, (0.0, 0.0, 1.0));
+
+    let (forward, right, up) = Vector::new(0.0, 90.0, 0.0).angle_vectors();
+    assert_near(forward, (0.0, 1.0, 0.0));
+    assert_near(right, (1.0, 0.0, 0.0));
+    assert_near(up, (0.0, 0.0, 1.0));
+
+    // Positive pitch looks down
+    let (forward, _, up) = Vector::new(90.0, 0.0, 0.0).angle_vectors();
+    assert_near(forward, (0.0, 0.0, -1.0));
+    assert_near(up, (1.0, 0.0, 0.0));
+
+    let (forward, right, up) = Vector::new(0.0, 0.0, 90.0).angle_vectors();
+    assert_near(forward, (1.0, 0.0, 0.0));
+    assert_near(right, (0.0, 0.0, -1.0));
+    assert_near(up, (0.0, -1.0, 0.0));
+  }
+
+  #[test]
+  fn components_cant_be_assigned() {
+    let lua = rlua::Lua::new();
+    lua.context(|ctx| {
+      ctx.globals().set("v", Vector::new(1.0, 2.0, 3.0)).unwrap();
+      assert!(ctx.load("v.x = 5").exec().is_err());
+      assert_eq!(ctx.load("return v.x").eval::<c_float>().unwrap(), 1.0);
+    });
+  }
+}
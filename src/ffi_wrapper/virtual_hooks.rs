@@ -0,0 +1,515 @@
+// Hooks on the virtual functions of the game's entity classes. The slot of
+// the function in a class's vtable is swapped for a trampoline, which hands
+// the call to `MetaContext::virtual_hook` before and after calling the
+// original, much like Metamod does for the game's exported functions.
+//
+// Every argument is assumed to be passed as one 32-bit word, which is what
+// the 32-bit builds of the engine and the mods do.
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_float, c_void};
+use std::sync::Mutex;
+use serde_derive::{Serialize, Deserialize};
+use crate::config::VirtualFunctionConfig;
+use crate::meta_ffi::types::{Edict, EntVars, EngineVector3, MetaResult};
+use super::hl_lua_bridge::EntityHandle;
+use super::dll_hooks::HookResult;
+use super::{create_named_entity, remove_entity, module_context};
+
+// Limits of the trampolines generated below
+const MAX_ARGS: usize = 6;
+const MAX_FUNCTIONS: usize = 64;
+
+/// Types of arguments and return values of virtual functions.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum VirtualType {
+  Void,
+  Int,
+  Float,
+  Bool,
+  /// `CBaseEntity *`
+  Entity,
+  /// `entvars_t *`
+  EntVars,
+  /// `edict_t *`
+  Edict,
+  /// `Vector *` or `const Vector &`
+  Vector,
+  /// `const char *`
+  String,
+}
+
+#[derive(Clone)]
+pub enum VirtualValue {
+  Void,
+  Int(c_int),
+  Float(c_float),
+  Bool(bool),
+  Entity(Option<EntityHandle>),
+  Vector(EngineVector3),
+  String(String),
+}
+
+impl VirtualValue {
+  /// Converts what a hook returned into a value of type `value_type`.
+  pub fn from_lua<'lua>(
+    value_type: VirtualType,
+    value: rlua::Value<'lua>,
+    ctx: rlua::Context<'lua>,
+  ) -> rlua::Result<Self> {
+    use rlua::FromLua;
+
+    Ok(match value_type {
+      VirtualType::Void => VirtualValue::Void,
+      VirtualType::Int => VirtualValue::Int(c_int::from_lua(value, ctx)?),
+      VirtualType::Float => VirtualValue::Float(c_float::from_lua(value, ctx)?),
+      VirtualType::Bool => VirtualValue::Bool(bool::from_lua(value, ctx)?),
+      VirtualType::Entity | VirtualType::EntVars | VirtualType::Edict => {
+        VirtualValue::Entity(Option::<EntityHandle>::from_lua(value, ctx)?)
+      }
+      VirtualType::Vector => VirtualValue::Vector(EngineVector3::from_lua(value, ctx)?),
+      VirtualType::String => VirtualValue::String(String::from_lua(value, ctx)?),
+    })
+  }
+}
+
+impl<'lua> rlua::ToLua<'lua> for VirtualValue {
+  fn to_lua(self, ctx: rlua::Context<'lua>) -> rlua::Result<rlua::Value<'lua>> {
+    match self {
+      VirtualValue::Void => Ok(rlua::Nil),
+      VirtualValue::Int(value) => value.to_lua(ctx),
+      VirtualValue::Float(value) => value.to_lua(ctx),
+      VirtualValue::Bool(value) => value.to_lua(ctx),
+      VirtualValue::Entity(value) => value.to_lua(ctx),
+      VirtualValue::Vector(value) => value.to_lua(ctx),
+      VirtualValue::String(value) => value.to_lua(ctx),
+    }
+  }
+}
+
+/// A call of a hooked virtual function.
+pub struct VirtualCall {
+  pub function: String,
+  // Every hooked class whose vtable this is. Classes that are the same C++
+  // class share one.
+  pub classes: Vec<String>,
+  pub entity: EntityHandle,
+  pub args: Vec<VirtualValue>,
+  pub return_type: VirtualType,
+  /// What the function returned, only set for post hooks.
+  pub original_return: Option<VirtualValue>,
+}
+
+struct Function {
+  name: String,
+  offset: usize,
+  args: Vec<VirtualType>,
+  return_type: VirtualType,
+}
+
+struct Patch {
+  slot: *mut usize,
+  original: usize,
+  classes: Vec<String>,
+}
+
+struct VirtualHookTable {
+  // Indexed by the id the trampolines are instantiated with
+  functions: Vec<Function>,
+  // By function id and vtable
+  patches: HashMap<(usize, usize), Patch>,
+  vtables: HashMap<String, usize>,
+}
+
+unsafe impl Send for VirtualHookTable { }
+
+lazy_static! {
+  static ref TABLE: Mutex<VirtualHookTable> = Mutex::new(VirtualHookTable {
+    functions: Vec::new(),
+    patches: HashMap::new(),
+    vtables: HashMap::new(),
+  });
+}
+
+/// Sets the functions that can be hooked. Must be called before anything
+/// is hooked.
+pub fn set_functions(functions: HashMap<String, VirtualFunctionConfig>) -> Result<(), String> {
+  if functions.len() > MAX_FUNCTIONS {
+    return Err(format!("At most {} virtual functions are supported", MAX_FUNCTIONS));
+  }
+
+  let mut functions: Vec<Function> = functions.into_iter()
+    .map(|(name, config)| Function {
+      offset: config.offset(),
+      name,
+      args: config.args,
+      return_type: config.return_type,
+    })
+    .collect();
+  functions.sort_by(|a, b| a.name.cmp(&b.name));
+
+  if let Some(function) = functions.iter().find(|function| function.args.len() > MAX_ARGS) {
+    return Err(format!(
+      "Virtual function \"{}\" has more than {} arguments", function.name, MAX_ARGS,
+    ));
+  }
+
+  // A `Vector` is returned through a hidden pointer argument, which the
+  // trampolines don't pass along
+  if let Some(function) = functions.iter().find(|function| function.return_type == VirtualType::Vector) {
+    return Err(format!(
+      "Virtual function \"{}\" returns a Vector, which isn't supported", function.name,
+    ));
+  }
+
+  TABLE.lock().unwrap().functions = functions;
+  Ok(())
+}
+
+/// Whether `function` is in the mod's table.
+pub fn is_function(function: &str) -> bool {
+  TABLE.lock().unwrap().functions.iter().any(|f| f.name == function)
+}
+
+/// Hooks `function` for the game class `classname`. Hooking it again does
+/// nothing. Entities have to be creatable to find the class's vtable, so
+/// this only works while a map is running.
+pub fn hook(classname: &str, function: &str) -> Result<(), String> {
+  let (id, cached) = {
+    let table = TABLE.lock().unwrap();
+    let id = table.functions.iter()
+      .position(|f| f.name == function)
+      .ok_or_else(|| format!("Unknown virtual function \"{}\"", function))?;
+    (id, table.vtables.get(classname).copied())
+  };
+
+  // Not locked while the game creates the entity, that runs Luna's hooks
+  let vtable = match cached {
+    Some(vtable) => vtable,
+    None => vtable_of_class(classname)
+      .ok_or_else(|| format!("Unknown entity class \"{}\"", classname))?,
+  };
+
+  let mut table = TABLE.lock().unwrap();
+  table.vtables.insert(classname.to_string(), vtable);
+
+  if let Some(patch) = table.patches.get_mut(&(id, vtable)) {
+    if !patch.classes.iter().any(|class| class == classname) {
+      patch.classes.push(classname.to_string());
+    }
+    return Ok(());
+  }
+
+  let function = &table.functions[id];
+  let float = function.return_type == VirtualType::Float;
+  let trampoline = trampoline(function.args.len(), float, id).unwrap();
+  let slot = (vtable as *mut usize).wrapping_add(function.offset);
+
+  unsafe {
+    let original = *slot;
+    if !write_slot(slot, trampoline) {
+      return Err(format!("Couldn't patch the vtable of \"{}\"", classname));
+    }
+
+    table.patches.insert((id, vtable), Patch {
+      slot,
+      original,
+      classes: vec![classname.to_string()],
+    });
+  }
+
+  Ok(())
+}
+
+/// Puts every original function back into its vtable.
+pub fn restore_all() {
+  let mut table = TABLE.lock().unwrap();
+  for (_, patch) in table.patches.drain() {
+    unsafe { write_slot(patch.slot, patch.original); }
+  }
+  table.vtables.clear();
+}
+
+// Creates a throwaway entity of the class to read its vtable.
+fn vtable_of_class(classname: &str) -> Option<usize> {
+  let edict = create_named_entity(classname)?;
+  let vtable = unsafe {
+    (edict.private_data() as *const usize).as_ref().copied()
+  };
+  remove_entity(edict);
+  vtable
+}
+
+#[cfg(unix)]
+unsafe fn write_slot(slot: *mut usize, value: usize) -> bool {
+  extern "C" {
+    fn mprotect(addr: *mut c_void, len: usize, prot: c_int) -> c_int;
+  }
+  const PAGE_SIZE: usize = 4096;
+  const PROT_READ_WRITE_EXEC: c_int = 7;
+
+  // The slot may straddle two pages
+  let page = slot as usize & !(PAGE_SIZE - 1);
+  let len = slot as usize + std::mem::size_of::<usize>() - page;
+  if mprotect(page as *mut c_void, len, PROT_READ_WRITE_EXEC) != 0 {
+    return false;
+  }
+
+  *slot = value;
+  true
+}
+
+#[cfg(windows)]
+unsafe fn write_slot(slot: *mut usize, value: usize) -> bool {
+  #[link(name = "kernel32")]
+  extern "system" {
+    fn VirtualProtect(addr: *mut c_void, size: usize, new: u32, old: *mut u32) -> c_int;
+  }
+  const PAGE_EXECUTE_READWRITE: u32 = 0x40;
+
+  let size = std::mem::size_of::<usize>();
+  let mut old = 0;
+  if VirtualProtect(slot as *mut c_void, size, PAGE_EXECUTE_READWRITE, &mut old) == 0 {
+    return false;
+  }
+
+  *slot = value;
+  VirtualProtect(slot as *mut c_void, size, old, &mut old);
+  true
+}
+
+// The entity behind a `CBaseEntity *`, whose first member after the vtable
+// is `entvars_t *pev`.
+pub(super) unsafe fn entity_of_object(object: *mut c_void) -> Option<EntityHandle> {
+  if object.is_null() {
+    return None;
+  }
+  entity_of_entvars(*(object as *const *mut EntVars).add(1))
+}
+
+unsafe fn entity_of_entvars(pev: *mut EntVars) -> Option<EntityHandle> {
+  pev.as_ref()?.containing_entity.as_ref().map(EntityHandle::new)
+}
+
+unsafe fn value_of_word(value_type: VirtualType, word: usize) -> VirtualValue {
+  match value_type {
+    VirtualType::Void => VirtualValue::Void,
+    VirtualType::Int => VirtualValue::Int(word as c_int),
+    VirtualType::Float => VirtualValue::Float(c_float::from_bits(word as u32)),
+    VirtualType::Bool => VirtualValue::Bool(word as u8 != 0),
+    VirtualType::Entity => VirtualValue::Entity(entity_of_object(word as *mut c_void)),
+    VirtualType::EntVars => VirtualValue::Entity(entity_of_entvars(word as *mut EntVars)),
+    VirtualType::Edict => {
+      VirtualValue::Entity((word as *const Edict).as_ref().map(EntityHandle::new))
+    }
+    VirtualType::Vector => {
+      VirtualValue::Vector((word as *const EngineVector3).as_ref().copied().unwrap_or(EngineVector3(0.0, 0.0, 0.0)))
+    }
+    VirtualType::String => VirtualValue::String(match (word as *const c_char).is_null() {
+      true => String::new(),
+      false => CStr::from_ptr(word as *const c_char).to_string_lossy().into_owned(),
+    }),
+  }
+}
+
+// A `Vector` or `String` can't be returned through a register, those are
+// left to the original.
+fn word_of_value(value: &VirtualValue) -> Option<usize> {
+  match value {
+    VirtualValue::Void => Some(0),
+    VirtualValue::Int(value) => Some(*value as usize),
+    VirtualValue::Bool(value) => Some(*value as usize),
+    VirtualValue::Float(value) => Some(value.to_bits() as usize),
+    VirtualValue::Entity(entity) => Some(
+      entity.as_ref().and_then(EntityHandle::get).map_or(0, |edict| edict.private_data() as usize)
+    ),
+    VirtualValue::Vector(_) | VirtualValue::String(_) => None,
+  }
+}
+
+#[derive(Clone, Copy)]
+enum RawReturn {
+  Word(usize),
+  Float(c_float),
+}
+
+impl RawReturn {
+  unsafe fn to_value(self, value_type: VirtualType) -> VirtualValue {
+    match self {
+      RawReturn::Word(word) => value_of_word(value_type, word),
+      RawReturn::Float(value) => VirtualValue::Float(value),
+    }
+  }
+
+  fn with_value(self, value: &VirtualValue) -> Self {
+    match (self, value) {
+      (RawReturn::Float(_), VirtualValue::Float(value)) => RawReturn::Float(*value),
+      (RawReturn::Float(original), _) => RawReturn::Float(original),
+      (RawReturn::Word(original), value) => RawReturn::Word(word_of_value(value).unwrap_or(original)),
+    }
+  }
+
+  fn word(self) -> usize {
+    match self {
+      RawReturn::Word(word) => word,
+      RawReturn::Float(value) => value.to_bits() as usize,
+    }
+  }
+
+  fn float(self) -> c_float {
+    match self {
+      RawReturn::Word(word) => c_float::from_bits(word as u32),
+      RawReturn::Float(value) => value,
+    }
+  }
+}
+
+unsafe fn run_hook(call: &VirtualCall, post: bool) -> HookResult<VirtualValue> {
+  match module_context() {
+    Some(ctx) => ctx.virtual_hook(call, post),
+    None => (MetaResult::Ignored, None),
+  }
+}
+
+// Runs the hooks around the original function. The table isn't locked while
+// they run, hooks may well end up calling hooked functions themselves.
+unsafe fn call_hooked(
+  id: usize,
+  this: *mut c_void,
+  args: &[usize],
+  zero: RawReturn,
+  call_original: &dyn Fn(usize) -> RawReturn,
+) -> RawReturn {
+  let vtable = *(this as *const usize);
+  let found = {
+    let table = TABLE.lock().unwrap();
+    let function = &table.functions[id];
+    match table.patches.get(&(id, vtable)) {
+      Some(patch) => Ok((VirtualCall {
+        function: function.name.clone(),
+        classes: patch.classes.clone(),
+        entity: entity_of_object(this).unwrap_or_default(),
+        args: function.args.iter()
+          .zip(args)
+          .map(|(&arg_type, &word)| value_of_word(arg_type, word))
+          .collect(),
+        return_type: function.return_type,
+        original_return: None,
+      }, patch.original)),
+      // The vtable was restored while the call was on its way, or the object
+      // isn't the one the patched vtable belongs to
+      None => Err(unhooked_function(function, id, vtable)),
+    }
+  };
+  let (mut call, original) = match found {
+    Ok(found) => found,
+    Err(unhooked) => return unhooked.map_or(zero, call_original),
+  };
+
+  let (pre_result, pre_value) = run_hook(&call, false);
+  let original_return = match pre_result {
+    MetaResult::Supercede => pre_value.as_ref().map_or(zero, |value| zero.with_value(value)),
+    _ => call_original(original),
+  };
+
+  call.original_return = Some(original_return.to_value(call.return_type));
+  let (post_result, post_value) = run_hook(&call, true);
+
+  let value = match (post_result, post_value, pre_result, pre_value) {
+    (MetaResult::Override, Some(value), _, _) => Some(value),
+    (_, _, MetaResult::Override, Some(value)) => Some(value),
+    _ => None,
+  };
+  value.map_or(original_return, |value| original_return.with_value(&value))
+}
+
+// The function in the slot of `vtable`, unless that's the trampoline of `id`.
+unsafe fn unhooked_function(function: &Function, id: usize, vtable: usize) -> Option<usize> {
+  let float = function.return_type == VirtualType::Float;
+  let current = *(vtable as *const usize).wrapping_add(function.offset);
+  match trampoline(function.args.len(), float, id) {
+    Some(trampoline) if trampoline == current => None,
+    _ => Some(current),
+  }
+}
+
+// Trampolines take the object and up to `MAX_ARGS` words, for every function
+// id, returning either a word or a float. Member functions are `thiscall`
+// with MSVC and `cdecl` with the object first with GCC.
+macro_rules! define_trampolines {
+  ($abi:literal; $($word:ident, $float:ident: ($($arg:ident),*);)*) => {
+    $(
+      unsafe extern $abi fn $word<const ID: usize>(this: *mut c_void, $($arg: usize),*) -> usize {
+        call_hooked(ID, this, &[$($arg),*], RawReturn::Word(0), &|original| {
+          let original: unsafe extern $abi fn(*mut c_void, $($arg: usize),*) -> usize =
+            std::mem::transmute(original);
+          RawReturn::Word(original(this, $($arg),*))
+        }).word()
+      }
+
+      unsafe extern $abi fn $float<const ID: usize>(this: *mut c_void, $($arg: usize),*) -> c_float {
+        call_hooked(ID, this, &[$($arg),*], RawReturn::Float(0.0), &|original| {
+          let original: unsafe extern $abi fn(*mut c_void, $($arg: usize),*) -> c_float =
+            std::mem::transmute(original);
+          RawReturn::Float(original(this, $($arg),*))
+        }).float()
+      }
+    )*
+  };
+}
+
+macro_rules! for_each_arity {
+  ($callback:ident!($abi:literal)) => {
+    $callback! {
+      $abi;
+      word0, float0: ();
+      word1, float1: (a1);
+      word2, float2: (a1, a2);
+      word3, float3: (a1, a2, a3);
+      word4, float4: (a1, a2, a3, a4);
+      word5, float5: (a1, a2, a3, a4, a5);
+      word6, float6: (a1, a2, a3, a4, a5, a6);
+    }
+  };
+}
+
+#[cfg(all(windows, target_arch = "x86"))]
+for_each_arity!(define_trampolines!("thiscall"));
+#[cfg(not(all(windows, target_arch = "x86")))]
+for_each_arity!(define_trampolines!("C"));
+
+// Addresses of one trampoline for every function id.
+macro_rules! trampoline_table {
+  ($trampoline:ident) => {
+    trampoline_table!($trampoline;
+      0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26
+      27 28 29 30 31 32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50
+      51 52 53 54 55 56 57 58 59 60 61 62 63
+    )
+  };
+  ($trampoline:ident; $($id:literal)*) => {
+    &[$($trampoline::<$id> as *const () as usize),*]
+  };
+}
+
+fn trampoline(arity: usize, float: bool, id: usize) -> Option<usize> {
+  let table: &[usize] = match (arity, float) {
+    (0, false) => trampoline_table!(word0),
+    (1, false) => trampoline_table!(word1),
+    (2, false) => trampoline_table!(word2),
+    (3, false) => trampoline_table!(word3),
+    (4, false) => trampoline_table!(word4),
+    (5, false) => trampoline_table!(word5),
+    (6, false) => trampoline_table!(word6),
+    (0, true) => trampoline_table!(float0),
+    (1, true) => trampoline_table!(float1),
+    (2, true) => trampoline_table!(float2),
+    (3, true) => trampoline_table!(float3),
+    (4, true) => trampoline_table!(float4),
+    (5, true) => trampoline_table!(float5),
+    (6, true) => trampoline_table!(float6),
+    _ => return None,
+  };
+  table.get(id).copied()
+}
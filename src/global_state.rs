@@ -1,14 +1,37 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use crate::plugin_sys::events::LuaEventEmitter;
+use crate::plugin_sys::commands::LuaCommandRegistry;
+use crate::plugin_sys::timers::TimerScheduler;
+use crate::plugin_sys::tasks::TaskScheduler;
+use crate::plugin_sys::entity_classes::EntityClassRegistry;
+use crate::plugin_sys::virtual_hooks::VirtualHookRegistry;
 
 pub struct GlobalState {
   pub listeners: LuaEventEmitter,
+  pub commands: LuaCommandRegistry,
+  pub timers: TimerScheduler,
+  pub tasks: TaskScheduler,
+  pub entity_classes: EntityClassRegistry,
+  pub virtual_hooks: VirtualHookRegistry,
+  // Plugins to reload at the start of the next server frame
+  pub pending_reloads: Vec<String>,
+  // Files each plugin pulled in through `require`
+  pub required_files: HashMap<String, HashSet<PathBuf>>,
 }
 
 impl GlobalState {
   pub fn new() -> Self {
     GlobalState {
       listeners: LuaEventEmitter::new(),
+      commands: LuaCommandRegistry::new(),
+      timers: TimerScheduler::new(),
+      tasks: TaskScheduler::new(),
+      entity_classes: EntityClassRegistry::new(),
+      virtual_hooks: VirtualHookRegistry::new(),
+      pending_reloads: Vec::new(),
+      required_files: HashMap::new(),
     }
   }
 }
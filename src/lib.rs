@@ -1,6 +1,4 @@
 #[macro_use]
-extern crate memoffset;
-#[macro_use]
 extern crate lazy_static;
 
 mod ffi_wrapper;
@@ -10,5 +8,6 @@ mod meta_ffi;
 mod plugin_sys;
 mod global_state;
 mod lua_helpers;
+mod config;
 
 pub mod meta_api;
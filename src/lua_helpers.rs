@@ -35,17 +35,72 @@ where
   TParams: rlua::ToLuaMulti<'lua>,
   TReturn: rlua::FromLuaMulti<'lua>,
 {
+  call_lua_impl(ctx, None, func, params)
+}
+
+/// Like `call_lua`, but errors are reported as coming from the plugin
+/// `owner`. Used for callbacks that don't run as part of a plugin's own call.
+pub fn call_plugin_lua<'lua, TParams, TReturn>(
+  ctx: &rlua::Context<'lua>,
+  owner: &str,
+  func: &rlua::Function<'lua>,
+  params: TParams,
+) -> rlua::Result<TReturn>
+where
+  TParams: rlua::ToLuaMulti<'lua>,
+  TReturn: rlua::FromLuaMulti<'lua>,
+{
+  call_lua_impl(ctx, Some(owner), func, params)
+}
+
+/// Resumes a task's coroutine. Errors are reported like with
+/// `call_plugin_lua`.
+pub fn resume_plugin_thread<'lua, TParams, TReturn>(
+  ctx: &rlua::Context<'lua>,
+  owner: &str,
+  thread: &rlua::Thread<'lua>,
+  params: TParams,
+) -> rlua::Result<TReturn>
+where
+  TParams: rlua::ToLuaMulti<'lua>,
+  TReturn: rlua::FromLuaMulti<'lua>,
+{
+  guarded_call(ctx, Some(owner), || thread.resume(params))
+}
+
+fn call_lua_impl<'lua, TParams, TReturn>(
+  ctx: &rlua::Context<'lua>,
+  owner: Option<&str>,
+  func: &rlua::Function<'lua>,
+  params: TParams,
+) -> rlua::Result<TReturn>
+where
+  TParams: rlua::ToLuaMulti<'lua>,
+  TReturn: rlua::FromLuaMulti<'lua>,
+{
+  guarded_call(ctx, owner, || func.call(params))
+}
+
+fn guarded_call<'lua, TReturn>(
+  ctx: &rlua::Context<'lua>,
+  owner: Option<&str>,
+  call: impl FnOnce() -> rlua::Result<TReturn>,
+) -> rlua::Result<TReturn> {
   let globals = ctx.globals();
   let call_level: usize = globals.raw_get("luna_call_level").unwrap();
   globals.raw_set("luna_call_level", call_level + 1).unwrap();
 
-  let result = func.call(params);
+  let result = call();
 
   globals.raw_set("luna_call_level", call_level).unwrap();
 
-  // If we are at the bottom of the call stack, print the error
-  if call_level == 0 {
+  // If we are at the bottom of the call stack, print the error. Plugin
+  // callbacks never hand their errors back to Lua, so print those too.
+  if call_level == 0 || owner.is_some() {
     if let Err(err) = &result {
+      if let Some(owner) = owner {
+        log_error(format!("Error in plugin \"{}\":", owner));
+      }
       print_lua_error(&err);
     }
   }
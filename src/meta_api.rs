@@ -1,6 +1,7 @@
-use std::os::raw::{c_char, c_int};
+use std::os::raw::{c_char, c_void, c_int, c_uint, c_float, c_uchar as c_byte};
 use crate::plugin_info::PLUGIN_INFO;
 use crate::ffi_wrapper;
+use crate::ffi_wrapper::dll_hooks::{self, for_each_dll_hook};
 use crate::meta_ffi::globals;
 use crate::meta_ffi::util::{set_meta_result, meta_return_value};
 use crate::meta_ffi::constant::{
@@ -22,6 +23,16 @@ use crate::meta_ffi::types::{
   MetaFunctions,
   MetaResult,
   Edict,
+  EntityState,
+  KeyValueData,
+  SaveRestoreData,
+  TypeDescription,
+  Customization,
+  PlayerMove,
+  ClientData,
+  WeaponData,
+  UserCmd,
+  NetAdr,
 };
 
 
@@ -91,6 +102,8 @@ pub unsafe extern fn meta_detach(
     return 0;
   }
 
+  // Nothing of Luna may be left in the game once it's unloaded
+  ffi_wrapper::game_shutdown();
   1
 }
 
@@ -110,6 +123,9 @@ unsafe extern fn get_entity_api2(
   (*funcs).client_connect = client_connect;
   (*funcs).client_put_in_server = client_put_in_server;
   (*funcs).client_disconnect = client_disconnect;
+  (*funcs).client_command = client_command;
+  (*funcs).start_frame = start_frame;
+  install_dll_hooks(funcs);
 
   1
 }
@@ -128,6 +144,7 @@ unsafe extern fn get_entity_api2_post(
 
   (*funcs).client_put_in_server = client_put_in_server_post;
   (*funcs).client_disconnect = client_disconnect_post;
+  install_dll_hooks_post(funcs);
 
   1
 }
@@ -144,6 +161,8 @@ unsafe extern fn get_new_dll_functions(
 
   globals::NEWDLL_HOOK_TABLE = funcs;
 
+  (*funcs).on_free_ent_private_data = on_free_ent_private_data;
+
   1
 }
 
@@ -194,6 +213,12 @@ unsafe extern fn get_engine_functions_post(
   1
 }
 
+// The game function has already been called by the time post hooks run,
+// Metamod doesn't allow superceding it anymore.
+fn post_result(result: MetaResult) -> MetaResult {
+  result.min(MetaResult::Handled)
+}
+
 unsafe extern fn game_init() {
   ffi_wrapper::game_init();
   set_meta_result(MetaResult::Ignored)
@@ -204,32 +229,83 @@ unsafe extern fn game_shutdown() {
   set_meta_result(MetaResult::Ignored)
 }
 
+unsafe extern fn start_frame() {
+  ffi_wrapper::start_frame();
+  set_meta_result(MetaResult::Ignored)
+}
+
 unsafe extern fn client_connect(
   entity: *mut Edict,
   name: *const c_char,
   address: *const c_char,
   reject_reason: *mut c_char,
 ) -> c_int {
-  let ret = ffi_wrapper::client_connect(entity, name, address, reject_reason);
-  meta_return_value(MetaResult::Ignored, ret)
+  let (result, ret) = ffi_wrapper::client_connect(entity, name, address, reject_reason);
+  meta_return_value(result, ret)
 }
 
 unsafe extern fn client_put_in_server(entity: *mut Edict) {
-  ffi_wrapper::client_put_in_server(entity);
-  set_meta_result(MetaResult::Ignored)
+  set_meta_result(ffi_wrapper::client_put_in_server(entity))
 }
 
 unsafe extern fn client_put_in_server_post(entity: *mut Edict) {
-  ffi_wrapper::client_put_in_server_post(entity);
-  set_meta_result(MetaResult::Ignored)
+  set_meta_result(post_result(ffi_wrapper::client_put_in_server_post(entity)))
 }
 
 unsafe extern fn client_disconnect(entity: *mut Edict) {
-  ffi_wrapper::client_disconnect(entity);
-  set_meta_result(MetaResult::Ignored)
+  set_meta_result(ffi_wrapper::client_disconnect(entity))
 }
 
 unsafe extern fn client_disconnect_post(entity: *mut Edict) {
-  ffi_wrapper::client_disconnect_post(entity);
+  set_meta_result(post_result(ffi_wrapper::client_disconnect_post(entity)))
+}
+
+unsafe extern fn on_free_ent_private_data(entity: *mut Edict) {
+  ffi_wrapper::on_free_ent_private_data(entity);
   set_meta_result(MetaResult::Ignored)
-}
\ No newline at end of file
+}
+
+unsafe extern fn client_command(entity: *mut Edict) {
+  set_meta_result(ffi_wrapper::client_command(entity))
+}
+
+pub unsafe extern fn server_command() {
+  ffi_wrapper::server_command();
+}
+
+macro_rules! define_dll_hooks {
+  ($($pre:ident, $post:ident: ($($arg:ident: $ty:ty),* $(,)?) -> $ret:ty;)*) => {
+    $(
+      unsafe extern fn $pre($($arg: $ty),*) -> $ret {
+        let (result, value) = dll_hooks::$pre($($arg),*);
+        meta_return_value(result, value.unwrap_or_else(|| std::mem::zeroed()))
+      }
+
+      unsafe extern fn $post($($arg: $ty),*) -> $ret {
+        let (result, value) = dll_hooks::$post($($arg),*);
+        meta_return_value(
+          post_result(result),
+          value.unwrap_or_else(|| std::mem::zeroed()),
+        )
+      }
+    )*
+
+    unsafe fn install_dll_hooks(funcs: *mut DLLFunctions) {
+      $(
+        if dll_hooks::HOOKED.contains(&stringify!($pre)) {
+          (*funcs).$pre = $pre;
+        }
+      )*
+    }
+
+    unsafe fn install_dll_hooks_post(funcs: *mut DLLFunctions) {
+      $(
+        if dll_hooks::HOOKED.contains(&stringify!($post)) {
+          (*funcs).$pre = $post;
+        }
+      )*
+    }
+  };
+}
+
+for_each_dll_hook!(define_dll_hooks);
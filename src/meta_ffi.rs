@@ -2,3 +2,4 @@ pub mod types;
 pub mod constant;
 pub mod globals;
 pub mod util;
+mod layout;
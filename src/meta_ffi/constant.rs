@@ -6,3 +6,11 @@ pub static mut META_INTERFACE_VERSION: *const c_char =
 pub const DLL_INTERFACE_VERSION: c_int = 140;
 pub const ENGINE_INTERFACE_VERSION: c_int = 138;
 pub const NEW_DLL_INTERFACE_VERSION: c_int = 1;
+
+// Size of the buffer the engine passes to `ClientConnect` for the reason a
+// connection was rejected, including the terminating null.
+pub const REJECT_REASON_SIZE: usize = 128;
+
+// `EntVars::flags` bits that mark players and bots
+pub const FL_CLIENT: c_int = 1 << 3;
+pub const FL_FAKECLIENT: c_int = 1 << 13;
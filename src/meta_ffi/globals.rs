@@ -10,7 +10,7 @@ use super::types::{
 };
 
 pub static mut ENGINE_FUNCTIONS: *const EngineFunctions = null();
-pub static mut GLOBAL_VARS: *const GlobalVars = null();
+pub static mut GLOBAL_VARS: *mut GlobalVars = null_mut();
 pub static mut META_UTIL_FUNCS: *const MetaUtilFuncs = null();
 pub static mut META_GLOBALS: *mut MetaGlobals = null_mut();
 pub static mut GAME_DLL_FUNCTIONS: *const GameDLLFunctions = null();
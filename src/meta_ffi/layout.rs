@@ -0,0 +1,233 @@
+// Compile time checks that the function tables match the engine's layout.
+// Every slot is a single function pointer, so each field has to sit at its
+// index in the C struct times the size of a pointer.
+
+use std::mem::{offset_of, size_of};
+use super::types::{EngineFunctions, DLLFunctions};
+
+macro_rules! assert_slots {
+  ($table:ty, $count:expr, { $($field:ident: $index:expr,)* }) => {
+    const _: () = assert!(size_of::<$table>() == $count * size_of::<usize>());
+    $(
+      const _: () = assert!(
+        offset_of!($table, $field) == $index * size_of::<usize>()
+      );
+    )*
+  };
+}
+
+// `enginefuncs_t`, interface version 138
+assert_slots!(EngineFunctions, 158, {
+  precache_model: 0,
+  precache_sound: 1,
+  set_model: 2,
+  model_index: 3,
+  model_frames: 4,
+  set_size: 5,
+  change_level: 6,
+  get_spawn_parms: 7,
+  save_spawn_parms: 8,
+  vec_to_yaw: 9,
+  vec_to_angles: 10,
+  move_to_origin: 11,
+  change_yaw: 12,
+  change_pitch: 13,
+  find_entity_by_string: 14,
+  get_entity_illum: 15,
+  find_entity_in_sphere: 16,
+  find_client_in_pvs: 17,
+  entities_in_pvs: 18,
+  make_vectors: 19,
+  angle_vectors: 20,
+  create_entity: 21,
+  remove_entity: 22,
+  create_named_entity: 23,
+  make_static: 24,
+  ent_is_on_floor: 25,
+  drop_to_floor: 26,
+  walk_move: 27,
+  set_origin: 28,
+  emit_sound: 29,
+  emit_ambient_sound: 30,
+  trace_line: 31,
+  trace_toss: 32,
+  trace_monster_hull: 33,
+  trace_hull: 34,
+  trace_model: 35,
+  trace_texture: 36,
+  trace_sphere: 37,
+  get_aim_vector: 38,
+  server_command: 39,
+  server_execute: 40,
+  client_command: 41,
+  particle_effect: 42,
+  light_style: 43,
+  decal_index: 44,
+  point_contents: 45,
+  message_begin: 46,
+  message_end: 47,
+  write_byte: 48,
+  write_char: 49,
+  write_short: 50,
+  write_long: 51,
+  write_angle: 52,
+  write_coord: 53,
+  write_string: 54,
+  write_entity: 55,
+  cvar_register: 56,
+  cvar_get_float: 57,
+  cvar_get_string: 58,
+  cvar_set_float: 59,
+  cvar_set_string: 60,
+  alert_message: 61,
+  engine_fprintf: 62,
+  pv_alloc_ent_private_data: 63,
+  pv_ent_private_data: 64,
+  free_ent_private_data: 65,
+  sz_from_index: 66,
+  alloc_string: 67,
+  get_vars_of_ent: 68,
+  p_entity_of_ent_offset: 69,
+  ent_offset_of_p_entity: 70,
+  index_of_edict: 71,
+  p_entity_of_ent_index: 72,
+  find_entity_by_vars: 73,
+  get_model_ptr: 74,
+  reg_user_msg: 75,
+  animation_automove: 76,
+  get_bone_position: 77,
+  function_from_name: 78,
+  name_for_function: 79,
+  client_printf: 80,
+  server_print: 81,
+  cmd_args: 82,
+  cmd_argv: 83,
+  cmd_argc: 84,
+  get_attachment: 85,
+  crc32_init: 86,
+  crc32_process_buffer: 87,
+  crc32_process_byte: 88,
+  crc32_final: 89,
+  random_long: 90,
+  random_float: 91,
+  set_view: 92,
+  time: 93,
+  crosshair_angle: 94,
+  load_file_for_me: 95,
+  free_file: 96,
+  end_section: 97,
+  compare_file_time: 98,
+  get_game_dir: 99,
+  cvar_register_variable: 100,
+  fade_client_volume: 101,
+  set_client_maxspeed: 102,
+  create_fake_client: 103,
+  run_player_move: 104,
+  number_of_entities: 105,
+  get_info_key_buffer: 106,
+  info_key_value: 107,
+  set_key_value: 108,
+  set_client_key_value: 109,
+  is_map_valid: 110,
+  static_decal: 111,
+  precache_generic: 112,
+  get_player_user_id: 113,
+  build_sound_msg: 114,
+  is_dedicated_server: 115,
+  cvar_get_pointer: 116,
+  get_player_won_id: 117,
+  info_remove_key: 118,
+  get_physics_key_value: 119,
+  set_physics_key_value: 120,
+  get_physics_info_string: 121,
+  precache_event: 122,
+  playback_event: 123,
+  set_fat_pvs: 124,
+  set_fat_pas: 125,
+  check_visibility: 126,
+  delta_set_field: 127,
+  delta_unset_field: 128,
+  delta_add_encoder: 129,
+  get_current_player: 130,
+  can_skip_player: 131,
+  delta_find_field: 132,
+  delta_set_field_by_index: 133,
+  delta_unset_field_by_index: 134,
+  set_group_mask: 135,
+  create_instanced_baseline: 136,
+  cvar_direct_set: 137,
+  force_unmodified: 138,
+  get_player_stats: 139,
+  add_server_command: 140,
+  voice_get_client_listening: 141,
+  voice_set_client_listening: 142,
+  get_player_auth_id: 143,
+  sequence_get: 144,
+  sequence_pick_sentence: 145,
+  get_file_size: 146,
+  get_approx_wave_play_len: 147,
+  is_career_match: 148,
+  get_localized_string_length: 149,
+  register_tutor_message_shown: 150,
+  get_times_tutor_message_shown: 151,
+  process_tutor_message_decay_buffer: 152,
+  construct_tutor_message_decay_buffer: 153,
+  reset_tutor_message_decay_data: 154,
+  query_client_cvar_value: 155,
+  query_client_cvar_value2: 156,
+  check_parm: 157,
+});
+
+// `DLL_FUNCTIONS`, interface version 140
+assert_slots!(DLLFunctions, 50, {
+  game_init: 0,
+  spawn: 1,
+  think: 2,
+  use_: 3,
+  touch: 4,
+  blocked: 5,
+  key_value: 6,
+  save: 7,
+  restore: 8,
+  set_abs_box: 9,
+  save_write_fields: 10,
+  save_read_fields: 11,
+  save_global_state: 12,
+  restore_global_state: 13,
+  reset_global_state: 14,
+  client_connect: 15,
+  client_disconnect: 16,
+  client_kill: 17,
+  client_put_in_server: 18,
+  client_command: 19,
+  client_user_info_changed: 20,
+  server_activate: 21,
+  server_deactivate: 22,
+  player_pre_think: 23,
+  player_post_think: 24,
+  start_frame: 25,
+  parms_new_level: 26,
+  parms_change_level: 27,
+  get_game_description: 28,
+  player_customization: 29,
+  spectator_connect: 30,
+  spectator_disconnect: 31,
+  spectator_think: 32,
+  sys_error: 33,
+  pm_move: 34,
+  pm_init: 35,
+  pm_find_texture_type: 36,
+  setup_visibility: 37,
+  update_client_data: 38,
+  add_to_full_pack: 39,
+  create_baseline: 40,
+  register_encoders: 41,
+  get_weapon_data: 42,
+  cmd_start: 43,
+  cmd_end: 44,
+  connectionless_packet: 45,
+  get_hull_bounds: 46,
+  create_instanced_baselines: 47,
+  inconsistent_file: 48,
+  allow_lag_compensation: 49,
+});
@@ -1,10 +1,11 @@
 use std::os::raw::{
-  c_char, c_void, c_int, c_short, c_float, c_uchar as c_byte
+  c_char, c_void, c_int, c_uint, c_short, c_ushort, c_ulong, c_float,
+  c_uchar as c_byte,
 };
 
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
 pub enum MetaResult {
   Unset = 0,
   Ignored,
@@ -61,33 +62,33 @@ pub struct PluginInfo {
 
 unsafe impl Sync for PluginInfo { }
 
-// #[repr(C)]
-// #[derive(Clone, Copy)]
-// pub enum AlertType {
-//   Notice = 0,
-//   Console,
-//   AIConsole,
-//   Warning,
-//   Error,
-//   Logged,
-// }
-
-// #[repr(C)]
-// #[derive(Clone, Copy)]
-// pub enum PrintType {
-//   Console = 0,
-//   Center,
-//   Chat,
-// }
-
-// #[repr(C)]
-// #[derive(Clone, Copy)]
-// pub enum ForceType {
-//   ExactFile = 0,
-//   ModelSameBounds,
-//   ModelSpecifyBounds,
-//   ModelSpecifyBoundsIfAvailable,
-// }
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum AlertType {
+  Notice = 0,
+  Console,
+  AIConsole,
+  Warning,
+  Error,
+  Logged,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum PrintType {
+  Console = 0,
+  Center,
+  Chat,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum ForceType {
+  ExactFile = 0,
+  ModelSameBounds,
+  ModelSpecifyBounds,
+  ModelSpecifyBoundsIfAvailable,
+}
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -95,7 +96,7 @@ pub struct EngineStringHandle(pub c_int);
 
 #[repr(C)]
 #[derive(Clone, Copy)]
-pub struct EngineVector3(c_float, c_float, c_float);
+pub struct EngineVector3(pub c_float, pub c_float, pub c_float);
 
 #[repr(C)]
 pub struct Edict {
@@ -290,190 +291,596 @@ pub struct EntVars {
   pub euser4: *mut Edict,
 }
 
-// #[repr(C)]
-// pub struct TraceResult {
-  
-// }
+#[repr(C)]
+pub struct TraceResult {
+  pub all_solid: c_int,
+  pub start_solid: c_int,
+  pub in_open: c_int,
+  pub in_water: c_int,
+  pub fraction: c_float,
+  pub end_pos: EngineVector3,
+  pub plane_dist: c_float,
+  pub plane_normal: EngineVector3,
+  pub hit: *mut Edict,
+  pub hitgroup: c_int,
+}
+
+#[repr(C)]
+pub struct CVar {
+  pub name: *const c_char,
+  pub string: *mut c_char,
+  pub flags: c_int,
+  pub value: c_float,
+  pub next: *mut CVar,
+}
+
+// Only ever handled through pointers
+#[repr(C)]
+pub struct Delta { _private: [u8; 0] }
+
+#[repr(C)]
+pub struct EntityState { _private: [u8; 0] }
+
+#[repr(C)]
+pub struct KeyValueData {
+  pub classname: *mut c_char,
+  pub key: *mut c_char,
+  pub value: *mut c_char,
+  pub handled: c_int,
+}
+
+#[repr(C)]
+pub struct SaveRestoreData { _private: [u8; 0] }
+
+#[repr(C)]
+pub struct TypeDescription { _private: [u8; 0] }
+
+#[repr(C)]
+pub struct Customization { _private: [u8; 0] }
+
+#[repr(C)]
+pub struct PlayerMove { _private: [u8; 0] }
+
+#[repr(C)]
+pub struct ClientData { _private: [u8; 0] }
+
+#[repr(C)]
+pub struct WeaponData { _private: [u8; 0] }
+
+#[repr(C)]
+pub struct UserCmd { _private: [u8; 0] }
+
+#[repr(C)]
+pub struct NetAdr { _private: [u8; 0] }
 
 #[repr(C)]
 pub struct EngineFunctions {
-  pub f0: unsafe extern fn() -> (),
-  pub f1: unsafe extern fn() -> (),
-  pub f2: unsafe extern fn() -> (),
-  pub f3: unsafe extern fn() -> (),
-  pub f4: unsafe extern fn() -> (),
-  pub f5: unsafe extern fn() -> (),
-  pub f6: unsafe extern fn() -> (),
-  pub f7: unsafe extern fn() -> (),
-  pub f8: unsafe extern fn() -> (),
-  pub f9: unsafe extern fn() -> (),
-  pub f10: unsafe extern fn() -> (),
-  pub f11: unsafe extern fn() -> (),
-  pub f12: unsafe extern fn() -> (),
-  pub f13: unsafe extern fn() -> (),
-  pub f14: unsafe extern fn() -> (),
-  pub f15: unsafe extern fn() -> (),
-  pub f16: unsafe extern fn() -> (),
-  pub f17: unsafe extern fn() -> (),
-  pub f18: unsafe extern fn() -> (),
-  pub f19: unsafe extern fn() -> (),
-  pub f20: unsafe extern fn() -> (),
-  pub f21: unsafe extern fn() -> (),
-  pub f22: unsafe extern fn() -> (),
-  pub f23: unsafe extern fn() -> (),
-  pub f24: unsafe extern fn() -> (),
-  pub f25: unsafe extern fn() -> (),
-  pub f26: unsafe extern fn() -> (),
-  pub f27: unsafe extern fn() -> (),
-  pub f28: unsafe extern fn() -> (),
-  pub f29: unsafe extern fn() -> (),
-  pub f30: unsafe extern fn() -> (),
-  pub f31: unsafe extern fn() -> (),
-  pub f32: unsafe extern fn() -> (),
-  pub f33: unsafe extern fn() -> (),
-  pub f34: unsafe extern fn() -> (),
-  pub f35: unsafe extern fn() -> (),
-  pub f36: unsafe extern fn() -> (),
-  pub f37: unsafe extern fn() -> (),
-  pub f38: unsafe extern fn() -> (),
-  pub f39: unsafe extern fn() -> (),
-  pub f40: unsafe extern fn() -> (),
-  pub f41: unsafe extern fn() -> (),
-  pub f42: unsafe extern fn() -> (),
-  pub f43: unsafe extern fn() -> (),
-  pub f44: unsafe extern fn() -> (),
-  pub f45: unsafe extern fn() -> (),
-  pub f46: unsafe extern fn() -> (),
-  pub f47: unsafe extern fn() -> (),
-  pub f48: unsafe extern fn() -> (),
-  pub f49: unsafe extern fn() -> (),
-  pub f50: unsafe extern fn() -> (),
-  pub f51: unsafe extern fn() -> (),
-  pub f52: unsafe extern fn() -> (),
-  pub f53: unsafe extern fn() -> (),
-  pub f54: unsafe extern fn() -> (),
-  pub f55: unsafe extern fn() -> (),
-  pub f56: unsafe extern fn() -> (),
-  pub f57: unsafe extern fn() -> (),
-  pub f58: unsafe extern fn() -> (),
-  pub f59: unsafe extern fn() -> (),
-  pub f60: unsafe extern fn() -> (),
-  pub f61: unsafe extern fn() -> (),
-  pub f62: unsafe extern fn() -> (),
-  pub f63: unsafe extern fn() -> (),
-  pub f64: unsafe extern fn() -> (),
-  pub f65: unsafe extern fn() -> (),
-  pub sz_from_index: unsafe extern fn(c_int) -> *const c_char,
-  pub alloc_string: unsafe extern fn(*const c_char) -> c_int,
-  pub get_vars_of_ent: unsafe extern fn(*mut Edict) -> *mut EntVars,
-  pub f69: unsafe extern fn() -> (),
-  pub f70: unsafe extern fn() -> (),
-  pub f71: unsafe extern fn() -> (),
-  pub f72: unsafe extern fn() -> (),
-  pub f73: unsafe extern fn() -> (),
-  pub f74: unsafe extern fn() -> (),
-  pub f75: unsafe extern fn() -> (),
-  pub f76: unsafe extern fn() -> (),
-  pub f77: unsafe extern fn() -> (),
-  pub f78: unsafe extern fn() -> (),
-  pub f79: unsafe extern fn() -> (),
-  pub f80: unsafe extern fn() -> (),
-  pub server_print: unsafe extern fn(*const c_char) -> (),
-  pub f82: unsafe extern fn() -> (),
-  pub f83: unsafe extern fn() -> (),
-  pub f84: unsafe extern fn() -> (),
-  pub f85: unsafe extern fn() -> (),
-  pub f86: unsafe extern fn() -> (),
-  pub f87: unsafe extern fn() -> (),
-  pub f88: unsafe extern fn() -> (),
-  pub f89: unsafe extern fn() -> (),
-  pub f90: unsafe extern fn() -> (),
-  pub f91: unsafe extern fn() -> (),
-  pub f92: unsafe extern fn() -> (),
-  pub f93: unsafe extern fn() -> (),
-  pub f94: unsafe extern fn() -> (),
-  pub f95: unsafe extern fn() -> (),
-  pub f96: unsafe extern fn() -> (),
-  pub f97: unsafe extern fn() -> (),
-  pub f98: unsafe extern fn() -> (),
-  pub f99: unsafe extern fn() -> (),
-  pub f100: unsafe extern fn() -> (),
-  pub f101: unsafe extern fn() -> (),
-  pub f102: unsafe extern fn() -> (),
-  pub f103: unsafe extern fn() -> (),
-  pub f104: unsafe extern fn() -> (),
-  pub f105: unsafe extern fn() -> (),
-  pub f106: unsafe extern fn() -> (),
-  pub f107: unsafe extern fn() -> (),
-  pub f108: unsafe extern fn() -> (),
-  pub f109: unsafe extern fn() -> (),
-  pub f110: unsafe extern fn() -> (),
-  pub f111: unsafe extern fn() -> (),
-  pub f112: unsafe extern fn() -> (),
-  pub f113: unsafe extern fn() -> (),
-  pub f114: unsafe extern fn() -> (),
-  pub f115: unsafe extern fn() -> (),
-  pub f116: unsafe extern fn() -> (),
-  pub f117: unsafe extern fn() -> (),
-  pub f118: unsafe extern fn() -> (),
-  pub f119: unsafe extern fn() -> (),
-  pub f120: unsafe extern fn() -> (),
-  pub f121: unsafe extern fn() -> (),
-  pub f122: unsafe extern fn() -> (),
-  pub f123: unsafe extern fn() -> (),
-  pub f124: unsafe extern fn() -> (),
-  pub f125: unsafe extern fn() -> (),
-  pub f126: unsafe extern fn() -> (),
-  pub f127: unsafe extern fn() -> (),
-  pub f128: unsafe extern fn() -> (),
-  pub f129: unsafe extern fn() -> (),
-  pub f130: unsafe extern fn() -> (),
-  pub f131: unsafe extern fn() -> (),
-  pub f132: unsafe extern fn() -> (),
-  pub f133: unsafe extern fn() -> (),
-  pub f134: unsafe extern fn() -> (),
-  pub f135: unsafe extern fn() -> (),
-  pub f136: unsafe extern fn() -> (),
-  pub f137: unsafe extern fn() -> (),
-  pub f138: unsafe extern fn() -> (),
-  pub f139: unsafe extern fn() -> (),
-  pub f140: unsafe extern fn() -> (),
-  pub f141: unsafe extern fn() -> (),
-  pub f142: unsafe extern fn() -> (),
-  pub f143: unsafe extern fn() -> (),
-  pub f144: unsafe extern fn() -> (),
-  pub f145: unsafe extern fn() -> (),
-  pub f146: unsafe extern fn() -> (),
-  pub f147: unsafe extern fn() -> (),
-  pub f148: unsafe extern fn() -> (),
-  pub f149: unsafe extern fn() -> (),
-  pub f150: unsafe extern fn() -> (),
-  pub f151: unsafe extern fn() -> (),
-  pub f152: unsafe extern fn() -> (),
-  pub f153: unsafe extern fn() -> (),
-  pub f154: unsafe extern fn() -> (),
-  pub f155: unsafe extern fn() -> (),
-  pub f156: unsafe extern fn() -> (),
-  pub f157: unsafe extern fn() -> (),
+  pub precache_model: unsafe extern fn(name: *const c_char) -> c_int,
+  pub precache_sound: unsafe extern fn(name: *const c_char) -> c_int,
+  pub set_model: unsafe extern fn(
+    entity: *mut Edict,
+    model: *const c_char,
+  ) -> (),
+  pub model_index: unsafe extern fn(model: *const c_char) -> c_int,
+  pub model_frames: unsafe extern fn(model_index: c_int) -> c_int,
+  pub set_size: unsafe extern fn(
+    entity: *mut Edict,
+    min: *const c_float,
+    max: *const c_float,
+  ) -> (),
+  pub change_level: unsafe extern fn(
+    map: *const c_char,
+    landmark: *const c_char,
+  ) -> (),
+  pub get_spawn_parms: unsafe extern fn(entity: *mut Edict) -> (),
+  pub save_spawn_parms: unsafe extern fn(entity: *mut Edict) -> (),
+  pub vec_to_yaw: unsafe extern fn(vector: *const c_float) -> c_float,
+  pub vec_to_angles: unsafe extern fn(
+    vector_in: *const c_float,
+    vector_out: *mut c_float,
+  ) -> (),
+  pub move_to_origin: unsafe extern fn(
+    entity: *mut Edict,
+    goal: *const c_float,
+    dist: c_float,
+    move_type: c_int,
+  ) -> (),
+  pub change_yaw: unsafe extern fn(entity: *mut Edict) -> (),
+  pub change_pitch: unsafe extern fn(entity: *mut Edict) -> (),
+  pub find_entity_by_string: unsafe extern fn(
+    start_after: *mut Edict,
+    field: *const c_char,
+    value: *const c_char,
+  ) -> *mut Edict,
+  pub get_entity_illum: unsafe extern fn(entity: *mut Edict) -> c_int,
+  pub find_entity_in_sphere: unsafe extern fn(
+    start_after: *mut Edict,
+    origin: *const c_float,
+    radius: c_float,
+  ) -> *mut Edict,
+  pub find_client_in_pvs: unsafe extern fn(entity: *mut Edict) -> *mut Edict,
+  pub entities_in_pvs: unsafe extern fn(player: *mut Edict) -> *mut Edict,
+  pub make_vectors: unsafe extern fn(angles: *const c_float) -> (),
+  pub angle_vectors: unsafe extern fn(
+    angles: *const c_float,
+    forward: *mut c_float,
+    right: *mut c_float,
+    up: *mut c_float,
+  ) -> (),
+  pub create_entity: unsafe extern fn() -> *mut Edict,
+  pub remove_entity: unsafe extern fn(entity: *mut Edict) -> (),
+  pub create_named_entity: unsafe extern fn(classname: c_int) -> *mut Edict,
+  pub make_static: unsafe extern fn(entity: *mut Edict) -> (),
+  pub ent_is_on_floor: unsafe extern fn(entity: *mut Edict) -> c_int,
+  pub drop_to_floor: unsafe extern fn(entity: *mut Edict) -> c_int,
+  pub walk_move: unsafe extern fn(
+    entity: *mut Edict,
+    yaw: c_float,
+    dist: c_float,
+    mode: c_int,
+  ) -> c_int,
+  pub set_origin: unsafe extern fn(
+    entity: *mut Edict,
+    origin: *const c_float,
+  ) -> (),
+  pub emit_sound: unsafe extern fn(
+    entity: *mut Edict,
+    channel: c_int,
+    sample: *const c_char,
+    volume: c_float,
+    attenuation: c_float,
+    flags: c_int,
+    pitch: c_int,
+  ) -> (),
+  pub emit_ambient_sound: unsafe extern fn(
+    entity: *mut Edict,
+    origin: *mut c_float,
+    sample: *const c_char,
+    volume: c_float,
+    attenuation: c_float,
+    flags: c_int,
+    pitch: c_int,
+  ) -> (),
+  pub trace_line: unsafe extern fn(
+    start: *const c_float,
+    end: *const c_float,
+    no_monsters: c_int,
+    skip: *mut Edict,
+    trace: *mut TraceResult,
+  ) -> (),
+  pub trace_toss: unsafe extern fn(
+    entity: *mut Edict,
+    ignore: *mut Edict,
+    trace: *mut TraceResult,
+  ) -> (),
+  pub trace_monster_hull: unsafe extern fn(
+    entity: *mut Edict,
+    start: *const c_float,
+    end: *const c_float,
+    no_monsters: c_int,
+    skip: *mut Edict,
+    trace: *mut TraceResult,
+  ) -> c_int,
+  pub trace_hull: unsafe extern fn(
+    start: *const c_float,
+    end: *const c_float,
+    no_monsters: c_int,
+    hull_number: c_int,
+    skip: *mut Edict,
+    trace: *mut TraceResult,
+  ) -> (),
+  pub trace_model: unsafe extern fn(
+    start: *const c_float,
+    end: *const c_float,
+    hull_number: c_int,
+    entity: *mut Edict,
+    trace: *mut TraceResult,
+  ) -> (),
+  pub trace_texture: unsafe extern fn(
+    texture_entity: *mut Edict,
+    start: *const c_float,
+    end: *const c_float,
+  ) -> *const c_char,
+  pub trace_sphere: unsafe extern fn(
+    start: *const c_float,
+    end: *const c_float,
+    no_monsters: c_int,
+    radius: c_float,
+    skip: *mut Edict,
+    trace: *mut TraceResult,
+  ) -> (),
+  pub get_aim_vector: unsafe extern fn(
+    entity: *mut Edict,
+    speed: c_float,
+    aim: *mut c_float,
+  ) -> (),
+  pub server_command: unsafe extern fn(command: *const c_char) -> (),
+  pub server_execute: unsafe extern fn() -> (),
+  pub client_command: unsafe extern fn(
+    entity: *mut Edict,
+    format: *const c_char,
+    ...,
+  ) -> (),
+  pub particle_effect: unsafe extern fn(
+    origin: *const c_float,
+    direction: *const c_float,
+    color: c_float,
+    count: c_float,
+  ) -> (),
+  pub light_style: unsafe extern fn(style: c_int, value: *const c_char) -> (),
+  pub decal_index: unsafe extern fn(name: *const c_char) -> c_int,
+  pub point_contents: unsafe extern fn(point: *const c_float) -> c_int,
+  pub message_begin: unsafe extern fn(
+    dest: c_int,
+    msg_type: c_int,
+    origin: *const c_float,
+    entity: *mut Edict,
+  ) -> (),
+  pub message_end: unsafe extern fn() -> (),
+  pub write_byte: unsafe extern fn(value: c_int) -> (),
+  pub write_char: unsafe extern fn(value: c_int) -> (),
+  pub write_short: unsafe extern fn(value: c_int) -> (),
+  pub write_long: unsafe extern fn(value: c_int) -> (),
+  pub write_angle: unsafe extern fn(value: c_float) -> (),
+  pub write_coord: unsafe extern fn(value: c_float) -> (),
+  pub write_string: unsafe extern fn(value: *const c_char) -> (),
+  pub write_entity: unsafe extern fn(value: c_int) -> (),
+  pub cvar_register: unsafe extern fn(cvar: *mut CVar) -> (),
+  pub cvar_get_float: unsafe extern fn(name: *const c_char) -> c_float,
+  pub cvar_get_string: unsafe extern fn(name: *const c_char) -> *const c_char,
+  pub cvar_set_float: unsafe extern fn(
+    name: *const c_char,
+    value: c_float,
+  ) -> (),
+  pub cvar_set_string: unsafe extern fn(
+    name: *const c_char,
+    value: *const c_char,
+  ) -> (),
+  pub alert_message: unsafe extern fn(
+    alert_type: AlertType,
+    format: *const c_char,
+    ...,
+  ) -> (),
+  pub engine_fprintf: unsafe extern fn(
+    file: *mut c_void,
+    format: *const c_char,
+    ...,
+  ) -> (),
+  pub pv_alloc_ent_private_data: unsafe extern fn(
+    entity: *mut Edict,
+    size: c_int,
+  ) -> *mut c_void,
+  pub pv_ent_private_data: unsafe extern fn(entity: *mut Edict) -> *mut c_void,
+  pub free_ent_private_data: unsafe extern fn(entity: *mut Edict) -> (),
+  pub sz_from_index: unsafe extern fn(string: c_int) -> *const c_char,
+  pub alloc_string: unsafe extern fn(value: *const c_char) -> c_int,
+  pub get_vars_of_ent: unsafe extern fn(entity: *mut Edict) -> *mut EntVars,
+  pub p_entity_of_ent_offset: unsafe extern fn(offset: c_int) -> *mut Edict,
+  pub ent_offset_of_p_entity: unsafe extern fn(entity: *const Edict) -> c_int,
+  pub index_of_edict: unsafe extern fn(entity: *const Edict) -> c_int,
+  pub p_entity_of_ent_index: unsafe extern fn(index: c_int) -> *mut Edict,
+  pub find_entity_by_vars: unsafe extern fn(vars: *mut EntVars) -> *mut Edict,
+  pub get_model_ptr: unsafe extern fn(entity: *mut Edict) -> *mut c_void,
+  pub reg_user_msg: unsafe extern fn(name: *const c_char, size: c_int) -> c_int,
+  pub animation_automove: unsafe extern fn(
+    entity: *const Edict,
+    time: c_float,
+  ) -> (),
+  pub get_bone_position: unsafe extern fn(
+    entity: *const Edict,
+    bone: c_int,
+    origin: *mut c_float,
+    angles: *mut c_float,
+  ) -> (),
+  pub function_from_name: unsafe extern fn(name: *const c_char) -> c_uint,
+  pub name_for_function: unsafe extern fn(function: c_uint) -> *const c_char,
+  pub client_printf: unsafe extern fn(
+    entity: *mut Edict,
+    print_type: PrintType,
+    message: *const c_char,
+  ) -> (),
+  pub server_print: unsafe extern fn(message: *const c_char) -> (),
+  pub cmd_args: unsafe extern fn() -> *const c_char,
+  pub cmd_argv: unsafe extern fn(argc: c_int) -> *const c_char,
+  pub cmd_argc: unsafe extern fn() -> c_int,
+  pub get_attachment: unsafe extern fn(
+    entity: *const Edict,
+    attachment: c_int,
+    origin: *mut c_float,
+    angles: *mut c_float,
+  ) -> (),
+  pub crc32_init: unsafe extern fn(crc: *mut c_ulong) -> (),
+  pub crc32_process_buffer: unsafe extern fn(
+    crc: *mut c_ulong,
+    buffer: *mut c_void,
+    len: c_int,
+  ) -> (),
+  pub crc32_process_byte: unsafe extern fn(
+    crc: *mut c_ulong,
+    byte: c_byte,
+  ) -> (),
+  pub crc32_final: unsafe extern fn(crc: c_ulong) -> c_ulong,
+  pub random_long: unsafe extern fn(low: c_int, high: c_int) -> c_int,
+  pub random_float: unsafe extern fn(low: c_float, high: c_float) -> c_float,
+  pub set_view: unsafe extern fn(
+    client: *const Edict,
+    view_entity: *const Edict,
+  ) -> (),
+  pub time: unsafe extern fn() -> c_float,
+  pub crosshair_angle: unsafe extern fn(
+    client: *const Edict,
+    pitch: c_float,
+    yaw: c_float,
+  ) -> (),
+  pub load_file_for_me: unsafe extern fn(
+    filename: *const c_char,
+    length: *mut c_int,
+  ) -> *mut c_byte,
+  pub free_file: unsafe extern fn(buffer: *mut c_void) -> (),
+  pub end_section: unsafe extern fn(section_name: *const c_char) -> (),
+  pub compare_file_time: unsafe extern fn(
+    filename1: *const c_char,
+    filename2: *const c_char,
+    compare: *mut c_int,
+  ) -> c_int,
+  pub get_game_dir: unsafe extern fn(game_dir: *mut c_char) -> (),
+  pub cvar_register_variable: unsafe extern fn(cvar: *mut CVar) -> (),
+  pub fade_client_volume: unsafe extern fn(
+    entity: *const Edict,
+    fade_percent: c_int,
+    fade_out_seconds: c_int,
+    hold_time: c_int,
+    fade_in_seconds: c_int,
+  ) -> (),
+  pub set_client_maxspeed: unsafe extern fn(
+    entity: *const Edict,
+    speed: c_float,
+  ) -> (),
+  pub create_fake_client: unsafe extern fn(name: *const c_char) -> *mut Edict,
+  pub run_player_move: unsafe extern fn(
+    fake_client: *mut Edict,
+    view_angles: *const c_float,
+    forward_move: c_float,
+    side_move: c_float,
+    up_move: c_float,
+    buttons: c_ushort,
+    impulse: c_byte,
+    msec: c_byte,
+  ) -> (),
+  pub number_of_entities: unsafe extern fn() -> c_int,
+  pub get_info_key_buffer: unsafe extern fn(entity: *mut Edict) -> *mut c_char,
+  pub info_key_value: unsafe extern fn(
+    info_buffer: *mut c_char,
+    key: *const c_char,
+  ) -> *mut c_char,
+  pub set_key_value: unsafe extern fn(
+    info_buffer: *mut c_char,
+    key: *const c_char,
+    value: *const c_char,
+  ) -> (),
+  pub set_client_key_value: unsafe extern fn(
+    client_index: c_int,
+    info_buffer: *mut c_char,
+    key: *const c_char,
+    value: *const c_char,
+  ) -> (),
+  pub is_map_valid: unsafe extern fn(map: *const c_char) -> c_int,
+  pub static_decal: unsafe extern fn(
+    origin: *const c_float,
+    decal_index: c_int,
+    entity_index: c_int,
+    model_index: c_int,
+  ) -> (),
+  pub precache_generic: unsafe extern fn(name: *const c_char) -> c_int,
+  pub get_player_user_id: unsafe extern fn(entity: *mut Edict) -> c_int,
+  pub build_sound_msg: unsafe extern fn(
+    entity: *mut Edict,
+    channel: c_int,
+    sample: *const c_char,
+    volume: c_float,
+    attenuation: c_float,
+    flags: c_int,
+    pitch: c_int,
+    dest: c_int,
+    msg_type: c_int,
+    origin: *const c_float,
+    msg_entity: *mut Edict,
+  ) -> (),
+  pub is_dedicated_server: unsafe extern fn() -> c_int,
+  pub cvar_get_pointer: unsafe extern fn(name: *const c_char) -> *mut CVar,
+  pub get_player_won_id: unsafe extern fn(entity: *mut Edict) -> c_uint,
+  pub info_remove_key: unsafe extern fn(
+    info_buffer: *mut c_char,
+    key: *const c_char,
+  ) -> (),
+  pub get_physics_key_value: unsafe extern fn(
+    client: *const Edict,
+    key: *const c_char,
+  ) -> *const c_char,
+  pub set_physics_key_value: unsafe extern fn(
+    client: *const Edict,
+    key: *const c_char,
+    value: *const c_char,
+  ) -> (),
+  pub get_physics_info_string: unsafe extern fn(
+    client: *const Edict,
+  ) -> *const c_char,
+  pub precache_event: unsafe extern fn(
+    event_type: c_int,
+    name: *const c_char,
+  ) -> c_ushort,
+  pub playback_event: unsafe extern fn(
+    flags: c_int,
+    invoker: *const Edict,
+    event_index: c_ushort,
+    delay: c_float,
+    origin: *mut c_float,
+    angles: *mut c_float,
+    fparam1: c_float,
+    fparam2: c_float,
+    iparam1: c_int,
+    iparam2: c_int,
+    bparam1: c_int,
+    bparam2: c_int,
+  ) -> (),
+  pub set_fat_pvs: unsafe extern fn(origin: *mut c_float) -> *mut c_byte,
+  pub set_fat_pas: unsafe extern fn(origin: *mut c_float) -> *mut c_byte,
+  pub check_visibility: unsafe extern fn(
+    entity: *const Edict,
+    set: *mut c_byte,
+  ) -> c_int,
+  pub delta_set_field: unsafe extern fn(
+    fields: *mut Delta,
+    field_name: *const c_char,
+  ) -> (),
+  pub delta_unset_field: unsafe extern fn(
+    fields: *mut Delta,
+    field_name: *const c_char,
+  ) -> (),
+  pub delta_add_encoder: unsafe extern fn(
+    name: *const c_char,
+    conditional_encode: unsafe extern fn(
+      fields: *mut Delta,
+      from: *const c_byte,
+      to: *const c_byte,
+    ) -> (),
+  ) -> (),
+  pub get_current_player: unsafe extern fn() -> c_int,
+  pub can_skip_player: unsafe extern fn(player: *const Edict) -> c_int,
+  pub delta_find_field: unsafe extern fn(
+    fields: *mut Delta,
+    field_name: *const c_char,
+  ) -> c_int,
+  pub delta_set_field_by_index: unsafe extern fn(
+    fields: *mut Delta,
+    field_number: c_int,
+  ) -> (),
+  pub delta_unset_field_by_index: unsafe extern fn(
+    fields: *mut Delta,
+    field_number: c_int,
+  ) -> (),
+  pub set_group_mask: unsafe extern fn(mask: c_int, op: c_int) -> (),
+  pub create_instanced_baseline: unsafe extern fn(
+    classname: c_int,
+    baseline: *mut EntityState,
+  ) -> c_int,
+  pub cvar_direct_set: unsafe extern fn(
+    cvar: *mut CVar,
+    value: *const c_char,
+  ) -> (),
+  pub force_unmodified: unsafe extern fn(
+    force_type: ForceType,
+    min: *mut c_float,
+    max: *mut c_float,
+    filename: *const c_char,
+  ) -> (),
+  pub get_player_stats: unsafe extern fn(
+    client: *const Edict,
+    ping: *mut c_int,
+    packet_loss: *mut c_int,
+  ) -> (),
+  pub add_server_command: unsafe extern fn(
+    cmd_name: *const c_char,
+    function: unsafe extern fn() -> (),
+  ) -> (),
+  pub voice_get_client_listening: unsafe extern fn(
+    receiver: c_int,
+    sender: c_int,
+  ) -> c_int,
+  pub voice_set_client_listening: unsafe extern fn(
+    receiver: c_int,
+    sender: c_int,
+    listen: c_int,
+  ) -> c_int,
+  pub get_player_auth_id: unsafe extern fn(entity: *mut Edict) -> *const c_char,
+  pub sequence_get: unsafe extern fn(
+    filename: *const c_char,
+    entry_name: *const c_char,
+  ) -> *mut c_void,
+  pub sequence_pick_sentence: unsafe extern fn(
+    group_name: *const c_char,
+    pick_method: c_int,
+    picked: *mut c_int,
+  ) -> *mut c_void,
+  pub get_file_size: unsafe extern fn(filename: *const c_char) -> c_int,
+  pub get_approx_wave_play_len: unsafe extern fn(
+    filepath: *const c_char,
+  ) -> c_uint,
+  pub is_career_match: unsafe extern fn() -> c_int,
+  pub get_localized_string_length: unsafe extern fn(
+    label: *const c_char,
+  ) -> c_int,
+  pub register_tutor_message_shown: unsafe extern fn(message_id: c_int) -> (),
+  pub get_times_tutor_message_shown: unsafe extern fn(
+    message_id: c_int,
+  ) -> c_int,
+  pub process_tutor_message_decay_buffer: unsafe extern fn(
+    buffer: *mut c_int,
+    buffer_length: c_int,
+  ) -> (),
+  pub construct_tutor_message_decay_buffer: unsafe extern fn(
+    buffer: *mut c_int,
+    buffer_length: c_int,
+  ) -> (),
+  pub reset_tutor_message_decay_data: unsafe extern fn() -> (),
+  pub query_client_cvar_value: unsafe extern fn(
+    player: *const Edict,
+    cvar_name: *const c_char,
+  ) -> (),
+  pub query_client_cvar_value2: unsafe extern fn(
+    player: *const Edict,
+    cvar_name: *const c_char,
+    request_id: c_int,
+  ) -> (),
+  pub check_parm: unsafe extern fn(
+    token: *const c_char,
+    next: *mut *mut c_char,
+  ) -> c_int,
 }
 
 #[repr(C)]
 pub struct DLLFunctions {
   pub game_init: unsafe extern fn() -> (),
-  pub f1: unsafe extern fn() -> (),
-  pub f2: unsafe extern fn() -> (),
-  pub f3: unsafe extern fn() -> (),
-  pub f4: unsafe extern fn() -> (),
-  pub f5: unsafe extern fn() -> (),
-  pub f6: unsafe extern fn() -> (),
-  pub f7: unsafe extern fn() -> (),
-  pub f8: unsafe extern fn() -> (),
-  pub f9: unsafe extern fn() -> (),
-  pub f10: unsafe extern fn() -> (),
-  pub f11: unsafe extern fn() -> (),
-  pub f12: unsafe extern fn() -> (),
-  pub f13: unsafe extern fn() -> (),
-  pub f14: unsafe extern fn() -> (),
+  pub spawn: unsafe extern fn(entity: *mut Edict) -> c_int,
+  pub think: unsafe extern fn(entity: *mut Edict) -> (),
+  pub use_: unsafe extern fn(used: *mut Edict, other: *mut Edict) -> (),
+  pub touch: unsafe extern fn(touched: *mut Edict, other: *mut Edict) -> (),
+  pub blocked: unsafe extern fn(blocked: *mut Edict, other: *mut Edict) -> (),
+  pub key_value: unsafe extern fn(
+    entity: *mut Edict,
+    data: *mut KeyValueData,
+  ) -> (),
+  pub save: unsafe extern fn(
+    entity: *mut Edict,
+    save_data: *mut SaveRestoreData,
+  ) -> (),
+  pub restore: unsafe extern fn(
+    entity: *mut Edict,
+    save_data: *mut SaveRestoreData,
+    global_entity: c_int,
+  ) -> c_int,
+  pub set_abs_box: unsafe extern fn(entity: *mut Edict) -> (),
+  pub save_write_fields: unsafe extern fn(
+    save_data: *mut SaveRestoreData,
+    name: *const c_char,
+    base_data: *mut c_void,
+    fields: *mut TypeDescription,
+    field_count: c_int,
+  ) -> (),
+  pub save_read_fields: unsafe extern fn(
+    save_data: *mut SaveRestoreData,
+    name: *const c_char,
+    base_data: *mut c_void,
+    fields: *mut TypeDescription,
+    field_count: c_int,
+  ) -> (),
+  pub save_global_state: unsafe extern fn(
+    save_data: *mut SaveRestoreData,
+  ) -> (),
+  pub restore_global_state: unsafe extern fn(
+    save_data: *mut SaveRestoreData,
+  ) -> (),
+  pub reset_global_state: unsafe extern fn() -> (),
   pub client_connect: unsafe extern fn(
     entity: *mut Edict,
     name: *const c_char,
@@ -491,36 +898,87 @@ pub struct DLLFunctions {
   pub server_activate: unsafe extern fn(
     edict_list: *mut Edict,
     edict_count: c_int,
-    max_clients: c_int
+    max_clients: c_int,
   ) -> (),
   pub server_deactivate: unsafe extern fn() -> (),
   pub player_pre_think: unsafe extern fn(entity: *mut Edict) -> (),
   pub player_post_think: unsafe extern fn(entity: *mut Edict) -> (),
   pub start_frame: unsafe extern fn() -> (),
-  pub f26: unsafe extern fn() -> (),
-  pub f27: unsafe extern fn() -> (),
-  pub f28: unsafe extern fn() -> (),
-  pub f29: unsafe extern fn() -> (),
-  pub f30: unsafe extern fn() -> (),
-  pub f31: unsafe extern fn() -> (),
-  pub f32: unsafe extern fn() -> (),
-  pub f33: unsafe extern fn() -> (),
-  pub f34: unsafe extern fn() -> (),
-  pub f35: unsafe extern fn() -> (),
-  pub f36: unsafe extern fn() -> (),
-  pub f37: unsafe extern fn() -> (),
-  pub f38: unsafe extern fn() -> (),
-  pub f39: unsafe extern fn() -> (),
-  pub f40: unsafe extern fn() -> (),
-  pub f41: unsafe extern fn() -> (),
-  pub f42: unsafe extern fn() -> (),
-  pub f43: unsafe extern fn() -> (),
-  pub f44: unsafe extern fn() -> (),
-  pub f45: unsafe extern fn() -> (),
-  pub f46: unsafe extern fn() -> (),
-  pub f47: unsafe extern fn() -> (),
-  pub f48: unsafe extern fn() -> (),
-  pub f49: unsafe extern fn() -> (),
+  pub parms_new_level: unsafe extern fn() -> (),
+  pub parms_change_level: unsafe extern fn() -> (),
+  pub get_game_description: unsafe extern fn() -> *const c_char,
+  pub player_customization: unsafe extern fn(
+    entity: *mut Edict,
+    customization: *mut Customization,
+  ) -> (),
+  pub spectator_connect: unsafe extern fn(entity: *mut Edict) -> (),
+  pub spectator_disconnect: unsafe extern fn(entity: *mut Edict) -> (),
+  pub spectator_think: unsafe extern fn(entity: *mut Edict) -> (),
+  pub sys_error: unsafe extern fn(error: *const c_char) -> (),
+  pub pm_move: unsafe extern fn(
+    player_move: *mut PlayerMove,
+    server: c_int,
+  ) -> (),
+  pub pm_init: unsafe extern fn(player_move: *mut PlayerMove) -> (),
+  pub pm_find_texture_type: unsafe extern fn(name: *mut c_char) -> c_char,
+  pub setup_visibility: unsafe extern fn(
+    view_entity: *mut Edict,
+    client: *mut Edict,
+    pvs: *mut *mut c_byte,
+    pas: *mut *mut c_byte,
+  ) -> (),
+  pub update_client_data: unsafe extern fn(
+    entity: *const Edict,
+    send_weapons: c_int,
+    client_data: *mut ClientData,
+  ) -> (),
+  pub add_to_full_pack: unsafe extern fn(
+    state: *mut EntityState,
+    index: c_int,
+    entity: *mut Edict,
+    host: *mut Edict,
+    host_flags: c_int,
+    player: c_int,
+    set: *mut c_byte,
+  ) -> c_int,
+  pub create_baseline: unsafe extern fn(
+    player: c_int,
+    index: c_int,
+    baseline: *mut EntityState,
+    entity: *mut Edict,
+    player_model_index: c_int,
+    player_mins: *mut c_float,
+    player_maxs: *mut c_float,
+  ) -> (),
+  pub register_encoders: unsafe extern fn() -> (),
+  pub get_weapon_data: unsafe extern fn(
+    player: *mut Edict,
+    info: *mut WeaponData,
+  ) -> c_int,
+  pub cmd_start: unsafe extern fn(
+    player: *const Edict,
+    cmd: *const UserCmd,
+    random_seed: c_uint,
+  ) -> (),
+  pub cmd_end: unsafe extern fn(player: *const Edict) -> (),
+  pub connectionless_packet: unsafe extern fn(
+    from: *const NetAdr,
+    args: *const c_char,
+    response_buffer: *mut c_char,
+    response_buffer_size: *mut c_int,
+  ) -> c_int,
+  pub get_hull_bounds: unsafe extern fn(
+    hull_number: c_int,
+    mins: *mut c_float,
+    maxs: *mut c_float,
+  ) -> c_int,
+  pub create_instanced_baselines: unsafe extern fn() -> (),
+  pub inconsistent_file: unsafe extern fn(
+    player: *const Edict,
+    filename: *const c_char,
+    disconnect_message: *mut c_char,
+  ) -> c_int,
+  pub allow_lag_compensation: unsafe extern fn() -> c_int,
 }
 
 #[repr(C)]
@@ -543,8 +1001,8 @@ pub struct NewDLLFunctions {
 
 #[repr(C)]
 pub struct GameDLLFunctions {
-  dllapi_table: *const DLLFunctions,
-  newapi_table: *const NewDLLFunctions,
+  pub dllapi_table: *const DLLFunctions,
+  pub newapi_table: *const NewDLLFunctions,
 }
 
 #[repr(C)]
@@ -590,7 +1048,37 @@ pub struct MetaFunctions {
 
 #[repr(C)]
 pub struct GlobalVars {
-
+  pub time: c_float,
+  pub frametime: c_float,
+  pub force_retouch: c_float,
+  pub mapname: EngineStringHandle,
+  pub startspot: EngineStringHandle,
+  pub deathmatch: c_float,
+  pub coop: c_float,
+  pub teamplay: c_float,
+  pub serverflags: c_float,
+  pub found_secrets: c_float,
+  pub v_forward: EngineVector3,
+  pub v_up: EngineVector3,
+  pub v_right: EngineVector3,
+  pub trace_allsolid: c_float,
+  pub trace_startsolid: c_float,
+  pub trace_fraction: c_float,
+  pub trace_endpos: EngineVector3,
+  pub trace_plane_normal: EngineVector3,
+  pub trace_plane_dist: c_float,
+  pub trace_ent: *mut Edict,
+  pub trace_inopen: c_float,
+  pub trace_inwater: c_float,
+  pub trace_hitgroup: c_int,
+  pub trace_flags: c_int,
+  pub msg_entity: c_int,
+  pub cd_audio_track: c_int,
+  pub max_clients: c_int,
+  pub max_entities: c_int,
+  pub string_base: *const c_char,
+  pub save_data: *mut c_void,
+  pub landmark_offset: EngineVector3,
 }
 
 #[repr(C)]
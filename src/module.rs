@@ -1,15 +1,51 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use crate::plugin_sys::PluginSystem;
+use std::os::raw::c_int;
+use std::time::Duration;
+use crate::config::{Config, load_private_data, load_virtual_functions};
+use crate::plugin_sys::{
+  PluginSystem,
+  events,
+  commands,
+  console,
+  timers,
+  tasks,
+  entity_data,
+  entity_classes,
+  virtual_hooks,
+};
 use crate::global_state::GlobalState;
+use crate::meta_ffi::types::{Edict, KeyValueData, MetaResult};
+use crate::lua_helpers::print_lua_error;
+use rlua::FromLua;
 use crate::ffi_wrapper::{
   MetaContext,
+  ConnectResponse,
   get_meta_plugin_path,
+  add_server_command,
+  server_print,
+  command_arg_string,
+  engine_time,
+  game_dir,
+  log_error,
+  log_message,
+  player::PlayerHandle,
   hl_lua_bridge::EntityHandle,
+  dll_hooks::{DllHooks, HookResult},
+  virtual_hooks::{self as vtable_hooks, VirtualCall, VirtualValue},
+  private_data,
 };
 
+// Hooks run inside each other all the time, a plugin that spawns an entity
+// runs the game's spawn hook for example. So the context is only ever shared.
+// Hooks only need the Lua state and the global state. The plugin system is
+// borrowed mutably just to load and unload plugins, which no hook does.
 struct ModuleContext {
   state: Arc<Mutex<GlobalState>>,
-  plugin_system: PluginSystem,
+  lua: Rc<rlua::Lua>,
+  plugin_system: RefCell<PluginSystem>,
 }
 
 impl ModuleContext {
@@ -17,53 +53,282 @@ impl ModuleContext {
     let state = Arc::new(Mutex::new(GlobalState::new()));
     
     let path = get_meta_plugin_path();
-    let mut pl_dir = path.parent().unwrap().parent().unwrap().to_path_buf();
-    pl_dir.push("Plugins");
+    let base_dir = path.parent().unwrap().parent().unwrap().to_path_buf();
+    let pl_dir = base_dir.join("Plugins");
+
+    let config = match Config::load(&base_dir.join("Luna.toml")) {
+      Ok(config) => config,
+      Err(e) => {
+        log_error(format!("Couldn't load Luna.toml: {}", e));
+        Config::default()
+      }
+    };
+
+    let functions_path = base_dir.join("VirtualHooks").join(format!("{}.toml", game_dir()));
+    let functions = load_virtual_functions(&functions_path)
+      .map_err(|e| e.to_string())
+      .and_then(vtable_hooks::set_functions);
+    if let Err(e) = functions {
+      log_error(format!("Couldn't load {}: {}", functions_path.display(), e));
+    }
 
-    let plugin_sys = PluginSystem::mount(pl_dir, state.clone());
+    let fields_path = base_dir.join("PrivateData").join(format!("{}.toml", game_dir()));
+    match load_private_data(&fields_path) {
+      Ok(fields) => private_data::set_fields(fields),
+      Err(e) => log_error(format!("Couldn't load {}: {}", fields_path.display(), e)),
+    }
+
+    add_server_command("luna");
+
+    let mut plugin_sys = PluginSystem::mount(pl_dir, state.clone());
     plugin_sys.run_plugins();
 
+    if config.hot_reload.enabled {
+      let interval = Duration::from_millis(
+        (config.hot_reload.poll_interval.max(0.0) * 1000.0) as u64
+      );
+      plugin_sys.enable_hot_reload(interval);
+      log_message("Hot reloading of plugins is enabled.");
+    }
+
     ModuleContext {
       state: state,
-      plugin_system: plugin_sys,
+      lua: plugin_sys.lua(),
+      plugin_system: RefCell::new(plugin_sys),
     }
   }
+
+  fn interact(&self, callback: &str, entity: *mut Edict, other: *mut Edict) -> HookResult<()> {
+    let (edict, other) = match unsafe { (entity.as_ref(), other.as_ref()) } {
+      (Some(edict), other) => (edict, other),
+      _ => return (MetaResult::Ignored, None),
+    };
+
+    self.lua.context(|ctx: rlua::Context| {
+      match entity_classes::interact(&ctx, &self.state, callback, edict, other) {
+        true => (MetaResult::Supercede, None),
+        false => (MetaResult::Ignored, None),
+      }
+    })
+  }
+
+  fn process_pending_reloads(&self) {
+    // Plugins are being loaded or unloaded right now, try again next frame
+    let mut plugin_system = match self.plugin_system.try_borrow_mut() {
+      Ok(plugin_system) => plugin_system,
+      Err(_) => return,
+    };
+
+    let mut pending = std::mem::take(
+      &mut self.state.lock().unwrap().pending_reloads
+    );
+
+    for identifier in plugin_system.changed_plugins() {
+      if !pending.contains(&identifier) {
+        log_message(format!("Detected changes in \"{}\", reloading.", identifier));
+        pending.push(identifier);
+      }
+    }
+
+    // Reloading a plugin reloads its dependents too, no need to do it twice
+    let mut reloaded = HashSet::new();
+    for identifier in pending {
+      if reloaded.contains(&identifier) {
+        continue;
+      }
+
+      match plugin_system.reload_plugin(&identifier) {
+        Ok(identifiers) => reloaded.extend(identifiers),
+        Err(e) => log_error(format!("Couldn't reload \"{}\": {}", identifier, e)),
+      }
+    }
+  }
+}
+
+// Lua entity classes, see `entity_classes`, and virtual hooks
+impl DllHooks for ModuleContext {
+  // Virtual hooks added before the map started can be set up now
+  fn server_activate(&self, _: *mut Edict, _: c_int, _: c_int) -> HookResult<()> {
+    virtual_hooks::hook_pending(&self.state);
+    (MetaResult::Ignored, None)
+  }
+
+  fn spawn(&self, entity: *mut Edict) -> HookResult<c_int> {
+    let edict = match unsafe { entity.as_ref() } {
+      Some(edict) => edict,
+      None => return (MetaResult::Ignored, None),
+    };
+
+    self.lua.context(|ctx: rlua::Context| {
+      match entity_classes::spawn(&ctx, &self.state, edict) {
+        Some(result) => (MetaResult::Supercede, Some(result)),
+        None => (MetaResult::Ignored, None),
+      }
+    })
+  }
+
+  fn key_value(&self, entity: *mut Edict, data: *mut KeyValueData) -> HookResult<()> {
+    let (edict, data) = match unsafe { (entity.as_ref(), data.as_mut()) } {
+      (Some(edict), Some(data)) => (edict, data),
+      _ => return (MetaResult::Ignored, None),
+    };
+
+    self.lua.context(|ctx: rlua::Context| {
+      match entity_classes::key_value(&ctx, &self.state, edict, data) {
+        true => (MetaResult::Supercede, None),
+        false => (MetaResult::Ignored, None),
+      }
+    })
+  }
+
+  fn think(&self, entity: *mut Edict) -> HookResult<()> {
+    let edict = match unsafe { entity.as_ref() } {
+      Some(edict) => edict,
+      None => return (MetaResult::Ignored, None),
+    };
+
+    self.lua.context(|ctx: rlua::Context| {
+      match entity_classes::think(&ctx, &self.state, edict) {
+        true => (MetaResult::Supercede, None),
+        false => (MetaResult::Ignored, None),
+      }
+    })
+  }
+
+  fn touch(&self, touched: *mut Edict, other: *mut Edict) -> HookResult<()> {
+    self.interact("Touch", touched, other)
+  }
+
+  fn use_(&self, used: *mut Edict, other: *mut Edict) -> HookResult<()> {
+    self.interact("Use", used, other)
+  }
+
+  fn blocked(&self, blocked: *mut Edict, other: *mut Edict) -> HookResult<()> {
+    self.interact("Blocked", blocked, other)
+  }
 }
 
 impl MetaContext for ModuleContext {
-  fn client_connect(&mut self, entity: EntityHandle) {
-    self.plugin_system.lua().context(|ctx: rlua::Context| {
-      let state = self.state.lock().unwrap();
-      let _ = state.listeners.emit(&ctx, "ClientConnect", entity);
-    });
+  fn client_connect(&self, player: PlayerHandle, name: &str, address: &str) -> ConnectResponse {
+    self.lua.context(|ctx: rlua::Context| {
+      // A listener rejects the connection by returning the reason. Every
+      // listener is called, listeners that fail are skipped and the first
+      // reason is the one the player gets to see.
+      let params = (player, name.to_string(), address.to_string());
+      let responses: Vec<rlua::Value> =
+        match events::emit_collect(&ctx, &self.state, "ClientConnect", params) {
+          Ok(responses) => responses,
+          Err(e) => {
+            print_lua_error(&e);
+            return ConnectResponse::Accept(MetaResult::Ignored);
+          }
+        };
+
+      let mut result = MetaResult::Ignored;
+      let mut rejection = None;
+      for response in responses {
+        match response {
+          rlua::Value::String(reason) => {
+            if rejection.is_none() {
+              rejection = Some(reason.to_str().unwrap_or("").to_string());
+            }
+          }
+          other => match MetaResult::from_lua(other, ctx) {
+            Ok(other) => result = result.max(other),
+            Err(e) => print_lua_error(&e),
+          },
+        }
+      }
+
+      match rejection {
+        Some(reason) => ConnectResponse::Reject(reason),
+        None => ConnectResponse::Accept(result),
+      }
+    })
   }
 
-  fn client_put_in_server(&mut self, entity: EntityHandle) {
-    self.plugin_system.lua().context(|ctx: rlua::Context| {
-      let state = self.state.lock().unwrap();
-      let _ = state.listeners.emit(&ctx, "PreClientPutInServer", entity);
-    });
+  fn client_put_in_server(&self, player: PlayerHandle) -> MetaResult {
+    self.lua.context(|ctx: rlua::Context| {
+      events::emit_hook(&ctx, &self.state, "PreClientPutInServer", player)
+        .unwrap_or(MetaResult::Ignored)
+    })
+  }
+
+  fn client_disconnect(&self, player: PlayerHandle) -> MetaResult {
+    self.lua.context(|ctx: rlua::Context| {
+      events::emit_hook(&ctx, &self.state, "ClientDisconnect", player)
+        .unwrap_or(MetaResult::Ignored)
+    })
   }
 
-  fn client_disconnect(&mut self, entity: EntityHandle) {
-    self.plugin_system.lua().context(|ctx: rlua::Context| {
-      let state = self.state.lock().unwrap();
-      let _ = state.listeners.emit(&ctx, "ClientDisconnect", entity);
+  fn client_put_in_server_post(&self, player: PlayerHandle) -> MetaResult {
+    self.lua.context(|ctx: rlua::Context| {
+      events::emit_hook(&ctx, &self.state, "ClientPutInServer", player)
+        .unwrap_or(MetaResult::Ignored)
+    })
+  }
+
+  fn client_disconnect_post(&self, player: PlayerHandle) -> MetaResult {
+    self.lua.context(|ctx: rlua::Context| {
+      events::emit_hook(&ctx, &self.state, "ClientDisconnected", player)
+        .unwrap_or(MetaResult::Ignored)
+    })
+  }
+
+  fn client_command(&self, player: PlayerHandle, args: &[String]) -> MetaResult {
+    let (name, args) = match args.split_first() {
+      Some(split) => split,
+      None => return MetaResult::Ignored,
+    };
+
+    self.lua.context(|ctx: rlua::Context| {
+      let arg_table = ctx.create_sequence_from(args.iter().cloned()).unwrap();
+      let params = (player, name.clone(), arg_table, command_arg_string());
+      events::emit_hook(&ctx, &self.state, "ClientCommand", params)
+        .unwrap_or(MetaResult::Ignored)
+    })
+  }
+
+  fn start_frame(&self) {
+    self.process_pending_reloads();
+
+    let now = f64::from(engine_time());
+    self.lua.context(|ctx: rlua::Context| {
+      timers::run_timers(&ctx, &self.state, now);
+      tasks::run_tasks(&ctx, &self.state, now);
     });
   }
 
-  fn client_put_in_server_post(&mut self, entity: EntityHandle) {
-    self.plugin_system.lua().context(|ctx: rlua::Context| {
-      let state = self.state.lock().unwrap();
-      let _ = state.listeners.emit(&ctx, "ClientPutInServer", entity);
+  fn server_command(&self, args: &[String]) {
+    if let Some("luna") = args.first().map(String::as_str) {
+      return match self.plugin_system.try_borrow_mut() {
+        Ok(mut plugin_system) => console::luna_command(&mut plugin_system, &args[1..]),
+        Err(_) => log_error("Plugins can't be managed while they are being loaded"),
+      };
+    }
+
+    let handled = self.lua.context(|ctx: rlua::Context| {
+      commands::dispatch(&ctx, &self.state, args)
     });
+
+    if let (false, Some(name)) = (handled, args.first()) {
+      server_print(format!("Unknown command: {}\n", name));
+    }
   }
 
-  fn client_disconnect_post(&mut self, entity: EntityHandle) {
-    self.plugin_system.lua().context(|ctx: rlua::Context| {
-      let state = self.state.lock().unwrap();
-      let _ = state.listeners.emit(&ctx, "ClientDisconnected", entity);
+  fn on_free_ent_private_data(&self, entity: EntityHandle) {
+    self.lua.context(|ctx: rlua::Context| {
+      if let Err(e) = entity_data::clear_entity(&ctx, &entity) {
+        print_lua_error(&e);
+      }
     });
+    self.state.lock().unwrap().entity_classes.remove_entity(&entity);
+  }
+
+  fn virtual_hook(&self, call: &VirtualCall, post: bool) -> HookResult<VirtualValue> {
+    self.lua.context(|ctx: rlua::Context| {
+      virtual_hooks::dispatch(&ctx, &self.state, call, post)
+    })
   }
 }
 
@@ -1,18 +1,30 @@
 mod luna_lib;
+mod dependency_graph;
+mod watcher;
 pub mod plugin;
+pub mod console;
 pub mod events;
-
-use std::collections::HashSet;
+pub mod commands;
+pub mod timers;
+pub mod tasks;
+pub mod entity_data;
+pub mod entity_classes;
+pub mod virtual_hooks;
+
+use std::collections::{BTreeMap, HashSet};
 use std::path::{PathBuf, Path};
 use std::error::Error;
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use std::io;
 use std::fs;
+use std::time::Duration;
 use crate::ffi_wrapper::{log_error, log_message};
 use crate::global_state::{GlobalState, GlobalStateUserData};
 use crate::lua_helpers;
 use self::plugin::Plugin;
-use self::luna_lib::{core, listeners};
+use self::watcher::PluginWatcher;
+use self::luna_lib::{core, meta, globals, math, players, private_data};
 
 
 pub fn get_identifier_from_path(dir: &Path) -> String {
@@ -26,73 +38,45 @@ pub fn get_identifier_from_path(dir: &Path) -> String {
   format!("{}/{}", namespace, ident)
 }
 
-fn load_plugin_from_dir(
-  base_dir: &Path,
-  dir: &Path,
-  identifier: &str,
-  visited: &mut HashSet<String>,
-  loaded: &mut Vec<Plugin>,
-) -> Result<(), Box<dyn Error>> {
-  if visited.contains(identifier) {
-    return Ok(());
-  }
-  
-  visited.insert(identifier.to_string());
-  
-  if !dir.exists() {
-    return Err(Box::new(io::Error::new(
-      io::ErrorKind::NotFound,
-      format!("Plugin directory {} doesn't exist.", dir.display()),
-    )));
-  }
-
-  let plugin = Plugin::load_plugin(dir, &identifier)?;
-  for dep in plugin.dependencies() {
-    let dep_ident: &String = dep.0;
-    let _dep_version = dep.1; // TODO: Check semver
-
-    let mut dep_dir = base_dir.to_path_buf();
-    dep_dir.extend(dep_ident.split('/'));
-    load_plugin_from_dir(base_dir, &dep_dir, dep_ident, visited, loaded)?;
-  } 
-
-  loaded.push(plugin);
-  Ok(())
-}
-
-fn load_plugins(dir: &Path) -> Vec<Plugin> {
-  let mut plugins = Vec::new();
-  let mut visited_plugins = HashSet::new();
+fn load_plugins(dir: &Path, errors: &mut BTreeMap<String, String>) -> Vec<Plugin> {
+  let mut plugins = BTreeMap::new();
 
   let ns_iter = match fs::read_dir(&dir) {
     Ok(iter) => iter,
     Err(err) => {
       // TODO: Add logging
       log_error(format!("Couldn't load from \"{}\": {}", dir.display(), err));
-      return plugins;
+      return Vec::new();
     },
   };
 
-  ns_iter
+  let mut plugin_dirs: Vec<PathBuf> = ns_iter
     .filter_map(Result::ok)
     .map(|entry| fs::read_dir(entry.path()))
     .filter_map(Result::ok)
     .flat_map(|x| x)
     .filter_map(Result::ok)
-    .for_each(|entry| {
-      let ident = get_identifier_from_path(&entry.path());
-      let load_result = load_plugin_from_dir(
-        &dir,
-        &entry.path(),
-        &ident,
-        &mut visited_plugins,
-        &mut plugins,
-      );
-
-      if let Err(e) = load_result {
+    .map(|entry| entry.path())
+    .collect();
+  plugin_dirs.sort();
+
+  for plugin_dir in plugin_dirs {
+    let ident = get_identifier_from_path(&plugin_dir);
+    match Plugin::load_plugin(&plugin_dir, &ident) {
+      Ok(plugin) => { plugins.insert(ident, plugin); }
+      Err(e) => {
         log_error(format!("Couldn't load \"{}\": {}", ident, e));
+        errors.insert(ident, e.to_string());
       }
-    });
+    }
+  }
+
+  let order = dependency_graph::resolve_load_order(&mut plugins, errors);
+  log_message(format!("Load order: {}", order.join(", ")));
+
+  let plugins: Vec<Plugin> = order.iter()
+    .filter_map(|ident| plugins.remove(ident))
+    .collect();
 
   log_message(format!("Loaded {} plugins.", plugins.len()));
 
@@ -131,7 +115,8 @@ fn init_luna_libs<'lua>(
   let lib_core: rlua::Table = ctx.create_table().unwrap();
   let lib_table: rlua::Table = ctx.create_table().unwrap();
   let lib_string: rlua::Table = ctx.create_table().unwrap();
-  let lib_listeners: rlua::Table = ctx.create_table().unwrap();
+  let lib_coroutine: rlua::Table = ctx.create_table().unwrap();
+  let lib_math: rlua::Table = ctx.create_table().unwrap();
 
   ////////// Re-map old functions to new names //////////
 
@@ -171,31 +156,49 @@ fn init_luna_libs<'lua>(
   lua_helpers::map_funcs(&orig_lib_string, &lib_string, &old, &new);
   replace_string_metatable(ctx, lib_string.clone());
 
+  // Coroutine
+  let orig_lib_coroutine: rlua::Table = globals.raw_get("coroutine").unwrap();
+  let old = [
+    "create", "isyieldable", "resume", "running", "status", "wrap", "yield"
+  ];
+  let new = [
+    "Create", "IsYieldable", "Resume", "Running", "Status", "Wrap", "Yield"
+  ];
+  lua_helpers::map_funcs(&orig_lib_coroutine, &lib_coroutine, &old, &new);
+
+  // Math
+  let orig_lib_math: rlua::Table = globals.raw_get("math").unwrap();
+  let old = [
+    "abs", "ceil", "floor", "fmod", "max", "min", "sqrt", "exp", "log",
+    "sin", "cos", "tan", "asin", "acos", "atan", "random", "pi", "huge"
+  ];
+  let new = [
+    "Abs", "Ceil", "Floor", "Mod", "Max", "Min", "Sqrt", "Exp", "Log",
+    "Sin", "Cos", "Tan", "ASin", "ACos", "ATan", "Random", "Pi", "Huge"
+  ];
+  lua_helpers::map_funcs(&orig_lib_math, &lib_math, &old, &new);
+
   ////////// New functions //////////
   
   // Core
   let print_to_console = ctx.create_function(core::print_to_console).unwrap();
   lib_core.raw_set("PrintToConsole", print_to_console).unwrap();
+  let reload_plugin = ctx.create_function(core::reload_plugin).unwrap();
+  lib_core.raw_set("ReloadPlugin", reload_plugin).unwrap();
 
-  // Listeners
-  let enum_values = [
-    "ClientConnect", "PreClientPutInServer", "ClientPutInServer", "ClientDisconnect", "ClientDisconnected",
-    "PluginsLoaded", "PluginsWillUnload", "PluginsUnload",
-  ];
-  let events_enum = ctx.create_table_from(
-    enum_values.iter().map(|&x| x).zip(enum_values.iter().map(|&x| x))
-  ).unwrap();
-
-  let add_listener = ctx.create_function(listeners::add_listener).unwrap();
-  let remove_listener = ctx.create_function(listeners::remove_listener).unwrap();
-  lib_listeners.raw_set("On", add_listener).unwrap();
-  lib_listeners.raw_set("Off", remove_listener).unwrap();
-  lib_listeners.raw_set("Events", events_enum).unwrap();
+  // Math
+  let vector = ctx.create_function(math::vector).unwrap();
+  lib_math.raw_set("Vector", vector).unwrap();
 
   libs.raw_set("Luna/Core", lib_core).unwrap();
   libs.raw_set("Luna/Table", lib_table).unwrap();
   libs.raw_set("Luna/String", lib_string).unwrap();
-  libs.raw_set("Luna/Listeners", lib_listeners).unwrap();
+  libs.raw_set("Luna/Coroutine", lib_coroutine).unwrap();
+  libs.raw_set("Luna/Math", lib_math).unwrap();
+  libs.raw_set("Luna/Meta", meta::create_lib(ctx)).unwrap();
+  libs.raw_set("Luna/Globals", globals::create_lib(ctx)).unwrap();
+  libs.raw_set("Luna/Players", players::create_lib(ctx)).unwrap();
+  libs.raw_set("Luna/PrivateData", private_data::create_lib(ctx)).unwrap();
 }
 
 fn init_plugin_libs<'lua>(
@@ -234,72 +237,424 @@ fn setup_lua_state(state: Arc<Mutex<GlobalState>>) -> rlua::Lua {
 
 
 pub struct PluginSystem {
+  directory: PathBuf,
   plugins: Vec<Plugin>,
-  lua: rlua::Lua,
+  // Why plugins failed to load or reload, by identifier
+  errors: BTreeMap<String, String>,
+  state: Arc<Mutex<GlobalState>>,
+  // Shared with the hooks, which need it while plugins are being loaded
+  lua: Rc<rlua::Lua>,
+  watcher: Option<PluginWatcher>,
 }
 
 impl PluginSystem {
   pub fn mount(directory: impl Into<PathBuf>, state: Arc<Mutex<GlobalState>>) -> Self {
     let directory = directory.into();
-    let plugins = load_plugins(&directory);
-    let lua = setup_lua_state(state);
+    let mut errors = BTreeMap::new();
+    let plugins = load_plugins(&directory, &mut errors);
+    let lua = setup_lua_state(state.clone());
     lua.context(|ctx| init_libs(&plugins, &ctx));
     
     PluginSystem {
+      directory: directory,
       plugins: plugins,
-      lua: lua,
+      errors: errors,
+      state: state,
+      lua: Rc::new(lua),
+      watcher: None,
     }
   }
 
-  pub fn run_plugins(&self) {
-    let mut visited = HashSet::new();
+  pub fn run_plugins(&mut self) {
+    let identifiers: Vec<String> = self.plugins.iter()
+      .map(|pl| pl.identifier().to_string())
+      .collect();
 
-    for pl in &self.plugins {
-      self.lua.context(|ctx: rlua::Context| {
-        Self::run_plugin(&pl, &ctx, &mut visited);
-      });
+    for ident in identifiers {
+      // Dropped when one of its dependencies failed
+      if self.plugin(&ident).is_none() {
+        continue;
+      }
+
+      if let Err(e) = self.start_plugin(&ident) {
+        log_error(format!("Couldn't load \"{}\": {}", ident, e));
+      }
+    }
+
+    self.lua.context(|ctx: rlua::Context| {
+      let _ = events::emit(&ctx, &self.state, "PluginsLoaded", ());
+    });
+  }
+
+  /// The Lua state the plugins run in. It lives as long as the plugin
+  /// system.
+  pub fn lua(&self) -> Rc<rlua::Lua> {
+    self.lua.clone()
+  }
+
+  /// Loaded plugins, in load order.
+  pub fn plugins(&self) -> &Vec<Plugin> {
+    &self.plugins
+  }
+
+  pub fn plugin(&self, identifier: &str) -> Option<&Plugin> {
+    self.plugins.iter().find(|pl| pl.identifier() == identifier)
+  }
+
+  pub fn errors(&self) -> &BTreeMap<String, String> {
+    &self.errors
+  }
+
+  /// Loaded plugins that depend on `identifier`, directly or optionally.
+  pub fn dependents(&self, identifier: &str) -> Vec<&Plugin> {
+    self.plugins.iter()
+      .filter(|pl| {
+        pl.dependencies().contains_key(identifier)
+          || pl.has_optional_dependency(identifier)
+      })
+      .collect()
+  }
+
+  /// Starts watching the files of every plugin for changes, see
+  /// `changed_plugins`.
+  pub fn enable_hot_reload(&mut self, poll_interval: Duration) {
+    let mut watcher = PluginWatcher::new(poll_interval);
+    for plugin in &self.plugins {
+      watcher.check(&self.plugin_files(plugin));
+    }
+
+    self.watcher = Some(watcher);
+  }
+
+  /// Returns the plugins whose files changed on disk since the last poll.
+  /// Always empty unless hot reloading is enabled.
+  pub fn changed_plugins(&mut self) -> Vec<String> {
+    let mut watcher = match self.watcher.take() {
+      Some(watcher) => watcher,
+      None => return Vec::new(),
+    };
+
+    let changed = match watcher.should_poll() {
+      true => self.plugins.iter()
+        .filter(|pl| watcher.check(&self.plugin_files(pl)))
+        .map(|pl| pl.identifier().to_string())
+        .collect(),
+      false => Vec::new(),
+    };
+
+    self.watcher = Some(watcher);
+    changed
+  }
+
+  // `Plugin.lua`, `Plugin.toml` and everything pulled in through `require`
+  fn plugin_files(&self, plugin: &Plugin) -> Vec<PathBuf> {
+    let mut files = vec![
+      plugin.main_source_path().to_path_buf(),
+      plugin.directory().join("Plugin.toml"),
+    ];
+
+    let state = self.state.lock().unwrap();
+    if let Some(required) = state.required_files.get(plugin.identifier()) {
+      files.extend(required.iter().cloned());
+    }
+
+    files
+  }
+
+  /// Reloads the plugin `identifier` together with every plugin that has a
+  /// hard dependency on it, in load order. Nothing is unloaded if any of the
+  /// new manifests or sources can't be loaded. A plugin whose `Plugin.lua`
+  /// fails when it runs stays unloaded along with its hard dependents.
+  /// Returns the identifiers of reloaded plugins.
+  pub fn reload_plugin(&mut self, identifier: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let affected = self.plugin_with_dependents(identifier)?;
+    let result = self.reload_plugins(&affected);
+    self.record_result(identifier, &result);
+    result.map(|_| affected)
+  }
+
+  /// Loads a plugin that isn't loaded yet, e.g. one that was unloaded
+  /// before. Its dependencies have to be loaded already.
+  pub fn load_plugin(&mut self, identifier: &str) -> Result<(), Box<dyn Error>> {
+    if self.plugin(identifier).is_some() {
+      return Err(Box::new(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("Plugin \"{}\" is already loaded.", identifier),
+      )));
+    }
+
+    let mut dir = self.directory.clone();
+    dir.extend(identifier.split('/'));
+    if !dir.exists() {
+      return Err(Box::new(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("Plugin directory {} doesn't exist.", dir.display()),
+      )));
+    }
+
+    let result = self.load_plugin_from_dir(&dir, identifier);
+    self.record_result(identifier, &result);
+    result
+  }
+
+  /// Unloads the plugin `identifier` together with every plugin that has a
+  /// hard dependency on it. Returns the identifiers of unloaded plugins.
+  pub fn unload_plugin(&mut self, identifier: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let affected = self.plugin_with_dependents(identifier)?;
+    self.unload_plugins(&affected);
+    self.remove_plugins(&affected);
+
+    log_message(format!("Unloaded {}.", affected.join(", ")));
+    Ok(affected)
+  }
+
+  fn record_result<T>(&mut self, identifier: &str, result: &Result<T, Box<dyn Error>>) {
+    match result {
+      Ok(_) => { self.errors.remove(identifier); }
+      Err(e) => { self.errors.insert(identifier.to_string(), e.to_string()); }
     }
+  }
+
+  fn load_plugin_from_dir(&mut self, dir: &Path, identifier: &str) -> Result<(), Box<dyn Error>> {
+    let mut plugin = Plugin::load_plugin(dir, identifier)?;
+    self.check_sources(&plugin)?;
+    Self::check_dependencies(&plugin, &self.plugins.iter().collect::<Vec<_>>())?;
+    self.resolve_optional_dependencies(&mut plugin);
 
     self.lua.context(|ctx: rlua::Context| {
       let globals = ctx.globals();
-      let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
-      let state = state.0.lock().unwrap();
-      let _ = state.listeners.emit(&ctx, "PluginsLoaded", ());
+      let libs: rlua::Table = globals.raw_get("luna_libs").unwrap();
+      if !libs.contains_key(identifier).unwrap() {
+        libs.raw_set(identifier, ctx.create_table().unwrap()).unwrap();
+      }
     });
+
+    self.plugins.push(plugin);
+    self.start_plugin(identifier)?;
+
+    log_message(format!("Loaded {}.", identifier));
+    Ok(())
   }
 
-  pub fn lua(&self) -> &rlua::Lua {
-    &self.lua
+  fn reload_plugins(&mut self, affected: &[String]) -> Result<(), Box<dyn Error>> {
+
+    let mut reloaded: Vec<Plugin> = Vec::with_capacity(affected.len());
+    for ident in affected {
+      let old = self.plugins.iter().find(|pl| pl.identifier() == ident).unwrap();
+      let plugin = Plugin::load_plugin(old.directory(), ident)?;
+      self.check_sources(&plugin)?;
+
+      // Dependencies have to load before the plugin, preferring the reloaded
+      // versions over the ones that are about to go away.
+      let pos = self.plugins.iter().position(|pl| pl.identifier() == ident).unwrap();
+      let available: Vec<&Plugin> = reloaded.iter()
+        .chain(self.plugins[..pos].iter())
+        .collect();
+      Self::check_dependencies(&plugin, &available)?;
+
+      reloaded.push(plugin);
+    }
+
+    self.unload_plugins(affected);
+
+    for mut plugin in reloaded {
+      self.resolve_optional_dependencies(&mut plugin);
+      let pos = self.plugins.iter()
+        .position(|pl| pl.identifier() == plugin.identifier())
+        .unwrap();
+      self.plugins[pos] = plugin;
+    }
+
+    let mut failed = None;
+    for ident in affected {
+      // Dropped when one of its dependencies failed
+      if self.plugin(ident).is_none() {
+        continue;
+      }
+
+      if let Err(e) = self.start_plugin(ident) {
+        failed.get_or_insert_with(|| io::Error::other(
+          format!("\"{}\" failed to load: {}", ident, e),
+        ));
+      }
+    }
+
+    if let Some(e) = failed {
+      return Err(Box::new(e));
+    }
+
+    log_message(format!("Reloaded {}.", affected.join(", ")));
+    Ok(())
+  }
+
+  // Runs the plugin `identifier`, which has to be in `plugins` already. If
+  // its `Plugin.lua` fails, whatever it registered up to that point is
+  // unloaded again and the plugins that depend on it are dropped before
+  // they run.
+  fn start_plugin(&mut self, identifier: &str) -> Result<(), Box<dyn Error>> {
+    let plugin = self.plugin(identifier).unwrap();
+    let result: Result<(), Box<dyn Error>> = self.lua.context(|ctx: rlua::Context| {
+      Self::run_plugin(plugin, &ctx, &mut HashSet::new())?;
+      let _ = events::emit(&ctx, &self.state, "PluginLoaded", identifier);
+      Ok(())
+    });
+
+    if let Err(e) = &result {
+      let affected = self.plugin_with_dependents(identifier)?;
+      self.unload_plugins(&affected[..1]);
+      self.remove_plugins(&affected);
+
+      self.errors.insert(identifier.to_string(), e.to_string());
+      for dependent in &affected[1..] {
+        let reason = format!("Dependency \"{}\" failed to load.", identifier);
+        log_error(format!("Couldn't load \"{}\": {}", dependent, reason));
+        self.errors.insert(dependent.clone(), reason);
+      }
+    }
+
+    result
+  }
+
+  fn remove_plugins(&mut self, identifiers: &[String]) {
+    self.plugins.retain(|pl| !identifiers.iter().any(|ident| ident == pl.identifier()));
+  }
+
+  // Returns `identifier` followed by everything that (transitively) has a
+  // hard dependency on it, in load order. Optional dependents only get the
+  // `PluginWillUnload` and `PluginUnload` events.
+  fn plugin_with_dependents(&self, identifier: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let pos = self.plugins.iter()
+      .position(|pl| pl.identifier() == identifier)
+      .ok_or_else(|| io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("Plugin \"{}\" isn't loaded.", identifier),
+      ))?;
+
+    let mut affected = vec![identifier.to_string()];
+    affected.extend(dependency_graph::hard_dependents(&self.plugins[pos + 1..], identifier));
+    Ok(affected)
   }
 
+  // Compiles every source file of `plugin` without running anything, so
+  // that a syntax error doesn't take down the version that is running now.
+  fn check_sources(&self, plugin: &Plugin) -> Result<(), Box<dyn Error>> {
+    let sources = self.plugin_files(plugin)
+      .into_iter()
+      .filter(|path| path.extension().is_some_and(|ext| ext == "lua"));
+
+    for path in sources {
+      let contents = fs::read_to_string(&path)?;
+      let local_path = path.strip_prefix(plugin.directory()).unwrap_or(&path);
+      let name = format!("{}::{}", plugin.identifier(), local_path.display());
+      self.lua.context(|ctx: rlua::Context| {
+        ctx.load(&contents)
+          .set_name(&name)?
+          .into_function()
+          .map(|_| ())
+      })?;
+    }
+
+    Ok(())
+  }
+
+  // Makes sure that the hard dependencies of `plugin` are satisfied by
+  // `available`. The first plugin with a matching identifier wins.
+  fn check_dependencies(plugin: &Plugin, available: &[&Plugin]) -> Result<(), Box<dyn Error>> {
+    for (dep_ident, dep_version) in plugin.dependencies() {
+      let dep = available.iter()
+        .find(|pl| pl.identifier() == dep_ident)
+        .ok_or_else(|| io::Error::new(
+          io::ErrorKind::NotFound,
+          format!(
+            "Dependency \"{}\" of \"{}\" isn't loaded before it.",
+            dep_ident, plugin.identifier(),
+          ),
+        ))?;
+
+      dependency_graph::check_requirement(dep, dep_version)
+        .map_err(|reason| io::Error::new(io::ErrorKind::InvalidData, reason))?;
+    }
+
+    Ok(())
+  }
+
+  fn resolve_optional_dependencies(&self, plugin: &mut Plugin) {
+    let found: Vec<String> = plugin.optional_dependencies()
+      .iter()
+      .filter(|(dep_ident, dep_version)| {
+        self.plugins.iter()
+          .find(|pl| pl.identifier() == dep_ident.as_str())
+          .is_some_and(|dep| dep_version.matches(dep.version()))
+      })
+      .map(|(dep_ident, _)| dep_ident.clone())
+      .collect();
+
+    for dep_ident in found {
+      plugin.set_optional_dependency_found(&dep_ident);
+    }
+  }
+
+  // Lets the plugins know that `identifiers` are about to go away and then
+  // drops everything they registered. Their library tables are emptied
+  // rather than replaced, since dependents hold on to them.
+  fn unload_plugins(&self, identifiers: &[String]) {
+    self.lua.context(|ctx: rlua::Context| {
+      for ident in identifiers.iter().rev() {
+        let _ = events::emit(&ctx, &self.state, "PluginWillUnload", ident.as_str());
+      }
+      for ident in identifiers.iter().rev() {
+        let _ = events::emit(&ctx, &self.state, "PluginUnload", ident.as_str());
+      }
+
+      let globals = ctx.globals();
+      let libs: rlua::Table = globals.raw_get("luna_libs").unwrap();
+      for ident in identifiers {
+        let mut state = self.state.lock().unwrap();
+        state.listeners.remove_plugin_listeners(ident);
+        state.commands.remove_plugin_commands(ident);
+        state.timers.remove_plugin_timers(ident);
+        state.tasks.remove_plugin_tasks(ident);
+        state.entity_classes.remove_plugin_classes(ident);
+        state.virtual_hooks.remove_plugin_hooks(ident);
+        state.required_files.remove(ident);
+        drop(state);
+        let _ = entity_data::remove_plugin_data(&ctx, ident);
+
+        let plugin_lib: rlua::Table = libs.raw_get(ident.as_str()).unwrap();
+        let keys: Vec<rlua::Value> = plugin_lib.clone()
+          .pairs::<rlua::Value, rlua::Value>()
+          .map(|pair| pair.unwrap().0)
+          .collect();
+        keys.into_iter().for_each(|k| plugin_lib.raw_set(k, rlua::Nil).unwrap());
+      }
+
+      ctx.expire_registry_values();
+    });
+  }
+
+  // Fails if `Plugin.lua` can't be read or compiled, or raises an error.
   fn run_plugin<'a>(
     plugin: &'a Plugin,
     ctx: &rlua::Context,
     visited: &mut HashSet<&'a str>,
-  ) {
+  ) -> Result<(), Box<dyn Error>> {
     visited.insert(plugin.identifier());
 
-    let main_contents = fs::read_to_string(plugin.main_source_path()).unwrap();
+    let main_contents = fs::read_to_string(plugin.main_source_path())?;
     let plugin_handle = Arc::new(ctx.create_registry_value(core::PluginHandle::from_plugin(&plugin)).unwrap());
     let env = core::setup_environment(plugin.directory(), plugin.directory(), plugin_handle, &ctx);
     let name = format!("{}::Plugin.lua", plugin.identifier());
     let chunk = ctx.load(&main_contents)
-                .set_name(&name).unwrap()
-                .set_environment(env).unwrap()
-                .into_function();
-
-    let chunk: rlua::Function = match chunk {
-      Ok(chunk) => chunk,
-      Err(err) => {
-        lua_helpers::print_lua_error(&err);
-        return;
-      }
-    };
+                .set_name(&name)?
+                .set_environment(env)?
+                .into_function()?;
 
-    let result = lua_helpers::call_lua::<_, rlua::Value>(&ctx, &chunk, ());
-    if let Ok(rlua::Value::Table(table)) = result {
+    let result = lua_helpers::call_lua::<_, rlua::Value>(&ctx, &chunk, ())?;
+    if let rlua::Value::Table(table) = result {
       Self::add_to_plugin_lib(&plugin, &ctx, &table);
     }
+
+    Ok(())
   }
 
   fn add_to_plugin_lib<'lua>(
@@ -319,12 +674,82 @@ impl PluginSystem {
 
 impl Drop for PluginSystem {
   fn drop(&mut self) {
+    // Same events as when the plugins are unloaded one by one, so plugins
+    // see them on shutdown too
     self.lua.context(|ctx: rlua::Context| {
-      let globals = ctx.globals();
-      let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
-      let state = state.0.lock().unwrap();
-      let _ = state.listeners.emit(&ctx, "PluginsWillUnload", ());
-      let _ = state.listeners.emit(&ctx, "PluginsUnload", ());
+      let _ = events::emit(&ctx, &self.state, "PluginsWillUnload", ());
+      for pl in self.plugins.iter().rev() {
+        let _ = events::emit(&ctx, &self.state, "PluginWillUnload", pl.identifier());
+      }
+      for pl in self.plugins.iter().rev() {
+        let _ = events::emit(&ctx, &self.state, "PluginUnload", pl.identifier());
+      }
+      let _ = events::emit(&ctx, &self.state, "PluginsUnload", ());
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use self::plugin::{test_manifest, test_plugin};
+
+  // Writes a plugin with the given manifest and `Plugin.lua` below `dir`.
+  fn write_plugin(dir: &Path, identifier: &str, manifest: &str, source: &str) {
+    let mut plugin_dir = dir.to_path_buf();
+    plugin_dir.extend(identifier.split('/'));
+    fs::create_dir_all(&plugin_dir).unwrap();
+    fs::write(plugin_dir.join("Plugin.toml"), test_manifest(identifier, "1.0.0", manifest)).unwrap();
+    fs::write(plugin_dir.join("Plugin.lua"), source).unwrap();
+  }
+
+  #[test]
+  fn dependencies_must_match_their_requirement() {
+    let plugin = test_plugin("NS/Plugin", "1.0.0", "[Dependencies]\n\"NS/Dep\" = \"^1.2\"\n");
+    let old = test_plugin("NS/Dep", "1.1.0", "");
+    let new = test_plugin("NS/Dep", "1.3.0", "");
+
+    assert!(PluginSystem::check_dependencies(&plugin, &[&new]).is_ok());
+    let error = PluginSystem::check_dependencies(&plugin, &[&old]).unwrap_err().to_string();
+    assert_eq!(error, "Dependency \"NS/Dep\" requires version ^1.2, found 1.1.0.");
+  }
+
+  #[test]
+  fn missing_dependencies_are_reported() {
+    let plugin = test_plugin("NS/Plugin", "1.0.0", "[Dependencies]\n\"NS/Dep\" = \"*\"\n");
+    let error = PluginSystem::check_dependencies(&plugin, &[]).unwrap_err().to_string();
+    assert!(error.contains("Dependency \"NS/Dep\" of \"NS/Plugin\" isn't loaded"), "{}", error);
+  }
+
+  #[test]
+  fn plugins_that_fail_to_run_are_unloaded() {
+    let dir = std::env::temp_dir().join(format!("luna-{}-failing", std::process::id()));
+    let listen = "local Listeners = require 'Luna/Listeners'\n\
+      Listeners.On(Listeners.Events.PluginsLoaded, function() end)\n";
+    write_plugin(&dir, "NS/Good", "", listen);
+    write_plugin(&dir, "NS/Bad", "", &format!("{}local missing = nil\nmissing.field = 1\n", listen));
+    write_plugin(&dir, "NS/Dependent", "[Dependencies]\n\"NS/Bad\" = \"*\"\n", listen);
+
+    write_plugin(&dir, "NS/Gone", "", listen);
+
+    let state = Arc::new(Mutex::new(GlobalState::new()));
+    let mut plugin_sys = PluginSystem::mount(&dir, state.clone());
+    fs::remove_file(dir.join("NS").join("Gone").join("Plugin.lua")).unwrap();
+    plugin_sys.run_plugins();
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(plugin_sys.plugin("NS/Good").is_some());
+    assert!(plugin_sys.plugin("NS/Bad").is_none());
+    assert!(plugin_sys.plugin("NS/Dependent").is_none());
+    assert!(plugin_sys.plugin("NS/Gone").is_none());
+    let error = &plugin_sys.errors()["NS/Bad"];
+    assert!(error.contains("attempt to index a nil value"), "{}", error);
+    assert_eq!(plugin_sys.errors()["NS/Dependent"], "Dependency \"NS/Bad\" failed to load.");
+
+    plugin_sys.lua().context(|ctx| {
+      let listeners = state.lock().unwrap().listeners.listeners(&ctx, "PluginsLoaded");
+      let owners: Vec<&str> = listeners.iter().map(|(owner, _)| owner.as_str()).collect();
+      assert_eq!(owners, vec!["NS/Good"]);
     });
   }
 }
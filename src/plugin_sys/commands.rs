@@ -0,0 +1,143 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use crate::ffi_wrapper::{add_server_command, command_arg_string, is_cvar};
+use crate::global_state::GlobalState;
+use crate::lua_helpers::call_plugin_lua;
+
+lazy_static! {
+  // The engine can't tell which commands exist, and silently keeps its own
+  // handler when a command is registered twice. These are the ones the
+  // engine itself (its `Cmd_AddCommand` calls) and Metamod always add.
+  // Server commands of the game or of other Metamod plugins can't be known,
+  // so apart from cvars those aren't caught and never reach Luna.
+  static ref ENGINE_COMMANDS: HashSet<&'static str> = [
+    "_restart", "addip", "alias", "autosave", "ban", "banid", "career",
+    "changelevel", "changelevel2", "cmd", "cmdlist", "connect", "cvarlist",
+    "demos", "disconnect", "dlfile", "dropclient", "echo", "exec", "exit",
+    "fly", "fullinfo", "god", "heartbeat", "hpkextract", "hpklist", "hpkremove",
+    "hpkval", "interp", "kick", "kill", "listid", "listip", "load", "localinfo",
+    "log", "logaddress", "logaddress_add", "logaddress_del", "map", "maps",
+    "maxplayers", "mcache", "meta", "motd", "motd_write", "noclip", "notarget",
+    "pause", "ping", "quit", "rcon", "reconnect", "reload", "removeid",
+    "removeip", "resetrcon", "restart", "save", "say", "say_team", "serverinfo",
+    "setinfo", "setmaster", "setpause", "showinfo", "shutdownserver",
+    "startdemos", "stat", "stats", "status", "stop", "stopdemo", "stuffcmds",
+    "tell", "unpause", "user", "users", "version", "viewframe", "viewmodel",
+    "viewnext", "viewprev", "wait", "writeid", "writeip",
+  ].iter().copied().collect();
+}
+
+struct Command {
+  owner: String,
+  handler: rlua::RegistryKey,
+}
+
+/// Server console commands registered by plugins.
+pub struct LuaCommandRegistry {
+  commands: HashMap<String, Command>,
+  // The engine can't unregister commands, so whatever ends up here stays
+  // registered until the server shuts down. Commands without a handler are
+  // simply ignored.
+  engine_commands: HashSet<String>,
+}
+
+impl LuaCommandRegistry {
+  pub fn new() -> Self {
+    LuaCommandRegistry {
+      commands: HashMap::new(),
+      engine_commands: HashSet::new(),
+    }
+  }
+
+  pub fn add_command<'lua>(
+    &mut self,
+    ctx: &rlua::Context<'lua>,
+    owner: &str,
+    name: &str,
+    handler: rlua::Function<'lua>,
+  ) -> Result<(), String> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+      return Err(format!("Invalid command name \"{}\"", name));
+    }
+
+    // The engine looks commands up case-insensitively
+    let lower_name = name.to_lowercase();
+    if lower_name == "luna" {
+      return Err(format!("Command \"{}\" is reserved", name));
+    }
+
+    if ENGINE_COMMANDS.contains(lower_name.as_str()) || is_cvar(&lower_name) {
+      return Err(format!("\"{}\" is already defined by the engine or the game", name));
+    }
+
+    if let Some(command) = self.commands.get(&lower_name) {
+      if command.owner != owner {
+        return Err(format!(
+          "Command \"{}\" is already registered by \"{}\"", name, command.owner,
+        ));
+      }
+    }
+
+    let handler = ctx.create_registry_value(handler).unwrap();
+    self.commands.insert(lower_name.clone(), Command {
+      owner: owner.to_string(),
+      handler,
+    });
+
+    if self.engine_commands.insert(lower_name.clone()) {
+      add_server_command(&lower_name);
+    }
+
+    Ok(())
+  }
+
+  pub fn remove_command(&mut self, owner: &str, name: &str) -> bool {
+    let name = name.to_lowercase();
+    match self.commands.get(&name) {
+      Some(command) if command.owner == owner => {
+        self.commands.remove(&name);
+        true
+      }
+      _ => false,
+    }
+  }
+
+  /// Removes every command that was registered by the plugin `owner`.
+  pub fn remove_plugin_commands(&mut self, owner: &str) {
+    self.commands.retain(|_, command| command.owner != owner);
+  }
+
+  /// The handler of the command `name` along with the plugin that added it.
+  pub fn handler<'lua>(
+    &self,
+    ctx: &rlua::Context<'lua>,
+    name: &str,
+  ) -> Option<(String, rlua::Function<'lua>)> {
+    self.commands
+      .get(&name.to_lowercase())
+      .map(|command| (command.owner.clone(), ctx.registry_value(&command.handler).unwrap()))
+  }
+}
+
+/// Runs the handler of the command in `args`. Returns `false` if no plugin
+/// handles it (anymore).
+pub fn dispatch<'lua>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  args: &[String],
+) -> bool {
+  let name = match args.first() {
+    Some(name) => name,
+    None => return false,
+  };
+
+  let handler = state.lock().unwrap().commands.handler(ctx, name);
+  let (owner, handler) = match handler {
+    Some(handler) => handler,
+    None => return false,
+  };
+
+  let arg_table = ctx.create_sequence_from(args[1..].iter().cloned()).unwrap();
+  let _ = call_plugin_lua::<_, ()>(ctx, &owner, &handler, (arg_table, command_arg_string()));
+  true
+}
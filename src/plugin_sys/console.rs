@@ -0,0 +1,151 @@
+use crate::ffi_wrapper::server_print;
+use crate::meta_ffi::constant::{
+  DLL_INTERFACE_VERSION,
+  ENGINE_INTERFACE_VERSION,
+  NEW_DLL_INTERFACE_VERSION,
+};
+use super::PluginSystem;
+
+const USAGE: &[&str] = &[
+  "Usage: luna <command> [arguments]",
+  "  list             List loaded plugins",
+  "  info <id>        Show information about a plugin",
+  "  deps <id>        Show dependencies and dependents of a plugin",
+  "  errors           Show why plugins failed to load",
+  "  reload <id>      Reload a plugin and its dependents",
+  "  unload <id>      Unload a plugin and its dependents",
+  "  load <id>        Load a plugin that isn't loaded",
+  "  version          Show the version of Luna",
+];
+
+fn print(message: impl AsRef<str>) {
+  server_print(format!("{}\n", message.as_ref()));
+}
+
+/// Handles the `luna` server command. `args` doesn't include the command
+/// name itself.
+pub fn luna_command(plugin_sys: &mut PluginSystem, args: &[String]) {
+  let subcommand = args.first().map(String::as_str);
+  let identifier = args.get(1).map(String::as_str);
+
+  match (subcommand, identifier) {
+    (Some("list"), _) => list(plugin_sys),
+    (Some("info"), Some(identifier)) => info(plugin_sys, identifier),
+    (Some("deps"), Some(identifier)) => deps(plugin_sys, identifier),
+    (Some("errors"), _) => errors(plugin_sys),
+    (Some("reload"), Some(identifier)) => {
+      if let Err(e) = plugin_sys.reload_plugin(identifier) {
+        print(format!("Couldn't reload \"{}\": {}", identifier, e));
+      }
+    }
+    (Some("unload"), Some(identifier)) => {
+      if let Err(e) = plugin_sys.unload_plugin(identifier) {
+        print(format!("Couldn't unload \"{}\": {}", identifier, e));
+      }
+    }
+    (Some("load"), Some(identifier)) => {
+      if let Err(e) = plugin_sys.load_plugin(identifier) {
+        print(format!("Couldn't load \"{}\": {}", identifier, e));
+      }
+    }
+    (Some("version"), _) => version(),
+    _ => USAGE.iter().for_each(print),
+  }
+}
+
+fn list(plugin_sys: &PluginSystem) {
+  let plugins = plugin_sys.plugins();
+  print(format!("Loaded plugins ({}):", plugins.len()));
+  for (idx, plugin) in plugins.iter().enumerate() {
+    print(format!(
+      "  [{:>2}] {:<32} {:<10} {}",
+      idx + 1, plugin.identifier(), plugin.version(), plugin.info().title,
+    ));
+  }
+
+  let failed = plugin_sys.errors();
+  if !failed.is_empty() {
+    print(format!("Failed plugins ({}), see \"luna errors\":", failed.len()));
+    failed.keys().for_each(|ident| print(format!("  {}", ident)));
+  }
+}
+
+fn info(plugin_sys: &PluginSystem, identifier: &str) {
+  let plugin = match plugin_sys.plugin(identifier) {
+    Some(plugin) => plugin,
+    None => return print(format!("Plugin \"{}\" isn't loaded.", identifier)),
+  };
+
+  let info = plugin.info();
+  print(format!("Identifier:    {}", plugin.identifier()));
+  print(format!("Title:         {}", info.title));
+  print(format!("Version:       {}", plugin.version()));
+  print(format!("Description:   {}", info.description));
+  print(format!("Authors:       {}", info.authors.join(", ")));
+  print(format!("Interface:     {}", info.interface));
+  print(format!("Load priority: {}", plugin.load_priority()));
+  print(format!("Directory:     {}", plugin.directory().display()));
+}
+
+fn deps(plugin_sys: &PluginSystem, identifier: &str) {
+  let plugin = match plugin_sys.plugin(identifier) {
+    Some(plugin) => plugin,
+    None => return print(format!("Plugin \"{}\" isn't loaded.", identifier)),
+  };
+
+  let found_version = |ident: &str| match plugin_sys.plugin(ident) {
+    Some(dep) => format!("found {}", dep.version()),
+    None => "not loaded".to_string(),
+  };
+
+  let mut dependencies: Vec<_> = plugin.dependencies().iter().collect();
+  dependencies.sort_by(|l, r| l.0.cmp(r.0));
+  print("Dependencies:");
+  for (dep_ident, dep_version) in dependencies {
+    print(format!("  {} {} ({})", dep_ident, dep_version, found_version(dep_ident)));
+  }
+
+  let mut optional: Vec<_> = plugin.optional_dependencies().iter().collect();
+  optional.sort_by(|l, r| l.0.cmp(r.0));
+  print("Optional dependencies:");
+  for (dep_ident, dep_version) in optional {
+    let status = match plugin.has_optional_dependency(dep_ident) {
+      true => found_version(dep_ident),
+      false => "not used".to_string(),
+    };
+    print(format!("  {} {} ({})", dep_ident, dep_version, status));
+  }
+
+  print("Load after:");
+  for after_ident in plugin.load_after() {
+    let status = match plugin_sys.plugin(after_ident) {
+      Some(_) => "loaded",
+      None => "not loaded",
+    };
+    print(format!("  {} ({})", after_ident, status));
+  }
+
+  print("Required by:");
+  for dependent in plugin_sys.dependents(identifier) {
+    print(format!("  {}", dependent.identifier()));
+  }
+}
+
+fn errors(plugin_sys: &PluginSystem) {
+  let errors = plugin_sys.errors();
+  if errors.is_empty() {
+    return print("No errors.");
+  }
+
+  for (identifier, error) in errors {
+    print(format!("{}: {}", identifier, error));
+  }
+}
+
+fn version() {
+  print(format!("Luna {}", env!("CARGO_PKG_VERSION")));
+  print(format!(
+    "Interfaces: DLL {}, new DLL {}, engine {}",
+    DLL_INTERFACE_VERSION, NEW_DLL_INTERFACE_VERSION, ENGINE_INTERFACE_VERSION,
+  ));
+}
@@ -0,0 +1,380 @@
+use std::collections::{BTreeMap, BTreeSet};
+use semver::VersionReq;
+use crate::ffi_wrapper::{log_error, log_message};
+use super::plugin::Plugin;
+
+// Plugins that are ready to load are picked by the highest `LoadPriority`
+// first and then by identifier, so the resulting order never depends on the
+// order in which the filesystem listed the plugin directories.
+type ReadyKey = (i64, String);
+
+fn ready_key(plugin: &Plugin) -> ReadyKey {
+  (-i64::from(plugin.load_priority()), plugin.identifier().to_string())
+}
+
+/// Checks that the version of `dependency` satisfies `requirement`.
+pub fn check_requirement(dependency: &Plugin, requirement: &VersionReq) -> Result<(), String> {
+  match requirement.matches(dependency.version()) {
+    true => Ok(()),
+    false => Err(format!(
+      "Dependency \"{}\" requires version {}, found {}.",
+      dependency.identifier(), requirement, dependency.version(),
+    )),
+  }
+}
+
+enum VisitState {
+  InProgress,
+  Done,
+}
+
+struct Validator<'a> {
+  plugins: &'a BTreeMap<String, Plugin>,
+  states: BTreeMap<&'a str, VisitState>,
+  chain: Vec<&'a str>,
+  errors: BTreeMap<&'a str, String>,
+}
+
+impl<'a> Validator<'a> {
+  fn new(plugins: &'a BTreeMap<String, Plugin>) -> Self {
+    Validator {
+      plugins,
+      states: BTreeMap::new(),
+      chain: Vec::new(),
+      errors: BTreeMap::new(),
+    }
+  }
+
+  fn fail(&mut self, identifier: &'a str, reason: String) {
+    self.errors.entry(identifier).or_insert(reason);
+  }
+
+  // Depth-first walk over the hard dependencies. Returns whether
+  // `identifier` can be loaded.
+  fn visit(&mut self, identifier: &'a str) -> bool {
+    if let Some(VisitState::Done) = self.states.get(identifier) {
+      return !self.errors.contains_key(identifier);
+    }
+
+    let plugins = self.plugins;
+    let plugin = &plugins[identifier];
+    self.states.insert(identifier, VisitState::InProgress);
+    self.chain.push(identifier);
+
+    let mut deps: Vec<_> = plugin.dependencies().iter().collect();
+    deps.sort_by(|l, r| l.0.cmp(r.0));
+
+    for (dep_ident, dep_version) in deps {
+      let dep = match plugins.get_key_value(dep_ident) {
+        Some((dep_ident, dep)) => (dep_ident.as_str(), dep),
+        None => {
+          self.fail(identifier, format!("Dependency \"{}\" not found.", dep_ident));
+          continue;
+        }
+      };
+
+      if let Some(VisitState::InProgress) = self.states.get(dep.0) {
+        let pos = self.chain.iter().position(|&id| id == dep.0).unwrap();
+        let cycle = self.chain[pos..].iter()
+          .chain(std::iter::once(&dep.0))
+          .cloned()
+          .collect::<Vec<_>>()
+          .join(" -> ");
+
+        for &member in &self.chain[pos..].to_vec() {
+          self.fail(member, format!("Dependency cycle detected: {}", cycle));
+        }
+        continue;
+      }
+
+      if !self.visit(dep.0) {
+        self.fail(identifier, format!("Dependency \"{}\" failed to load.", dep.0));
+      } else if let Err(reason) = check_requirement(dep.1, dep_version) {
+        self.fail(identifier, reason);
+      }
+    }
+
+    self.chain.pop();
+    self.states.insert(identifier, VisitState::Done);
+    !self.errors.contains_key(identifier)
+  }
+}
+
+/// Drops every plugin whose hard dependencies can't be satisfied (recording
+/// why in `errors`), resolves optional dependencies and returns the order the
+/// remaining plugins have to be loaded in.
+pub fn resolve_load_order(
+  plugins: &mut BTreeMap<String, Plugin>,
+  errors: &mut BTreeMap<String, String>,
+) -> Vec<String> {
+  let failed: Vec<(String, String)> = {
+    let mut validator = Validator::new(plugins);
+    for identifier in plugins.keys() {
+      validator.visit(identifier);
+    }
+
+    validator.errors.into_iter()
+      .map(|(ident, reason)| (ident.to_string(), reason))
+      .collect()
+  };
+
+  for (identifier, reason) in failed {
+    log_error(format!("Couldn't load \"{}\": {}", identifier, reason));
+    plugins.remove(&identifier);
+    errors.insert(identifier, reason);
+  }
+
+  // Edges point from a plugin to the plugins that have to wait for it.
+  let mut dependents: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+  let mut found_optional: Vec<(String, String)> = Vec::new();
+
+  for (identifier, plugin) in plugins.iter() {
+    let mut after = BTreeSet::new();
+    after.extend(plugin.dependencies().keys().map(String::as_str));
+
+    for (dep_ident, dep_version) in plugin.optional_dependencies() {
+      match plugins.get_key_value(dep_ident) {
+        Some((dep_ident, dep)) if dep_version.matches(dep.version()) => {
+          found_optional.push((identifier.clone(), dep_ident.clone()));
+          after.insert(dep_ident.as_str());
+        }
+        Some((dep_ident, dep)) => log_message(format!(
+          "Optional dependency \"{}\" of \"{}\" requires version {}, found {}.",
+          dep_ident, identifier, dep_version, dep.version(),
+        )),
+        None => { }
+      }
+    }
+
+    after.extend(
+      plugin.load_after().iter()
+        .filter_map(|ident| plugins.get_key_value(ident))
+        .map(|(ident, _)| ident.as_str())
+    );
+
+    for dep_ident in after {
+      dependents.entry(dep_ident).or_default().insert(identifier.as_str());
+    }
+  }
+
+  let mut in_degree: BTreeMap<&str, usize> = plugins.keys()
+    .map(|ident| (ident.as_str(), 0))
+    .collect();
+  for targets in dependents.values() {
+    for target in targets {
+      *in_degree.get_mut(target).unwrap() += 1;
+    }
+  }
+
+  // Hard dependencies that haven't been loaded yet. A cycle can only be
+  // broken at a plugin that has none left.
+  let mut hard_in_degree: BTreeMap<&str, usize> = plugins.iter()
+    .map(|(ident, plugin)| (ident.as_str(), plugin.dependencies().len()))
+    .collect();
+
+  let mut ready: BTreeSet<ReadyKey> = in_degree.iter()
+    .filter(|(_, &degree)| degree == 0)
+    .map(|(ident, _)| ready_key(&plugins[*ident]))
+    .collect();
+  let mut order: Vec<String> = Vec::with_capacity(plugins.len());
+
+  while order.len() < plugins.len() {
+    let next = match ready.iter().next().cloned() {
+      Some(key) => key,
+      None => {
+        // Hard dependency cycles were rejected above, so some of what is
+        // left only waits on optional dependencies or `LoadAfter` hints.
+        let key = in_degree.keys()
+          .filter(|ident| hard_in_degree[*ident] == 0)
+          .map(|ident| ready_key(&plugins[*ident]))
+          .min()
+          .unwrap();
+
+        log_message(format!(
+          "Ignoring load order hints of \"{}\": they form a cycle.", key.1,
+        ));
+        key
+      }
+    };
+
+    ready.remove(&next);
+    in_degree.remove(next.1.as_str());
+
+    if let Some(targets) = dependents.get(next.1.as_str()) {
+      // Plugins that were already loaded because a cycle had to be broken
+      // are no longer tracked in `in_degree`.
+      for target in targets {
+        if let Some(degree) = in_degree.get_mut(target) {
+          if plugins[*target].dependencies().contains_key(next.1.as_str()) {
+            *hard_in_degree.get_mut(target).unwrap() -= 1;
+          }
+          *degree -= 1;
+          if *degree == 0 {
+            ready.insert(ready_key(&plugins[*target]));
+          }
+        }
+      }
+    }
+
+    order.push(next.1);
+  }
+
+  for (identifier, dep_ident) in found_optional {
+    if let Some(plugin) = plugins.get_mut(&identifier) {
+      plugin.set_optional_dependency_found(&dep_ident);
+    }
+  }
+
+  order
+}
+
+/// Returns the plugins of `loaded` that (transitively) have a hard
+/// dependency on `identifier`, in load order. Optional dependents aren't
+/// included, they keep running without it.
+pub fn hard_dependents(loaded: &[Plugin], identifier: &str) -> Vec<String> {
+  let mut affected = vec![identifier];
+  for plugin in loaded {
+    if affected.iter().any(|ident| plugin.dependencies().contains_key(*ident)) {
+      affected.push(plugin.identifier());
+    }
+  }
+
+  affected.into_iter().skip(1).map(str::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::plugin::test_plugin;
+
+  fn plugins(list: &[(&str, &str)]) -> BTreeMap<String, Plugin> {
+    list.iter()
+      .map(|&(ident, manifest)| (ident.to_string(), test_plugin(ident, "1.0.0", manifest)))
+      .collect()
+  }
+
+  #[test]
+  fn hard_cycles_fail_with_the_whole_chain() {
+    let mut plugins = plugins(&[
+      ("NS/A", "[Dependencies]\n\"NS/B\" = \"*\"\n"),
+      ("NS/B", "[Dependencies]\n\"NS/C\" = \"*\"\n"),
+      ("NS/C", "[Dependencies]\n\"NS/A\" = \"*\"\n"),
+      ("NS/D", ""),
+    ]);
+    let mut errors = BTreeMap::new();
+
+    assert_eq!(resolve_load_order(&mut plugins, &mut errors), vec!["NS/D"]);
+    for ident in &["NS/A", "NS/B", "NS/C"] {
+      assert!(errors[*ident].contains("NS/A -> NS/B -> NS/C -> NS/A"), "{}", errors[*ident]);
+    }
+  }
+
+  #[test]
+  fn failures_propagate_to_dependents() {
+    let mut plugins = plugins(&[
+      ("NS/A", "[Dependencies]\n\"NS/Missing\" = \"*\"\n"),
+      ("NS/B", "[Dependencies]\n\"NS/A\" = \"*\"\n"),
+    ]);
+    let mut errors = BTreeMap::new();
+
+    assert!(resolve_load_order(&mut plugins, &mut errors).is_empty());
+    assert_eq!(errors["NS/A"], "Dependency \"NS/Missing\" not found.");
+    assert_eq!(errors["NS/B"], "Dependency \"NS/A\" failed to load.");
+  }
+
+  #[test]
+  fn dependencies_must_match_their_requirement() {
+    let mut plugins = plugins(&[("NS/A", "[Dependencies]\n\"NS/Dep\" = \"^1.2\"\n")]);
+    plugins.insert("NS/Dep".to_string(), test_plugin("NS/Dep", "1.1.0", ""));
+    let mut errors = BTreeMap::new();
+
+    assert_eq!(resolve_load_order(&mut plugins, &mut errors), vec!["NS/Dep"]);
+    assert_eq!(errors["NS/A"], "Dependency \"NS/Dep\" requires version ^1.2, found 1.1.0.");
+  }
+
+  #[test]
+  fn optional_dependencies_load_first_when_they_match() {
+    let mut plugins = plugins(&[
+      ("NS/A", "[OptionalDependencies]\n\"NS/Z\" = \"^1\"\n\"NS/Y\" = \"^2\"\n\"NS/X\" = \"*\"\n"),
+      ("NS/Y", ""),
+      ("NS/Z", ""),
+    ]);
+    let mut errors = BTreeMap::new();
+
+    let order = resolve_load_order(&mut plugins, &mut errors);
+    assert!(errors.is_empty());
+    assert_eq!(order, vec!["NS/Y", "NS/Z", "NS/A"]);
+
+    let plugin = &plugins["NS/A"];
+    assert!(plugin.has_optional_dependency("NS/Z"));
+    assert!(!plugin.has_optional_dependency("NS/Y"));
+    assert!(!plugin.has_optional_dependency("NS/X"));
+  }
+
+  #[test]
+  fn load_after_orders_without_requiring() {
+    let mut plugins = plugins(&[
+      ("NS/A", "LoadAfter = [\"NS/B\", \"NS/Missing\"]\n"),
+      ("NS/B", ""),
+    ]);
+    let mut errors = BTreeMap::new();
+
+    assert_eq!(resolve_load_order(&mut plugins, &mut errors), vec!["NS/B", "NS/A"]);
+    assert!(errors.is_empty());
+  }
+
+  #[test]
+  fn higher_priority_loads_first() {
+    let mut plugins = plugins(&[
+      ("NS/A", ""),
+      ("NS/B", "LoadPriority = 10\n"),
+      ("NS/C", "LoadPriority = -5\n"),
+      ("NS/D", ""),
+    ]);
+    let mut errors = BTreeMap::new();
+
+    let order = resolve_load_order(&mut plugins, &mut errors);
+    assert_eq!(order, vec!["NS/B", "NS/A", "NS/D", "NS/C"]);
+  }
+
+  #[test]
+  fn soft_cycles_are_broken_by_priority() {
+    let mut plugins = plugins(&[
+      ("NS/A", "LoadAfter = [\"NS/B\"]\n"),
+      ("NS/B", "LoadPriority = 1\nLoadAfter = [\"NS/A\"]\n"),
+    ]);
+    let mut errors = BTreeMap::new();
+
+    assert_eq!(resolve_load_order(&mut plugins, &mut errors), vec!["NS/B", "NS/A"]);
+    assert!(errors.is_empty());
+  }
+
+  #[test]
+  fn soft_cycles_are_not_broken_at_hard_dependents() {
+    let mut plugins = plugins(&[
+      ("NS/A", "[OptionalDependencies]\n\"NS/B\" = \"*\"\n"),
+      ("NS/B", "LoadAfter = [\"NS/A\"]\n"),
+      ("NS/C", "LoadPriority = 100\n[Dependencies]\n\"NS/A\" = \"*\"\n"),
+    ]);
+    let mut errors = BTreeMap::new();
+
+    let order = resolve_load_order(&mut plugins, &mut errors);
+    let position = |ident| order.iter().position(|id| id == ident).unwrap();
+    assert_eq!(order.len(), 3);
+    assert!(position("NS/A") < position("NS/C"), "{:?}", order);
+  }
+
+  #[test]
+  fn only_hard_dependents_are_affected() {
+    let loaded: Vec<Plugin> = plugins(&[
+      ("NS/A", ""),
+      ("NS/B", "[Dependencies]\n\"NS/A\" = \"*\"\n"),
+      ("NS/C", "[OptionalDependencies]\n\"NS/A\" = \"*\"\n"),
+      ("NS/D", "[Dependencies]\n\"NS/B\" = \"*\"\n"),
+      ("NS/E", "[Dependencies]\n\"NS/C\" = \"*\"\n"),
+    ]).into_iter().map(|(_, plugin)| plugin).collect();
+
+    assert_eq!(hard_dependents(&loaded, "NS/A"), vec!["NS/B", "NS/D"]);
+    assert!(hard_dependents(&loaded, "NS/D").is_empty());
+  }
+}
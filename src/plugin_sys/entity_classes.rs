@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_int, c_float};
+use std::sync::Mutex;
+use rlua::FromLua;
+use crate::ffi_wrapper::{
+  create_game_entity,
+  engine_string,
+  engine_time,
+  game_spawn,
+  set_classname,
+  set_next_think,
+};
+use crate::ffi_wrapper::hl_lua_bridge::EntityHandle;
+use crate::global_state::GlobalState;
+use crate::lua_helpers::call_plugin_lua;
+use crate::meta_ffi::types::{Edict, KeyValueData};
+
+struct EntityClass {
+  owner: String,
+  // The game class the entities are really made of
+  base: String,
+  // Table of callbacks, see `luna_lib::entities`
+  callbacks: rlua::RegistryKey,
+}
+
+/// Entity classes implemented by plugins, and which entities are of them.
+pub struct EntityClassRegistry {
+  classes: HashMap<String, EntityClass>,
+  // Lua class of every entity that has one, by `EntityHandle::key`. Entities
+  // keep their class when its plugin unloads, so they come back to life
+  // when it's loaded again.
+  entities: HashMap<i64, String>,
+}
+
+impl EntityClassRegistry {
+  pub fn new() -> Self {
+    EntityClassRegistry {
+      classes: HashMap::new(),
+      entities: HashMap::new(),
+    }
+  }
+
+  pub fn register_class<'lua>(
+    &mut self,
+    ctx: &rlua::Context<'lua>,
+    owner: &str,
+    name: &str,
+    base: &str,
+    callbacks: rlua::Table<'lua>,
+  ) -> Result<(), String> {
+    if let Some(class) = self.classes.get(name) {
+      if class.owner != owner {
+        return Err(format!(
+          "Entity class \"{}\" is already registered by \"{}\"", name, class.owner,
+        ));
+      }
+    }
+
+    if base.contains('\0') {
+      return Err(format!("Invalid base class \"{}\"", base));
+    }
+    self.classes.insert(name.to_string(), EntityClass {
+      owner: owner.to_string(),
+      base: base.to_string(),
+      callbacks: ctx.create_registry_value(callbacks).unwrap(),
+    });
+
+    Ok(())
+  }
+
+  /// Removes every class that was registered by the plugin `owner`.
+  pub fn remove_plugin_classes(&mut self, owner: &str) {
+    self.classes.retain(|_, class| class.owner != owner);
+  }
+
+  pub fn is_class(&self, name: &str) -> bool {
+    self.classes.contains_key(name)
+  }
+
+  fn set_class(&mut self, entity: &EntityHandle, name: &str) {
+    if let Some(key) = entity.key() {
+      self.entities.insert(key, name.to_string());
+    }
+  }
+
+  /// Forgets the class of `entity`. Called when the engine frees it.
+  pub fn remove_entity(&mut self, entity: &EntityHandle) {
+    if let Some(key) = entity.key() {
+      self.entities.remove(&key);
+    }
+  }
+
+  // The owner of the class of `entity` and its callback `name`, if it has
+  // one.
+  fn callback<'lua>(
+    &self,
+    ctx: &rlua::Context<'lua>,
+    entity: &EntityHandle,
+    name: &str,
+  ) -> Option<(String, rlua::Function<'lua>)> {
+    let class = self.classes.get(self.entities.get(&entity.key()?)?)?;
+    let callbacks: rlua::Table = ctx.registry_value(&class.callbacks).ok()?;
+    let callback = callbacks.get::<_, Option<rlua::Function>>(name).ok()??;
+    Some((class.owner.clone(), callback))
+  }
+}
+
+/// Creates an entity of the Lua class `name`. It still has to be spawned.
+pub fn create_entity(state: &Mutex<GlobalState>, name: &str) -> Option<EntityHandle> {
+  let base = state.lock().unwrap()
+    .entity_classes.classes.get(name)?
+    .base.clone();
+
+  let entity = EntityHandle::new(create_game_entity(&base)?);
+  set_classname(entity.get()?, name);
+  state.lock().unwrap().entity_classes.set_class(&entity, name);
+  Some(entity)
+}
+
+// Looks up the callback and calls it without holding the state.
+fn call_callback<'lua, TParams, TReturn>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  entity: &EntityHandle,
+  name: &str,
+  params: TParams,
+) -> Option<rlua::Result<TReturn>>
+where
+  TParams: rlua::ToLuaMulti<'lua>,
+  TReturn: rlua::FromLuaMulti<'lua>,
+{
+  let (owner, callback) = state.lock().unwrap()
+    .entity_classes.callback(ctx, entity, name)?;
+  Some(call_plugin_lua(ctx, &owner, &callback, params))
+}
+
+/// Runs while the engine loads the map's entities. When the class of an
+/// entity is a Lua class, the engine is told that it's the base class
+/// instead, or it would refuse to create it. Returns `true` if the key
+/// was taken care of.
+pub fn key_value<'lua>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  edict: &Edict,
+  data: &mut KeyValueData,
+) -> bool {
+  let entity = EntityHandle::new(edict);
+  let (key, value) = unsafe {
+    if data.key.is_null() || data.value.is_null() {
+      return false;
+    }
+    (
+      CStr::from_ptr(data.key).to_string_lossy().into_owned(),
+      CStr::from_ptr(data.value).to_string_lossy().into_owned(),
+    )
+  };
+
+  if key == "classname" {
+    let mut state = state.lock().unwrap();
+    let registry = &mut state.entity_classes;
+    if let Some(class) = registry.classes.get(&value) {
+      // The data only points to the value, so it has to outlive the call
+      data.value = engine_string(&class.base) as *mut _;
+      registry.set_class(&entity, &value);
+    }
+    return false;
+  }
+
+  match call_callback::<_, bool>(ctx, state, &entity, "KeyValue", (entity.clone(), key, value)) {
+    Some(Ok(true)) => {
+      data.handled = 1;
+      true
+    }
+    _ => false,
+  }
+}
+
+/// Spawns an entity of a Lua class, the base class first and then `Spawn`
+/// of the class if it has one. `None` if the entity isn't of a Lua class.
+/// `Some(-1)` removes the entity.
+pub fn spawn<'lua>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  edict: &Edict,
+) -> Option<c_int> {
+  let entity = EntityHandle::new(edict);
+  let name = state.lock().unwrap()
+    .entity_classes.entities.get(&entity.key()?)?.clone();
+
+  let result = game_spawn(edict);
+  if result == -1 {
+    return Some(-1);
+  }
+
+  // Map entities still carry the name of the base class, and some classes
+  // set their name while spawning
+  set_classname(edict, &name);
+
+  match call_callback::<_, rlua::Value>(ctx, state, &entity, "Spawn", entity.clone()) {
+    Some(Ok(rlua::Value::Boolean(false))) => Some(-1),
+    _ => Some(result),
+  }
+}
+
+/// Calls `Think`. If it returns a number, the entity thinks again after
+/// that many seconds. Returns `false` if the entity isn't of a Lua class
+/// with a `Think`.
+pub fn think<'lua>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  edict: &Edict,
+) -> bool {
+  let entity = EntityHandle::new(edict);
+  let result = match call_callback::<_, rlua::Value>(ctx, state, &entity, "Think", entity.clone()) {
+    Some(result) => result,
+    None => return false,
+  };
+
+  if let Ok(delay) = result.and_then(|value| Option::<c_float>::from_lua(value, *ctx)) {
+    if let (Some(delay), Some(edict)) = (delay, entity.get()) {
+      set_next_think(edict, engine_time() + delay);
+    }
+  }
+  true
+}
+
+/// Calls the callback `name` with the two entities of a `Touch`, `Use` or
+/// `Blocked`. Returns `false` if `edict` isn't of a Lua class with it.
+pub fn interact<'lua>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  name: &str,
+  edict: &Edict,
+  other: Option<&Edict>,
+) -> bool {
+  let entity = EntityHandle::new(edict);
+  let other = other.map(EntityHandle::new);
+  call_callback::<_, ()>(ctx, state, &entity, name, (entity.clone(), other)).is_some()
+}
@@ -0,0 +1,65 @@
+use crate::ffi_wrapper::hl_lua_bridge::EntityHandle;
+
+// Table in the registry with a table per plugin that maps entity keys to the
+// plugin's data for that entity
+const ENTITY_DATA_KEY: &str = "luna_entity_data";
+
+fn data_tables<'lua>(ctx: &rlua::Context<'lua>) -> rlua::Result<rlua::Table<'lua>> {
+  match ctx.named_registry_value::<_, Option<rlua::Table>>(ENTITY_DATA_KEY)? {
+    Some(tables) => Ok(tables),
+    None => {
+      let tables = ctx.create_table()?;
+      ctx.set_named_registry_value(ENTITY_DATA_KEY, tables.clone())?;
+      Ok(tables)
+    }
+  }
+}
+
+/// The table the plugin `owner` keeps its data for `entity` in. It's
+/// created on first use and lives until the entity is freed.
+pub fn get_data<'lua>(
+  ctx: &rlua::Context<'lua>,
+  owner: &str,
+  entity: &EntityHandle,
+) -> rlua::Result<rlua::Table<'lua>> {
+  let key = entity.key()
+    .filter(|_| entity.is_valid())
+    .ok_or_else(|| rlua::Error::RuntimeError("Invalid entity".into()))?;
+
+  let tables = data_tables(ctx)?;
+  let plugin_data = match tables.raw_get::<_, Option<rlua::Table>>(owner)? {
+    Some(plugin_data) => plugin_data,
+    None => {
+      let plugin_data = ctx.create_table()?;
+      tables.raw_set(owner, plugin_data.clone())?;
+      plugin_data
+    }
+  };
+
+  match plugin_data.raw_get::<_, Option<rlua::Table>>(key)? {
+    Some(data) => Ok(data),
+    None => {
+      let data = ctx.create_table()?;
+      plugin_data.raw_set(key, data.clone())?;
+      Ok(data)
+    }
+  }
+}
+
+/// Drops every plugin's data for `entity`. Called when the engine frees it.
+pub fn clear_entity(ctx: &rlua::Context, entity: &EntityHandle) -> rlua::Result<()> {
+  let key = match entity.key() {
+    Some(key) => key,
+    None => return Ok(()),
+  };
+
+  for pair in data_tables(ctx)?.pairs::<rlua::Value, rlua::Table>() {
+    pair?.1.raw_set(key, rlua::Nil)?;
+  }
+  Ok(())
+}
+
+/// Drops all the data the plugin `owner` attached to entities.
+pub fn remove_plugin_data(ctx: &rlua::Context, owner: &str) -> rlua::Result<()> {
+  data_tables(ctx)?.raw_set(owner, rlua::Nil)
+}
@@ -1,8 +1,17 @@
 use std::collections::HashMap;
-use crate::lua_helpers::call_lua;
+use std::sync::Mutex;
+use crate::global_state::GlobalState;
+use crate::lua_helpers::call_plugin_lua;
+use crate::meta_ffi::types::MetaResult;
+use super::tasks;
+
+struct Listener {
+  owner: String,
+  key: rlua::RegistryKey,
+}
 
 pub struct LuaEventEmitter {
-  handlers: HashMap<String, Vec<rlua::RegistryKey>>,
+  handlers: HashMap<String, Vec<Listener>>,
 }
 
 impl LuaEventEmitter {
@@ -12,11 +21,20 @@ impl LuaEventEmitter {
     }
   }
 
-  pub fn add_listener<'lua>(&mut self, ctx: &rlua::Context<'lua>, event_name: &str, func: rlua::Function<'lua>) {
+  pub fn add_listener<'lua>(
+    &mut self,
+    ctx: &rlua::Context<'lua>,
+    owner: &str,
+    event_name: &str,
+    func: rlua::Function<'lua>,
+  ) {
     if self.listener_exists(ctx, event_name, &func).is_none() {
       let key = ctx.create_registry_value(func).unwrap();
       let listeners = self.handlers.entry(event_name.to_string()).or_default();
-      listeners.push(key);
+      listeners.push(Listener {
+        owner: owner.to_string(),
+        key,
+      });
     }
   }
 
@@ -28,22 +46,25 @@ impl LuaEventEmitter {
     }
   }
 
-  pub fn emit<'lua, TParams>(
+  /// Removes every listener that was added by the plugin `owner`.
+  pub fn remove_plugin_listeners(&mut self, owner: &str) {
+    self.handlers
+      .values_mut()
+      .for_each(|listeners| listeners.retain(|l| l.owner != owner));
+  }
+
+  /// Listeners of `event_name` along with the plugins that added them.
+  pub fn listeners<'lua>(
     &self,
     ctx: &rlua::Context<'lua>,
     event_name: &str,
-    params: TParams,
-  ) -> rlua::Result<()>
-  where
-    TParams: rlua::ToLuaMulti<'lua> + Clone,
-  {
-    if let Some(listeners) = self.handlers.get(event_name) {
-      listeners
+  ) -> Vec<(String, rlua::Function<'lua>)> {
+    match self.handlers.get(event_name) {
+      Some(listeners) => listeners
         .iter()
-        .map(|key| ctx.registry_value::<rlua::Function>(key).unwrap())
-        .try_for_each(|f| call_lua::<_, ()>(&ctx, &f, params.clone()))
-    } else {
-      Ok(())
+        .map(|l| (l.owner.clone(), ctx.registry_value::<rlua::Function>(&l.key).unwrap()))
+        .collect(),
+      None => Vec::new(),
     }
   }
 
@@ -63,7 +84,7 @@ impl LuaEventEmitter {
     if let Some(listeners_vec) = listeners_vec {
       listeners_vec
         .iter()
-        .map(|f| ctx.registry_value::<rlua::Function>(f))
+        .map(|l| ctx.registry_value::<rlua::Function>(&l.key))
         .map(Result::unwrap)
         .position(|f| funcs_equal(&f, &func))
     } else {
@@ -71,3 +92,124 @@ impl LuaEventEmitter {
     }
   }
 }
+
+/// Calls every listener of `event_name`, then resumes the tasks waiting for
+/// it. The state is only locked while the listeners are collected so that
+/// they are free to add or remove listeners themselves. A listener that
+/// fails is reported and doesn't keep the others from being called.
+pub fn emit<'lua, TParams>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  event_name: &str,
+  params: TParams,
+) -> rlua::Result<()>
+where
+  TParams: rlua::ToLuaMulti<'lua> + Clone,
+{
+  emit_collect::<_, ()>(ctx, state, event_name, params)?;
+  Ok(())
+}
+
+/// Like `emit`, but for events that come from Metamod hooks. Listeners can
+/// return a `Luna/Meta` result and, just like with Metamod plugins, the
+/// highest one wins. Every listener is called either way.
+pub fn emit_hook<'lua, TParams>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  event_name: &str,
+  params: TParams,
+) -> rlua::Result<MetaResult>
+where
+  TParams: rlua::ToLuaMulti<'lua> + Clone,
+{
+  let results: Vec<MetaResult> = emit_collect(ctx, state, event_name, params)?;
+  Ok(combine_results(results))
+}
+
+/// The result of a hook given what each listener returned.
+pub fn combine_results(results: impl IntoIterator<Item = MetaResult>) -> MetaResult {
+  results.into_iter().fold(MetaResult::Ignored, MetaResult::max)
+}
+
+/// Calls every listener of `event_name` and returns what each of them
+/// returned, in the order they were called. Listeners that fail are left
+/// out.
+pub fn emit_collect<'lua, TParams, TReturn>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  event_name: &str,
+  params: TParams,
+) -> rlua::Result<Vec<TReturn>>
+where
+  TParams: rlua::ToLuaMulti<'lua> + Clone,
+  TReturn: rlua::FromLuaMulti<'lua>,
+{
+  let listeners = state.lock().unwrap().listeners.listeners(ctx, event_name);
+  let results = listeners
+    .iter()
+    .filter_map(|(owner, f)| call_plugin_lua(ctx, owner, f, params.clone()).ok())
+    .collect();
+
+  tasks::resume_event_waiters(ctx, state, event_name, params.to_lua_multi(*ctx)?);
+  Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn add_listeners(lua: &rlua::Lua, state: &Mutex<GlobalState>, event_name: &str, sources: &[&str]) {
+    lua.context(|ctx| {
+      ctx.globals().raw_set("luna_call_level", 0).unwrap();
+      let mut state = state.lock().unwrap();
+      for source in sources {
+        let func = ctx.load(source).eval().unwrap();
+        state.listeners.add_listener(&ctx, "test", event_name, func);
+      }
+    });
+  }
+
+  #[test]
+  fn highest_result_wins() {
+    assert!(combine_results(Vec::new()) == MetaResult::Ignored);
+    assert!(combine_results(vec![MetaResult::Handled, MetaResult::Ignored]) == MetaResult::Handled);
+    assert!(combine_results(vec![
+      MetaResult::Override,
+      MetaResult::Supercede,
+      MetaResult::Handled,
+    ]) == MetaResult::Supercede);
+  }
+
+  #[test]
+  fn failing_listeners_dont_stop_the_others() {
+    let lua = rlua::Lua::new();
+    let state = Mutex::new(GlobalState::new());
+    add_listeners(&lua, &state, "Hook", &[
+      "function() return 2 end",
+      "function() error('failed') end",
+      "function() return 'not a result' end",
+      "function() return 3 end",
+    ]);
+
+    lua.context(|ctx| {
+      let results: Vec<MetaResult> = emit_collect(&ctx, &state, "Hook", ()).unwrap();
+      assert!(results == vec![MetaResult::Handled, MetaResult::Override]);
+      assert!(emit_hook(&ctx, &state, "Hook", ()).unwrap() == MetaResult::Override);
+    });
+  }
+
+  #[test]
+  fn every_listener_is_called() {
+    let lua = rlua::Lua::new();
+    let state = Mutex::new(GlobalState::new());
+    add_listeners(&lua, &state, "Event", &[
+      "function() calls = (calls or 0) + 1; error('failed') end",
+      "function() calls = (calls or 0) + 1 end",
+    ]);
+
+    lua.context(|ctx| {
+      emit(&ctx, &state, "Event", ()).unwrap();
+      assert_eq!(ctx.globals().get::<_, i64>("calls").unwrap(), 2);
+    });
+  }
+}
@@ -1,2 +1,31 @@
 pub mod core;
 pub mod listeners;
+pub mod commands;
+pub mod meta;
+pub mod timers;
+pub mod tasks;
+pub mod globals;
+pub mod math;
+pub mod players;
+pub mod entities;
+pub mod virtual_hooks;
+pub mod private_data;
+
+/// Creates an instance of a library whose functions need to know which
+/// plugin they were called from, e.g. to clean up after it when it unloads.
+/// Returns `None` if `name` isn't such a library.
+pub fn create_plugin_lib<'lua>(
+  ctx: &rlua::Context<'lua>,
+  name: &str,
+  owner: &str,
+) -> Option<rlua::Table<'lua>> {
+  match name {
+    "Luna/Listeners" => Some(listeners::create_lib(ctx, owner)),
+    "Luna/Commands" => Some(commands::create_lib(ctx, owner)),
+    "Luna/Timers" => Some(timers::create_lib(ctx, owner)),
+    "Luna/Tasks" => Some(tasks::create_lib(ctx, owner)),
+    "Luna/Entities" => Some(entities::create_lib(ctx, owner)),
+    "Luna/VirtualHooks" => Some(virtual_hooks::create_lib(ctx, owner)),
+    _ => None,
+  }
+}
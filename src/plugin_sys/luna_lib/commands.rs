@@ -0,0 +1,68 @@
+use crate::ffi_wrapper::{command_args, command_arg_string};
+use crate::global_state::GlobalStateUserData;
+
+pub fn create_lib<'lua>(
+  ctx: &rlua::Context<'lua>,
+  owner: &str,
+) -> rlua::Table<'lua> {
+  let lib_commands: rlua::Table = ctx.create_table().unwrap();
+
+  let register_owner = owner.to_string();
+  let register = ctx.create_function(
+    move |ctx, params| register(ctx, &register_owner, params)
+  ).unwrap();
+  let unregister_owner = owner.to_string();
+  let unregister = ctx.create_function(
+    move |ctx, name| unregister(ctx, &unregister_owner, name)
+  ).unwrap();
+
+  lib_commands.raw_set("Register", register).unwrap();
+  lib_commands.raw_set("Unregister", unregister).unwrap();
+  lib_commands.raw_set("GetArgCount", ctx.create_function(get_arg_count).unwrap()).unwrap();
+  lib_commands.raw_set("GetArg", ctx.create_function(get_arg).unwrap()).unwrap();
+  lib_commands.raw_set("GetArgString", ctx.create_function(get_arg_string).unwrap()).unwrap();
+
+  lib_commands
+}
+
+pub fn register<'lua>(
+  ctx: rlua::Context<'lua>,
+  owner: &str,
+  params: (String, rlua::Function<'lua>),
+) -> rlua::Result<()> {
+  let name = params.0;
+  let handler = params.1;
+
+  let globals = ctx.globals();
+  let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
+  let mut state = state.0.lock().unwrap();
+  state.commands
+    .add_command(&ctx, owner, &name, handler)
+    .map_err(rlua::Error::RuntimeError)
+}
+
+pub fn unregister(
+  ctx: rlua::Context,
+  owner: &str,
+  name: String,
+) -> rlua::Result<bool> {
+  let globals = ctx.globals();
+  let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
+  let mut state = state.0.lock().unwrap();
+  Ok(state.commands.remove_command(owner, &name))
+}
+
+// These mirror the engine's Cmd_Argc, Cmd_Argv and Cmd_Args and are only
+// meaningful while a command is being handled.
+
+pub fn get_arg_count(_: rlua::Context, _: ()) -> rlua::Result<usize> {
+  Ok(command_args().len())
+}
+
+pub fn get_arg(_: rlua::Context, index: usize) -> rlua::Result<Option<String>> {
+  Ok(command_args().into_iter().nth(index))
+}
+
+pub fn get_arg_string(_: rlua::Context, _: ()) -> rlua::Result<String> {
+  Ok(command_arg_string())
+}
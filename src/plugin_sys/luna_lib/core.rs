@@ -4,7 +4,9 @@ use std::path::Path;
 use crate::plugin_sys::plugin::Plugin;
 use crate::plugin_sys::get_identifier_from_path;
 use crate::ffi_wrapper::log_console;
+use crate::global_state::GlobalStateUserData;
 use crate::lua_helpers;
+use super::create_plugin_lib;
 
 // TODO: Possibly take &rlua::Context in `from_plugin` and create plugin info
 // tables in the registry.
@@ -20,6 +22,7 @@ pub struct PluginHandle {
   pub identifier: String,
   pub info: PluginInfoHandle,
   pub metadata: HashMap<String, String>,
+  pub optional_dependencies: HashMap<String, bool>,
 }
 
 
@@ -35,6 +38,10 @@ impl PluginHandle {
         authors: info.authors.clone(),
       },
       metadata: plugin.metadata().clone(),
+      optional_dependencies: plugin.optional_dependencies()
+        .keys()
+        .map(|ident| (ident.clone(), plugin.has_optional_dependency(ident)))
+        .collect(),
     }
   }
 }
@@ -70,6 +77,15 @@ impl rlua::UserData for PluginHandle {
       );
       ctx.create_table_from(iter)
     });
+    methods.add_method("GetOptionalDependencies", |ctx: rlua::Context, handle: &Self, ()| {
+      let iter = handle.optional_dependencies.iter().map(
+        |(k, v)| (k.as_str(), *v)
+      );
+      ctx.create_table_from(iter)
+    });
+    methods.add_method("HasOptionalDependency", |_, handle: &Self, identifier: String| {
+      Ok(handle.optional_dependencies.get(&identifier).cloned().unwrap_or(false))
+    });
   }
 }
 
@@ -134,6 +150,14 @@ pub fn require_with_context(base: &Path, current: &Path, plugin_key: Arc<rlua::R
 
       let local_file_path = file_path.strip_prefix(&base).unwrap();
       let identifier = get_identifier_from_path(&base);
+
+      let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
+      state.0.lock().unwrap()
+        .required_files
+        .entry(identifier.clone())
+        .or_default()
+        .insert(file_path.clone());
+
       let name = format!("{}::{}", identifier, local_file_path.display());
       let chunk = ctx.load(&contents)
                   .set_name(&name).unwrap()
@@ -156,6 +180,11 @@ pub fn require_with_context(base: &Path, current: &Path, plugin_key: Arc<rlua::R
         ctx.create_table()
       }
     } else {
+      let identifier = get_identifier_from_path(&base);
+      if let Some(plugin_lib) = create_plugin_lib(&ctx, &lib, &identifier) {
+        return Ok(plugin_lib);
+      }
+
       let libs_table: rlua::Table = globals.raw_get("luna_libs").unwrap();
       libs_table.raw_get(lib)
     }
@@ -170,6 +199,19 @@ pub fn forbid_index(
   Err(rlua::Error::RuntimeError(format!("Attempt to access global {}", key)))
 }
 
+pub fn reload_plugin(ctx: rlua::Context, identifier: String) -> rlua::Result<()> {
+  // The plugin (and possibly the caller itself) can't be reloaded while Lua
+  // code is running, so this only happens at the start of the next frame.
+  let globals = ctx.globals();
+  let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
+  let mut state = state.0.lock().unwrap();
+  if !state.pending_reloads.contains(&identifier) {
+    state.pending_reloads.push(identifier);
+  }
+
+  Ok(())
+}
+
 pub fn print_to_console(_: rlua::Context, message: String) -> rlua::Result<()> {
   log_console(message);
   Ok(())
@@ -0,0 +1,114 @@
+use std::os::raw::{c_int, c_float};
+use crate::ffi_wrapper::{
+  create_named_entity,
+  edict_of_index,
+  find_entity_by_string,
+  find_entity_in_sphere,
+};
+use crate::ffi_wrapper::hl_lua_bridge::EntityHandle;
+use crate::meta_ffi::types::{Edict, EngineVector3};
+use crate::global_state::GlobalStateUserData;
+use crate::plugin_sys::{entity_data, entity_classes};
+
+// Game class Lua entity classes are made of unless they say otherwise
+const DEFAULT_BASE_CLASS: &str = "info_target";
+
+/// `Luna/Entities` creates entities and searches the engine's entity list.
+/// The `Find` functions are iterators for a generic `for`:
+///
+/// ```lua
+/// for spawn in Entities.FindByClassname('info_player_start') do
+///   ...
+/// end
+/// ```
+///
+/// `Data(entity)` is a table the plugin can keep its own state for the
+/// entity in. Other plugins have their own, and it's dropped once the
+/// engine frees the entity.
+///
+/// `RegisterClass(classname, class)` implements a new entity class, which
+/// works for `Create` as well as entities placed in maps. Every field of
+/// `class` is optional:
+///
+/// - `Base`, the game class the entity really is, `info_target` by default
+/// - `Spawn(entity)`, runs after the base class spawned the entity,
+///   returning `false` removes it
+/// - `Think(entity)`, returning a number thinks again after that many seconds
+/// - `Touch(entity, other)`, `Use(entity, other)` and `Blocked(entity, other)`
+/// - `KeyValue(entity, key, value)`, returning `true` handles the key
+///
+/// The base class handles whatever the class doesn't.
+pub fn create_lib<'lua>(
+  ctx: &rlua::Context<'lua>,
+  owner: &str,
+) -> rlua::Table<'lua> {
+  let lib_entities: rlua::Table = ctx.create_table().unwrap();
+
+  let create = ctx.create_function(|ctx, classname: String| {
+    let state: GlobalStateUserData = ctx.globals().get("luna_global_state").unwrap();
+    if state.0.lock().unwrap().entity_classes.is_class(&classname) {
+      return Ok(entity_classes::create_entity(&state.0, &classname));
+    }
+    Ok(create_named_entity(&classname).map(EntityHandle::new))
+  }).unwrap();
+  let class_owner = owner.to_string();
+  let register_class = ctx.create_function(
+    move |ctx, (classname, class): (String, rlua::Table)| {
+      let base = class.get::<_, Option<String>>("Base")?
+        .unwrap_or_else(|| DEFAULT_BASE_CLASS.to_string());
+
+      let state: GlobalStateUserData = ctx.globals().get("luna_global_state").unwrap();
+      let mut state = state.0.lock().unwrap();
+      state.entity_classes
+        .register_class(&ctx, &class_owner, &classname, &base, class)
+        .map_err(rlua::Error::RuntimeError)
+    }
+  ).unwrap();
+  let from_index = ctx.create_function(|_, index: c_int| {
+    Ok(edict_of_index(index).map(EntityHandle::new))
+  }).unwrap();
+  let find_by_classname = ctx.create_function(|ctx, classname: String| {
+    find_by_string(ctx, "classname", classname)
+  }).unwrap();
+  let find_by_targetname = ctx.create_function(|ctx, targetname: String| {
+    find_by_string(ctx, "targetname", targetname)
+  }).unwrap();
+  let find_in_sphere = ctx.create_function(
+    |ctx, (origin, radius): (EngineVector3, c_float)| {
+      ctx.create_function(move |_, (_, previous): (rlua::Value, Option<EntityHandle>)| {
+        Ok(find_entity_in_sphere(start_of(previous), origin, radius).map(EntityHandle::new))
+      })
+    }
+  ).unwrap();
+
+  let data_owner = owner.to_string();
+  let data = ctx.create_function(move |ctx, entity: EntityHandle| {
+    entity_data::get_data(&ctx, &data_owner, &entity)
+  }).unwrap();
+
+  lib_entities.raw_set("Create", create).unwrap();
+  lib_entities.raw_set("RegisterClass", register_class).unwrap();
+  lib_entities.raw_set("FromIndex", from_index).unwrap();
+  lib_entities.raw_set("FindByClassname", find_by_classname).unwrap();
+  lib_entities.raw_set("FindByTargetname", find_by_targetname).unwrap();
+  lib_entities.raw_set("FindInSphere", find_in_sphere).unwrap();
+  lib_entities.raw_set("Data", data).unwrap();
+
+  lib_entities
+}
+
+// The loop variable is passed back to the iterator, the search carries on
+// after it. That works even if the entity was removed inside the loop.
+fn start_of(previous: Option<EntityHandle>) -> *mut Edict {
+  previous.map_or(std::ptr::null_mut(), |previous| previous.edict_ptr())
+}
+
+fn find_by_string<'lua>(
+  ctx: rlua::Context<'lua>,
+  field: &'static str,
+  value: String,
+) -> rlua::Result<rlua::Function<'lua>> {
+  ctx.create_function(move |_, (_, previous): (rlua::Value, Option<EntityHandle>)| {
+    Ok(find_entity_by_string(start_of(previous), field, &value).map(EntityHandle::new))
+  })
+}
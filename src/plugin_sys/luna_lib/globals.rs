@@ -0,0 +1,24 @@
+use crate::ffi_wrapper::hl_lua_bridge::{get_global_var, set_global_var};
+
+/// `Luna/Globals` is an empty table whose metatable reads and writes the
+/// engine's `globalvars_t`, e.g. `Globals.time` or `Globals.maxClients`.
+pub fn create_lib<'lua>(ctx: &rlua::Context<'lua>) -> rlua::Table<'lua> {
+  let lib_globals: rlua::Table = ctx.create_table().unwrap();
+  let lib_mt: rlua::Table = ctx.create_table().unwrap();
+
+  let index = ctx.create_function(
+    |ctx, (_, key): (rlua::Table, String)| get_global_var(ctx, &key)
+  ).unwrap();
+  let newindex = ctx.create_function(
+    |ctx, (_, key, value): (rlua::Table, String, rlua::Value)| {
+      set_global_var(ctx, &key, value)
+    }
+  ).unwrap();
+
+  lib_mt.raw_set("__index", index).unwrap();
+  lib_mt.raw_set("__newindex", newindex).unwrap();
+  lib_mt.raw_set("__metatable", false).unwrap();
+  lib_globals.set_metatable(Some(lib_mt));
+
+  lib_globals
+}
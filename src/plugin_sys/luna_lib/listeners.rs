@@ -1,7 +1,37 @@
 use crate::global_state::GlobalStateUserData;
 
+pub const EVENTS: &[&str] = &[
+  "ClientConnect", "PreClientPutInServer", "ClientPutInServer", "ClientDisconnect", "ClientDisconnected",
+  "ClientCommand",
+  "PluginsLoaded", "PluginsWillUnload", "PluginsUnload",
+  "PluginLoaded", "PluginWillUnload", "PluginUnload",
+];
+
+pub fn create_lib<'lua>(
+  ctx: &rlua::Context<'lua>,
+  owner: &str,
+) -> rlua::Table<'lua> {
+  let lib_listeners: rlua::Table = ctx.create_table().unwrap();
+
+  let events_enum = ctx.create_table_from(
+    EVENTS.iter().map(|&x| x).zip(EVENTS.iter().map(|&x| x))
+  ).unwrap();
+
+  let owner = owner.to_string();
+  let add_listener = ctx.create_function(
+    move |ctx, params| add_listener(ctx, &owner, params)
+  ).unwrap();
+  let remove_listener = ctx.create_function(remove_listener).unwrap();
+  lib_listeners.raw_set("On", add_listener).unwrap();
+  lib_listeners.raw_set("Off", remove_listener).unwrap();
+  lib_listeners.raw_set("Events", events_enum).unwrap();
+
+  lib_listeners
+}
+
 pub fn add_listener<'lua>(
   ctx: rlua::Context<'lua>,
+  owner: &str,
   params: (String, rlua::Function<'lua>)
 ) -> Result<(), rlua::Error> {
   let event_name = params.0;
@@ -10,7 +40,7 @@ pub fn add_listener<'lua>(
   let globals = ctx.globals();
   let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
   let mut state = state.0.lock().unwrap();
-  state.listeners.add_listener(&ctx, &event_name, listener);
+  state.listeners.add_listener(&ctx, owner, &event_name, listener);
 
   Ok(())
 }
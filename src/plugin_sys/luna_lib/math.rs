@@ -0,0 +1,28 @@
+use crate::ffi_wrapper::vector::Vector;
+use crate::meta_ffi::types::EngineVector3;
+
+/// `Vector(x, y, z)`, `Vector({ x, y, z })` or `Vector()` for the zero
+/// vector. Missing components are zero.
+pub fn vector<'lua>(
+  ctx: rlua::Context<'lua>,
+  params: rlua::MultiValue<'lua>,
+) -> rlua::Result<Vector> {
+  use rlua::FromLua;
+
+  let mut params = params.into_iter();
+  match params.next() {
+    Some(value @ rlua::Value::Table(_)) | Some(value @ rlua::Value::UserData(_)) => {
+      Ok(Vector(EngineVector3::from_lua(value, ctx)?))
+    }
+    first => {
+      let component = |value: Option<rlua::Value<'lua>>| match value {
+        None | Some(rlua::Nil) => Ok(0.0),
+        Some(value) => f32::from_lua(value, ctx),
+      };
+      let x = component(first)?;
+      let y = component(params.next())?;
+      let z = component(params.next())?;
+      Ok(Vector::new(x, y, z))
+    }
+  }
+}
@@ -0,0 +1,75 @@
+use crate::ffi_wrapper::{
+  previous_meta_result,
+  meta_orig_return,
+  meta_override_return,
+};
+use crate::meta_ffi::types::MetaResult;
+
+const RESULTS: &[(&str, MetaResult)] = &[
+  ("Ignored", MetaResult::Ignored),
+  ("Handled", MetaResult::Handled),
+  ("Override", MetaResult::Override),
+  ("Supercede", MetaResult::Supercede),
+];
+
+// Listeners may return nothing, a `Luna/Meta` result, or `true` to block
+// the call like they could before results existed.
+impl<'lua> rlua::FromLua<'lua> for MetaResult {
+  fn from_lua(value: rlua::Value<'lua>, _: rlua::Context<'lua>) -> rlua::Result<Self> {
+    match value {
+      rlua::Value::Nil | rlua::Value::Boolean(false) => Ok(MetaResult::Ignored),
+      rlua::Value::Boolean(true) => Ok(MetaResult::Supercede),
+      rlua::Value::Integer(n) => RESULTS.iter()
+        .map(|&(_, result)| result)
+        .find(|&result| result as i64 == n)
+        .ok_or_else(|| rlua::Error::FromLuaConversionError {
+          from: "integer",
+          to: "MetaResult",
+          message: Some(format!("{} is not a valid result", n)),
+        }),
+      _ => Err(rlua::Error::FromLuaConversionError {
+        from: "value",
+        to: "MetaResult",
+        message: Some("expected a Luna/Meta result".to_string()),
+      }),
+    }
+  }
+}
+
+impl<'lua> rlua::ToLua<'lua> for MetaResult {
+  fn to_lua(self, _: rlua::Context<'lua>) -> rlua::Result<rlua::Value<'lua>> {
+    Ok(rlua::Value::Integer(self as i64))
+  }
+}
+
+pub fn create_lib<'lua>(ctx: &rlua::Context<'lua>) -> rlua::Table<'lua> {
+  let lib_meta: rlua::Table = ctx.create_table().unwrap();
+
+  let results_enum = ctx.create_table_from(RESULTS.iter().copied()).unwrap();
+  lib_meta.raw_set("Result", results_enum).unwrap();
+
+  let get_previous_result = ctx.create_function(get_previous_result).unwrap();
+  let get_original_return = ctx.create_function(get_original_return).unwrap();
+  let get_override_return = ctx.create_function(get_override_return).unwrap();
+  lib_meta.raw_set("GetPreviousResult", get_previous_result).unwrap();
+  lib_meta.raw_set("GetOriginalReturn", get_original_return).unwrap();
+  lib_meta.raw_set("GetOverrideReturn", get_override_return).unwrap();
+
+  lib_meta
+}
+
+/// Result that the plugins before Luna returned from the current hook.
+pub fn get_previous_result(_: rlua::Context, _: ()) -> rlua::Result<MetaResult> {
+  Ok(previous_meta_result())
+}
+
+// Only meaningful in post hooks of game functions that return an integer,
+// e.g. `ClientConnect`.
+
+pub fn get_original_return(_: rlua::Context, _: ()) -> rlua::Result<Option<i64>> {
+  Ok(meta_orig_return().map(i64::from))
+}
+
+pub fn get_override_return(_: rlua::Context, _: ()) -> rlua::Result<Option<i64>> {
+  Ok(meta_override_return().map(i64::from))
+}
@@ -0,0 +1,45 @@
+use std::os::raw::c_int;
+use crate::ffi_wrapper::{player_auth_id, player_user_id};
+use crate::ffi_wrapper::player::{PlayerHandle, connected_players};
+
+/// `Luna/Players` finds the players on the server. Every lookup returns
+/// `nil` if nobody matches.
+pub fn create_lib<'lua>(ctx: &rlua::Context<'lua>) -> rlua::Table<'lua> {
+  let lib_players: rlua::Table = ctx.create_table().unwrap();
+
+  let get_all = ctx.create_function(|_, ()| Ok(connected_players())).unwrap();
+  let get_count = ctx.create_function(|_, ()| Ok(connected_players().len())).unwrap();
+  let get_by_index = ctx.create_function(
+    |_, index: c_int| Ok(PlayerHandle::from_index(index))
+  ).unwrap();
+  let get_by_user_id = ctx.create_function(|_, user_id: c_int| {
+    Ok(find(|player| player.get().map(player_user_id) == Some(user_id)))
+  }).unwrap();
+  let get_by_auth_id = ctx.create_function(|_, auth_id: String| {
+    Ok(find(|player| {
+      player.get().and_then(player_auth_id).as_deref() == Some(auth_id.as_str())
+    }))
+  }).unwrap();
+  // Case-insensitive, returns every player whose name contains `name`
+  let find_by_name = ctx.create_function(|_, name: String| {
+    let name = name.to_lowercase();
+    Ok(connected_players().into_iter()
+      .filter(|player| {
+        player.name().is_some_and(|n| n.to_lowercase().contains(&name))
+      })
+      .collect::<Vec<_>>())
+  }).unwrap();
+
+  lib_players.raw_set("GetAll", get_all).unwrap();
+  lib_players.raw_set("GetCount", get_count).unwrap();
+  lib_players.raw_set("GetByIndex", get_by_index).unwrap();
+  lib_players.raw_set("GetByUserId", get_by_user_id).unwrap();
+  lib_players.raw_set("GetByAuthId", get_by_auth_id).unwrap();
+  lib_players.raw_set("FindByName", find_by_name).unwrap();
+
+  lib_players
+}
+
+fn find(f: impl Fn(&PlayerHandle) -> bool) -> Option<PlayerHandle> {
+  connected_players().into_iter().find(f)
+}
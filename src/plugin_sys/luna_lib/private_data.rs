@@ -0,0 +1,31 @@
+use crate::ffi_wrapper::hl_lua_bridge::EntityHandle;
+use crate::ffi_wrapper::private_data::{self, PrivateDataHandle};
+
+/// `Luna/PrivateData` reads and writes the fields of the game's C++ entity
+/// classes, which the mod's `PrivateData/<game dir>.toml` lists:
+///
+/// ```lua
+/// local data = PrivateData.Of(player)
+/// data.m_iAccount = data.m_iAccount + 100
+/// ```
+///
+/// Nothing checks that the entity is of a class that has the field, that's
+/// up to the plugin.
+pub fn create_lib<'lua>(ctx: &rlua::Context<'lua>) -> rlua::Table<'lua> {
+  let lib_private_data: rlua::Table = ctx.create_table().unwrap();
+
+  let of = ctx.create_function(|_, entity: EntityHandle| {
+    match entity.is_valid() {
+      true => Ok(PrivateDataHandle::new(entity)),
+      false => Err(rlua::Error::RuntimeError("Invalid entity".into())),
+    }
+  }).unwrap();
+  let has = ctx.create_function(|_, name: String| {
+    Ok(private_data::is_field(&name))
+  }).unwrap();
+
+  lib_private_data.raw_set("Of", of).unwrap();
+  lib_private_data.raw_set("Has", has).unwrap();
+
+  lib_private_data
+}
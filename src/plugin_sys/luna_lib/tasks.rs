@@ -0,0 +1,93 @@
+use crate::global_state::GlobalStateUserData;
+use crate::plugin_sys::tasks;
+
+// Rust functions can't yield, so the waiting functions are written in Lua.
+// What they yield is interpreted by `TaskScheduler::set_wait`.
+const WAIT_FUNCTIONS: &str = r#"
+  local yield, is_yieldable = coroutine.yield, coroutine.isyieldable
+
+  local function check(name)
+    if not is_yieldable() then
+      error(name .. " can only be called from a task", 3)
+    end
+  end
+
+  local function wait(seconds)
+    check("Wait")
+    yield("Wait", seconds)
+  end
+
+  local function wait_frames(frames)
+    check("WaitFrames")
+    yield("WaitFrames", frames or 1)
+  end
+
+  local function wait_for_event(name, filter)
+    check("WaitForEvent")
+    return yield("WaitForEvent", name, filter)
+  end
+
+  return wait, wait_frames, wait_for_event
+"#;
+
+/// Returned by `Spawn` so the task can be cancelled.
+#[derive(Clone, Copy)]
+pub struct TaskHandle(u64);
+
+impl rlua::UserData for TaskHandle {
+  fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+    methods.add_method("Cancel", |ctx, handle: &Self, ()| {
+      cancel(ctx, *handle)
+    });
+    methods.add_method("IsActive", |ctx, handle: &Self, ()| {
+      let globals = ctx.globals();
+      let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
+      let state = state.0.lock().unwrap();
+      Ok(state.tasks.is_active(handle.0))
+    });
+  }
+}
+
+pub fn create_lib<'lua>(
+  ctx: &rlua::Context<'lua>,
+  owner: &str,
+) -> rlua::Table<'lua> {
+  let lib_tasks: rlua::Table = ctx.create_table().unwrap();
+
+  let owner = owner.to_string();
+  let spawn = ctx.create_function(
+    move |ctx, params| spawn(ctx, &owner, params)
+  ).unwrap();
+
+  let (wait, wait_frames, wait_for_event): (rlua::Function, rlua::Function, rlua::Function) =
+    ctx.load(WAIT_FUNCTIONS).set_name("Luna/Tasks").unwrap().eval().unwrap();
+
+  lib_tasks.raw_set("Spawn", spawn).unwrap();
+  lib_tasks.raw_set("Cancel", ctx.create_function(cancel).unwrap()).unwrap();
+  lib_tasks.raw_set("Wait", wait).unwrap();
+  lib_tasks.raw_set("WaitFrames", wait_frames).unwrap();
+  lib_tasks.raw_set("WaitForEvent", wait_for_event).unwrap();
+
+  lib_tasks
+}
+
+pub fn spawn<'lua>(
+  ctx: rlua::Context<'lua>,
+  owner: &str,
+  params: (rlua::Function<'lua>, rlua::MultiValue<'lua>),
+) -> rlua::Result<TaskHandle> {
+  let func = params.0;
+  let args = params.1;
+
+  let globals = ctx.globals();
+  let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
+  let id = tasks::spawn(&ctx, &state.0, owner, func, args)?;
+  Ok(TaskHandle(id))
+}
+
+pub fn cancel(ctx: rlua::Context, handle: TaskHandle) -> rlua::Result<bool> {
+  let globals = ctx.globals();
+  let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
+  let mut state = state.0.lock().unwrap();
+  Ok(state.tasks.cancel(handle.0))
+}
@@ -0,0 +1,75 @@
+use crate::ffi_wrapper::engine_time;
+use crate::global_state::GlobalStateUserData;
+
+/// Returned by the functions that create timers so they can be cancelled.
+#[derive(Clone, Copy)]
+pub struct TimerHandle(u64);
+
+impl rlua::UserData for TimerHandle {
+  fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+    methods.add_method("Cancel", |ctx, handle: &Self, ()| {
+      cancel(ctx, *handle)
+    });
+    methods.add_method("IsActive", |ctx, handle: &Self, ()| {
+      let globals = ctx.globals();
+      let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
+      let state = state.0.lock().unwrap();
+      Ok(state.timers.is_active(handle.0))
+    });
+  }
+}
+
+pub fn create_lib<'lua>(
+  ctx: &rlua::Context<'lua>,
+  owner: &str,
+) -> rlua::Table<'lua> {
+  let lib_timers: rlua::Table = ctx.create_table().unwrap();
+
+  let timeout_owner = owner.to_string();
+  let set_timeout = ctx.create_function(
+    move |ctx, (callback, delay): (rlua::Function, f64)| {
+      add_timer(ctx, &timeout_owner, callback, delay, false)
+    }
+  ).unwrap();
+  let interval_owner = owner.to_string();
+  let set_interval = ctx.create_function(
+    move |ctx, (callback, interval): (rlua::Function, f64)| {
+      add_timer(ctx, &interval_owner, callback, interval, true)
+    }
+  ).unwrap();
+  let next_frame_owner = owner.to_string();
+  let next_frame = ctx.create_function(
+    move |ctx, callback: rlua::Function| {
+      add_timer(ctx, &next_frame_owner, callback, 0.0, false)
+    }
+  ).unwrap();
+
+  lib_timers.raw_set("SetTimeout", set_timeout).unwrap();
+  lib_timers.raw_set("SetInterval", set_interval).unwrap();
+  lib_timers.raw_set("NextFrame", next_frame).unwrap();
+  lib_timers.raw_set("Cancel", ctx.create_function(cancel).unwrap()).unwrap();
+
+  lib_timers
+}
+
+pub fn add_timer<'lua>(
+  ctx: rlua::Context<'lua>,
+  owner: &str,
+  callback: rlua::Function<'lua>,
+  delay: f64,
+  repeat: bool,
+) -> rlua::Result<TimerHandle> {
+  let globals = ctx.globals();
+  let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
+  let mut state = state.0.lock().unwrap();
+  let now = f64::from(engine_time());
+  let id = state.timers.add_timer(&ctx, owner, callback, delay, repeat, now);
+  Ok(TimerHandle(id))
+}
+
+pub fn cancel(ctx: rlua::Context, handle: TimerHandle) -> rlua::Result<bool> {
+  let globals = ctx.globals();
+  let state: GlobalStateUserData = globals.get("luna_global_state").unwrap();
+  let mut state = state.0.lock().unwrap();
+  Ok(state.timers.cancel(handle.0))
+}
@@ -0,0 +1,64 @@
+use crate::global_state::GlobalStateUserData;
+use crate::plugin_sys::virtual_hooks;
+
+/// `Luna/VirtualHooks` hooks virtual functions of the game's entity classes,
+/// for things the game never tells Metamod about:
+///
+/// ```lua
+/// VirtualHooks.Pre('player', 'TakeDamage', function(player, inflictor, attacker, damage, bits)
+///   if attacker == player then
+///     return Meta.Supercede, 0
+///   end
+/// end)
+/// ```
+///
+/// The functions that can be hooked and their arguments are listed per mod
+/// in `VirtualHooks/<game dir>.toml`. Callbacks get the entity and the
+/// arguments, post hooks also what the function returned. They return a
+/// `Luna/Meta` result and, for `Override` and `Supercede`, the value to
+/// return instead.
+pub fn create_lib<'lua>(
+  ctx: &rlua::Context<'lua>,
+  owner: &str,
+) -> rlua::Table<'lua> {
+  let lib_virtual_hooks: rlua::Table = ctx.create_table().unwrap();
+
+  let pre_owner = owner.to_string();
+  let pre = ctx.create_function(
+    move |ctx, (class, function, callback): (String, String, rlua::Function)| {
+      add_hook(ctx, &pre_owner, &class, &function, false, callback)
+    }
+  ).unwrap();
+  let post_owner = owner.to_string();
+  let post = ctx.create_function(
+    move |ctx, (class, function, callback): (String, String, rlua::Function)| {
+      add_hook(ctx, &post_owner, &class, &function, true, callback)
+    }
+  ).unwrap();
+  // Plugins can only remove their own hooks
+  let remove_owner = owner.to_string();
+  let remove = ctx.create_function(move |ctx, id: u64| {
+    let state: GlobalStateUserData = ctx.globals().get("luna_global_state").unwrap();
+    let mut state = state.0.lock().unwrap();
+    Ok(state.virtual_hooks.remove_hook(&remove_owner, id))
+  }).unwrap();
+
+  lib_virtual_hooks.raw_set("Pre", pre).unwrap();
+  lib_virtual_hooks.raw_set("Post", post).unwrap();
+  lib_virtual_hooks.raw_set("Remove", remove).unwrap();
+
+  lib_virtual_hooks
+}
+
+fn add_hook<'lua>(
+  ctx: rlua::Context<'lua>,
+  owner: &str,
+  class: &str,
+  function: &str,
+  post: bool,
+  callback: rlua::Function<'lua>,
+) -> rlua::Result<u64> {
+  let state: GlobalStateUserData = ctx.globals().get("luna_global_state").unwrap();
+  virtual_hooks::add_hook(&ctx, &state.0, owner, class, function, post, callback)
+    .map_err(rlua::Error::RuntimeError)
+}
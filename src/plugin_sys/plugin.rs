@@ -1,7 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{PathBuf, Path};
 use std::error::Error;
 use std::fs;
+use semver::{Version, VersionReq};
 use serde_derive::{Serialize, Deserialize};
 
 
@@ -24,14 +25,30 @@ struct Manifest {
   #[serde(default)]
   pub dependencies: HashMap<String, String>,
   #[serde(default)]
+  pub optional_dependencies: HashMap<String, String>,
+  #[serde(default)]
+  pub load_after: Vec<String>,
+  #[serde(default)]
+  pub load_priority: i32,
+  #[serde(default)]
   pub metadata: HashMap<String, String>,
 }
 
-fn load_manifest(
-  file_path: impl Into<PathBuf>
-) -> Result<Manifest, Box<dyn Error>> {
-  let contents = fs::read_to_string(file_path.into())?;
-  Ok(toml::from_str(&contents)?)
+fn parse_requirements(
+  requirements: HashMap<String, String>,
+) -> Result<HashMap<String, VersionReq>, Box<dyn Error>> {
+  let mut parsed = HashMap::new();
+  for (dep_ident, dep_version) in requirements {
+    let requirement = VersionReq::parse(&dep_version).map_err(|err| {
+      std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Invalid version requirement for \"{}\": {}", dep_ident, err),
+      )
+    })?;
+    parsed.insert(dep_ident, requirement);
+  }
+
+  Ok(parsed)
 }
 
 pub struct Plugin {
@@ -39,7 +56,12 @@ pub struct Plugin {
   directory: PathBuf,
   main_source_path: PathBuf,
   info: PluginInfo,
-  dependencies: HashMap<String, String>,
+  version: Version,
+  dependencies: HashMap<String, VersionReq>,
+  optional_dependencies: HashMap<String, VersionReq>,
+  found_optional_dependencies: HashSet<String>,
+  load_after: Vec<String>,
+  load_priority: i32,
   metadata: HashMap<String, String>,
 }
 
@@ -62,14 +84,40 @@ impl Plugin {
     let mut manifest_path = dir.clone();
     manifest_path.push("Plugin.toml");
 
-    let manifest = load_manifest(manifest_path)?;
+    let manifest = fs::read_to_string(manifest_path)?;
+    Self::from_manifest(dir, main_path, identifier, &manifest)
+  }
+
+  // Builds the plugin from the contents of its `Plugin.toml`.
+  fn from_manifest(
+    dir: PathBuf,
+    main_path: PathBuf,
+    identifier: &str,
+    manifest: &str,
+  ) -> Result<Self, Box<dyn Error>> {
+    let manifest: Manifest = toml::from_str(manifest)?;
+    let version = Version::parse(&manifest.info.version).map_err(|err| {
+      std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Invalid plugin version \"{}\": {}", manifest.info.version, err),
+      )
+    })?;
+
+    let dependencies = parse_requirements(manifest.dependencies)?;
+    let optional_dependencies =
+      parse_requirements(manifest.optional_dependencies)?;
     
     Ok(Plugin {
       identifier: identifier.to_string(),
-      main_source_path: main_path.clone(),
-      directory: dir.clone(),
+      main_source_path: main_path,
+      directory: dir,
       info: manifest.info,
-      dependencies: manifest.dependencies,
+      version,
+      dependencies,
+      optional_dependencies,
+      found_optional_dependencies: HashSet::new(),
+      load_after: manifest.load_after,
+      load_priority: manifest.load_priority,
       metadata: manifest.metadata,
     })
   }
@@ -82,6 +130,10 @@ impl Plugin {
     &self.info
   }
 
+  pub fn version(&self) -> &Version {
+    &self.version
+  }
+
   pub fn metadata(&self) -> &HashMap<String, String> {
     &self.metadata
   }
@@ -90,11 +142,81 @@ impl Plugin {
     &self.main_source_path
   }
 
-  pub fn dependencies(&self) -> &HashMap<String, String> {
+  pub fn dependencies(&self) -> &HashMap<String, VersionReq> {
     &self.dependencies
   }
 
+  pub fn optional_dependencies(&self) -> &HashMap<String, VersionReq> {
+    &self.optional_dependencies
+  }
+
+  pub fn has_optional_dependency(&self, identifier: &str) -> bool {
+    self.found_optional_dependencies.contains(identifier)
+  }
+
+  pub fn set_optional_dependency_found(&mut self, identifier: &str) {
+    self.found_optional_dependencies.insert(identifier.to_string());
+  }
+
+  pub fn load_after(&self) -> &Vec<String> {
+    &self.load_after
+  }
+
+  pub fn load_priority(&self) -> i32 {
+    self.load_priority
+  }
+
   pub fn directory(&self) -> &Path {
     &self.directory
   }
 }
+
+/// The contents of a `Plugin.toml` with `manifest` followed by a minimal
+/// `[Info]`.
+#[cfg(test)]
+pub(super) fn test_manifest(identifier: &str, version: &str, manifest: &str) -> String {
+  format!(
+    "{}\n[Info]\nTitle = \"{}\"\nVersion = \"{}\"\nAuthors = []\nInterface = 1\n",
+    manifest, identifier, version,
+  )
+}
+
+/// A plugin made from `test_manifest`.
+#[cfg(test)]
+pub(super) fn test_plugin(identifier: &str, version: &str, manifest: &str) -> Plugin {
+  let manifest = test_manifest(identifier, version, manifest);
+  Plugin::from_manifest(PathBuf::new(), PathBuf::new(), identifier, &manifest).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(version: &str, manifest: &str) -> Result<Plugin, Box<dyn Error>> {
+    let manifest = test_manifest("NS/Plugin", version, manifest);
+    Plugin::from_manifest(PathBuf::new(), PathBuf::new(), "NS/Plugin", &manifest)
+  }
+
+  #[test]
+  fn parses_version_and_requirements() {
+    let plugin = test_plugin("NS/Plugin", "1.2.3", "[Dependencies]\n\"NS/Dep\" = \"^1.4\"\n");
+    assert_eq!(*plugin.version(), Version::parse("1.2.3").unwrap());
+
+    let requirement = &plugin.dependencies()["NS/Dep"];
+    assert!(requirement.matches(&Version::parse("1.5.0").unwrap()));
+    assert!(!requirement.matches(&Version::parse("2.0.0").unwrap()));
+  }
+
+  #[test]
+  fn rejects_invalid_version() {
+    let error = parse("one", "").err().unwrap().to_string();
+    assert!(error.contains("Invalid plugin version \"one\""), "{}", error);
+  }
+
+  #[test]
+  fn rejects_invalid_requirement() {
+    let error = parse("1.0.0", "[Dependencies]\n\"NS/Dep\" = \"not a version\"\n")
+      .err().unwrap().to_string();
+    assert!(error.contains("Invalid version requirement for \"NS/Dep\""), "{}", error);
+  }
+}
@@ -0,0 +1,339 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use rlua::FromLua;
+use crate::ffi_wrapper::engine_time;
+use crate::global_state::GlobalState;
+use crate::lua_helpers::{call_plugin_lua, resume_plugin_thread};
+
+enum Wait {
+  // Running, or about to be resumed
+  Nothing,
+  Until(f64),
+  Frames(u32),
+  Event {
+    name: String,
+    filter: Option<rlua::RegistryKey>,
+  },
+}
+
+struct Task {
+  owner: String,
+  thread: rlua::RegistryKey,
+  wait: Wait,
+  // Bumped on every resume so that a task that is waiting for the same event
+  // again isn't resumed twice by one emit
+  resumes: u64,
+}
+
+/// Coroutines spawned by plugins through `Luna/Tasks`. A task tells Luna
+/// what it's waiting for by yielding, see `luna_lib::tasks`.
+pub struct TaskScheduler {
+  tasks: BTreeMap<u64, Task>,
+  next_id: u64,
+  last_time: f64,
+}
+
+impl TaskScheduler {
+  pub fn new() -> Self {
+    TaskScheduler {
+      tasks: BTreeMap::new(),
+      next_id: 1,
+      last_time: 0.0,
+    }
+  }
+
+  fn add_task<'lua>(
+    &mut self,
+    ctx: &rlua::Context<'lua>,
+    owner: &str,
+    thread: rlua::Thread<'lua>,
+  ) -> u64 {
+    let id = self.next_id;
+    self.next_id += 1;
+
+    self.tasks.insert(id, Task {
+      owner: owner.to_string(),
+      thread: ctx.create_registry_value(thread).unwrap(),
+      wait: Wait::Nothing,
+      resumes: 0,
+    });
+
+    id
+  }
+
+  pub fn cancel(&mut self, id: u64) -> bool {
+    self.tasks.remove(&id).is_some()
+  }
+
+  pub fn is_active(&self, id: u64) -> bool {
+    self.tasks.contains_key(&id)
+  }
+
+  /// Removes every task that was spawned by the plugin `owner`.
+  pub fn remove_plugin_tasks(&mut self, owner: &str) {
+    self.tasks.retain(|_, task| task.owner != owner);
+  }
+
+  // Moves on to the engine time `now`.
+  fn sync_time(&mut self, now: f64) {
+    // The engine's time starts over on every map change
+    if now < self.last_time {
+      let offset = self.last_time - now;
+      for task in self.tasks.values_mut() {
+        if let Wait::Until(until) = &mut task.wait {
+          *until -= offset;
+        }
+      }
+    }
+    self.last_time = now;
+  }
+
+  // Advances time and frames, returns the ids of the tasks that are ready to
+  // be resumed.
+  fn tick(&mut self, now: f64) -> Vec<u64> {
+    self.sync_time(now);
+
+    let mut ready = Vec::new();
+    for (&id, task) in self.tasks.iter_mut() {
+      let is_ready = match &mut task.wait {
+        Wait::Until(until) => *until <= now,
+        Wait::Frames(frames) => {
+          *frames = frames.saturating_sub(1);
+          *frames == 0
+        }
+        _ => false,
+      };
+
+      if is_ready {
+        ready.push(id);
+      }
+    }
+
+    ready
+  }
+
+  // Hands out the task's thread and marks it as running.
+  fn start_resume<'lua>(
+    &mut self,
+    ctx: &rlua::Context<'lua>,
+    id: u64,
+  ) -> Option<(String, rlua::Thread<'lua>)> {
+    let task = self.tasks.get_mut(&id)?;
+    task.wait = Wait::Nothing;
+    task.resumes += 1;
+    Some((task.owner.clone(), ctx.registry_value(&task.thread).unwrap()))
+  }
+
+  // Records what the task yielded at the engine time `now` as the thing it's
+  // waiting for.
+  fn set_wait<'lua>(
+    &mut self,
+    ctx: &rlua::Context<'lua>,
+    id: u64,
+    yielded: rlua::MultiValue<'lua>,
+    now: f64,
+  ) {
+    // Tasks can start waiting at any point of a frame, not just at its start
+    self.sync_time(now);
+    let task = match self.tasks.get_mut(&id) {
+      Some(task) => task,
+      None => return,
+    };
+
+    let mut yielded = yielded.into_iter();
+    let kind = yielded.next()
+      .and_then(|kind| String::from_lua(kind, *ctx).ok());
+    let arg = yielded.next().unwrap_or(rlua::Nil);
+
+    task.wait = match kind.as_deref() {
+      Some("Wait") => {
+        let seconds = f64::from_lua(arg, *ctx).unwrap_or(0.0);
+        Wait::Until(now + seconds.max(0.0))
+      }
+      Some("WaitFrames") => {
+        let frames = u32::from_lua(arg, *ctx).unwrap_or(1);
+        Wait::Frames(frames.max(1))
+      }
+      Some("WaitForEvent") => Wait::Event {
+        name: String::from_lua(arg, *ctx).unwrap_or_default(),
+        filter: match yielded.next() {
+          Some(rlua::Value::Function(filter)) => {
+            Some(ctx.create_registry_value(filter).unwrap())
+          }
+          _ => None,
+        },
+      },
+      // A plain `coroutine.yield` just gives other things a chance to run
+      _ => Wait::Frames(1),
+    };
+  }
+
+  fn event_waiters<'lua>(
+    &self,
+    ctx: &rlua::Context<'lua>,
+    event_name: &str,
+  ) -> Vec<(u64, u64, String, Option<rlua::Function<'lua>>)> {
+    self.tasks.iter()
+      .filter_map(|(&id, task)| match &task.wait {
+        Wait::Event { name, filter } if name == event_name => Some((
+          id,
+          task.resumes,
+          task.owner.clone(),
+          filter.as_ref().map(|filter| ctx.registry_value(filter).unwrap()),
+        )),
+        _ => None,
+      })
+      .collect()
+  }
+
+  fn resumes(&self, id: u64) -> Option<u64> {
+    self.tasks.get(&id).map(|task| task.resumes)
+  }
+}
+
+/// Spawns a task owned by `owner` and runs it until it first waits.
+pub fn spawn<'lua>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  owner: &str,
+  func: rlua::Function<'lua>,
+  params: rlua::MultiValue<'lua>,
+) -> rlua::Result<u64> {
+  let thread = ctx.create_thread(func)?;
+  let id = state.lock().unwrap().tasks.add_task(ctx, owner, thread);
+  resume(ctx, state, id, params);
+  Ok(id)
+}
+
+// The state is only locked around the bookkeeping, never while the task runs.
+fn resume<'lua>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  id: u64,
+  params: rlua::MultiValue<'lua>,
+) {
+  let started = state.lock().unwrap().tasks.start_resume(ctx, id);
+  let (owner, thread) = match started {
+    Some(started) => started,
+    None => return,
+  };
+
+  let result = resume_plugin_thread(ctx, &owner, &thread, params);
+  let mut state = state.lock().unwrap();
+  match (result, thread.status()) {
+    (Ok(yielded), rlua::ThreadStatus::Resumable) => {
+      state.tasks.set_wait(ctx, id, yielded, f64::from(engine_time()));
+    }
+    // Finished or failed, the error has been reported already
+    _ => { state.tasks.cancel(id); }
+  }
+}
+
+/// Resumes every task that is done waiting at the engine time `now`.
+pub fn run_tasks<'lua>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  now: f64,
+) {
+  let ready = state.lock().unwrap().tasks.tick(now);
+  for id in ready {
+    resume(ctx, state, id, rlua::MultiValue::new());
+  }
+}
+
+/// Resumes the tasks waiting for `event_name` whose filter accepts `params`.
+/// The event's parameters are what `WaitForEvent` returns.
+pub fn resume_event_waiters<'lua>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  event_name: &str,
+  params: rlua::MultiValue<'lua>,
+) {
+  let waiters = state.lock().unwrap().tasks.event_waiters(ctx, event_name);
+  for (id, resumes, owner, filter) in waiters {
+    // Skip tasks that were cancelled or resumed since
+    if state.lock().unwrap().tasks.resumes(id) != Some(resumes) {
+      continue;
+    }
+
+    if let Some(filter) = filter {
+      match call_plugin_lua::<_, bool>(ctx, &owner, &filter, params.clone()) {
+        Ok(true) => { }
+        Ok(false) => continue,
+        Err(_) => {
+          state.lock().unwrap().tasks.cancel(id);
+          continue;
+        }
+      }
+    }
+
+    resume(ctx, state, id, params.clone());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Adds a task that yielded `yielded` at `now`.
+  fn add_waiting(lua: &rlua::Lua, tasks: &mut TaskScheduler, yielded: &str, now: f64) -> u64 {
+    lua.context(|ctx| {
+      let func = ctx.load("function() end").eval().unwrap();
+      let id = tasks.add_task(&ctx, "test", ctx.create_thread(func).unwrap());
+      let yielded = ctx.load(yielded).eval().unwrap();
+      tasks.set_wait(&ctx, id, yielded, now);
+      id
+    })
+  }
+
+  #[test]
+  fn waits_start_at_the_time_they_were_made() {
+    let lua = rlua::Lua::new();
+    let mut tasks = TaskScheduler::new();
+    assert!(tasks.tick(1.0).is_empty());
+
+    // Started waiting halfway through the frame that started at 1.0
+    let id = add_waiting(&lua, &mut tasks, "'Wait', 1", 1.5);
+    assert!(tasks.tick(2.0).is_empty());
+    assert_eq!(tasks.tick(2.5), vec![id]);
+  }
+
+  #[test]
+  fn waits_survive_a_map_change() {
+    let lua = rlua::Lua::new();
+    let mut tasks = TaskScheduler::new();
+    let id = add_waiting(&lua, &mut tasks, "'Wait', 2", 100.0);
+
+    // The engine's time starts over, the task still has 2 seconds to go
+    let other = add_waiting(&lua, &mut tasks, "'Wait', 1", 0.5);
+    assert!(tasks.tick(1.0).is_empty());
+    assert_eq!(tasks.tick(2.5), vec![id, other]);
+  }
+
+  #[test]
+  fn frames_are_counted_down() {
+    let lua = rlua::Lua::new();
+    let mut tasks = TaskScheduler::new();
+    let id = add_waiting(&lua, &mut tasks, "'WaitFrames', 2", 0.0);
+    let plain = add_waiting(&lua, &mut tasks, "nil", 0.0);
+
+    assert_eq!(tasks.tick(0.1), vec![plain]);
+    lua.context(|ctx| assert!(tasks.start_resume(&ctx, plain).is_some()));
+    assert_eq!(tasks.tick(0.2), vec![id]);
+  }
+
+  #[test]
+  fn event_waiters_are_found_by_name() {
+    let lua = rlua::Lua::new();
+    let mut tasks = TaskScheduler::new();
+    let id = add_waiting(&lua, &mut tasks, "'WaitForEvent', 'Event'", 0.0);
+    add_waiting(&lua, &mut tasks, "'WaitForEvent', 'Other'", 0.0);
+
+    assert!(tasks.tick(10.0).is_empty());
+    lua.context(|ctx| {
+      let waiters = tasks.event_waiters(&ctx, "Event");
+      assert_eq!(waiters.len(), 1);
+      assert_eq!(waiters[0].0, id);
+    });
+  }
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use crate::global_state::GlobalState;
+use crate::lua_helpers::call_plugin_lua;
+
+struct Timer {
+  owner: String,
+  callback: rlua::RegistryKey,
+  next_run: f64,
+  // `None` for timers that only run once
+  interval: Option<f64>,
+}
+
+/// Timers created by plugins, run at the start of every server frame.
+pub struct TimerScheduler {
+  timers: BTreeMap<u64, Timer>,
+  next_id: u64,
+  last_time: f64,
+}
+
+impl TimerScheduler {
+  pub fn new() -> Self {
+    TimerScheduler {
+      timers: BTreeMap::new(),
+      next_id: 1,
+      last_time: 0.0,
+    }
+  }
+
+  /// Schedules `callback` to run `delay` seconds after the engine time
+  /// `now`, and then every `delay` seconds if `repeat` is set. Returns the id
+  /// of the timer.
+  pub fn add_timer<'lua>(
+    &mut self,
+    ctx: &rlua::Context<'lua>,
+    owner: &str,
+    callback: rlua::Function<'lua>,
+    delay: f64,
+    repeat: bool,
+    now: f64,
+  ) -> u64 {
+    let id = self.next_id;
+    self.next_id += 1;
+
+    // Timers can be created at any point of a frame, not just at its start
+    self.sync_time(now);
+    let delay = delay.max(0.0);
+    self.timers.insert(id, Timer {
+      owner: owner.to_string(),
+      callback: ctx.create_registry_value(callback).unwrap(),
+      next_run: now + delay,
+      interval: if repeat { Some(delay) } else { None },
+    });
+
+    id
+  }
+
+  pub fn cancel(&mut self, id: u64) -> bool {
+    self.timers.remove(&id).is_some()
+  }
+
+  pub fn is_active(&self, id: u64) -> bool {
+    self.timers.contains_key(&id)
+  }
+
+  /// Removes every timer that was created by the plugin `owner`.
+  pub fn remove_plugin_timers(&mut self, owner: &str) {
+    self.timers.retain(|_, timer| timer.owner != owner);
+  }
+
+  // Moves on to the engine time `now`.
+  fn sync_time(&mut self, now: f64) {
+    // The engine's time starts over on every map change
+    if now < self.last_time {
+      let offset = self.last_time - now;
+      self.timers.values_mut().for_each(|timer| timer.next_run -= offset);
+    }
+    self.last_time = now;
+  }
+
+  // Returns the ids of the timers that are due at `now`, earliest first.
+  fn due(&mut self, now: f64) -> Vec<u64> {
+    self.sync_time(now);
+
+    let mut due: Vec<(f64, u64)> = self.timers.iter()
+      .filter(|(_, timer)| timer.next_run <= now)
+      .map(|(&id, timer)| (timer.next_run, id))
+      .collect();
+    due.sort_by(|l, r| l.partial_cmp(r).unwrap());
+    due.into_iter().map(|(_, id)| id).collect()
+  }
+
+  // Reschedules or removes the timer and hands out its callback, unless it
+  // was cancelled in the meantime.
+  fn fire<'lua>(
+    &mut self,
+    ctx: &rlua::Context<'lua>,
+    id: u64,
+  ) -> Option<(String, rlua::Function<'lua>)> {
+    let now = self.last_time;
+    let timer = self.timers.get_mut(&id)?;
+    let callback = ctx.registry_value(&timer.callback).unwrap();
+    let owner = timer.owner.clone();
+
+    match timer.interval {
+      // Don't try to catch up on runs that were missed because of a hitch
+      Some(interval) => timer.next_run = (timer.next_run + interval).max(now),
+      None => { self.timers.remove(&id); }
+    }
+
+    Some((owner, callback))
+  }
+}
+
+/// Runs every timer that is due at the engine time `now`. Like with events,
+/// the state is only locked while a callback is looked up.
+pub fn run_timers<'lua>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  now: f64,
+) {
+  let due = state.lock().unwrap().timers.due(now);
+  for id in due {
+    let fired = state.lock().unwrap().timers.fire(ctx, id);
+    if let Some((owner, callback)) = fired {
+      let _ = call_plugin_lua::<_, ()>(ctx, &owner, &callback, ());
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn add(lua: &rlua::Lua, timers: &mut TimerScheduler, delay: f64, repeat: bool, now: f64) -> u64 {
+    lua.context(|ctx| {
+      let callback = ctx.load("function() end").eval().unwrap();
+      timers.add_timer(&ctx, "test", callback, delay, repeat, now)
+    })
+  }
+
+  #[test]
+  fn timers_start_at_the_time_they_were_created() {
+    let lua = rlua::Lua::new();
+    let mut timers = TimerScheduler::new();
+    assert!(timers.due(1.0).is_empty());
+
+    // Created halfway through the frame that started at 1.0
+    let id = add(&lua, &mut timers, 1.0, false, 1.5);
+    assert!(timers.due(2.0).is_empty());
+    assert_eq!(timers.due(2.5), vec![id]);
+  }
+
+  #[test]
+  fn due_timers_are_ordered_by_time() {
+    let lua = rlua::Lua::new();
+    let mut timers = TimerScheduler::new();
+    let late = add(&lua, &mut timers, 2.0, false, 0.0);
+    let early = add(&lua, &mut timers, 1.0, false, 0.0);
+    assert_eq!(timers.due(3.0), vec![early, late]);
+  }
+
+  #[test]
+  fn repeating_timers_are_rescheduled() {
+    let lua = rlua::Lua::new();
+    let mut timers = TimerScheduler::new();
+    let id = add(&lua, &mut timers, 1.0, true, 0.0);
+
+    lua.context(|ctx| {
+      assert_eq!(timers.due(1.0), vec![id]);
+      assert!(timers.fire(&ctx, id).is_some());
+      assert!(timers.due(1.5).is_empty());
+      assert_eq!(timers.due(2.0), vec![id]);
+    });
+  }
+
+  #[test]
+  fn one_shot_timers_are_removed_once_fired() {
+    let lua = rlua::Lua::new();
+    let mut timers = TimerScheduler::new();
+    let id = add(&lua, &mut timers, 0.0, false, 0.0);
+
+    lua.context(|ctx| {
+      assert_eq!(timers.due(0.0), vec![id]);
+      assert!(timers.fire(&ctx, id).is_some());
+      assert!(!timers.is_active(id));
+    });
+  }
+
+  #[test]
+  fn timers_survive_a_map_change() {
+    let lua = rlua::Lua::new();
+    let mut timers = TimerScheduler::new();
+    assert!(timers.due(100.0).is_empty());
+    let id = add(&lua, &mut timers, 2.0, false, 100.0);
+
+    // The engine's time starts over, the timer still has 2 seconds to go
+    let other = add(&lua, &mut timers, 1.0, false, 0.5);
+    assert!(timers.due(1.0).is_empty());
+    assert_eq!(timers.due(2.5), vec![other, id]);
+  }
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use rlua::{FromLua, ToLua};
+use crate::ffi_wrapper::{is_map_running, log_error};
+use crate::ffi_wrapper::dll_hooks::HookResult;
+use crate::ffi_wrapper::virtual_hooks::{self as vtable_hooks, VirtualCall, VirtualValue};
+use crate::global_state::GlobalState;
+use crate::lua_helpers::{call_plugin_lua, print_lua_error};
+use crate::meta_ffi::types::MetaResult;
+
+struct VirtualHook {
+  owner: String,
+  class: String,
+  function: String,
+  post: bool,
+  callback: rlua::RegistryKey,
+  // Whether the class's vtable has been patched yet
+  hooked: bool,
+}
+
+/// Lua callbacks on virtual functions of game classes, registered through
+/// `Luna/VirtualHooks`.
+pub struct VirtualHookRegistry {
+  hooks: BTreeMap<u64, VirtualHook>,
+  next_id: u64,
+}
+
+impl VirtualHookRegistry {
+  pub fn new() -> Self {
+    VirtualHookRegistry {
+      hooks: BTreeMap::new(),
+      next_id: 1,
+    }
+  }
+
+  /// Removes the hook `id` if it was registered by the plugin `owner`.
+  pub fn remove_hook(&mut self, owner: &str, id: u64) -> bool {
+    match self.hooks.get(&id) {
+      Some(hook) if hook.owner == owner => self.hooks.remove(&id).is_some(),
+      _ => false,
+    }
+  }
+
+  /// Removes every hook that was registered by the plugin `owner`. The
+  /// vtables stay patched, calls just go through to the game.
+  pub fn remove_plugin_hooks(&mut self, owner: &str) {
+    self.hooks.retain(|_, hook| hook.owner != owner);
+  }
+
+  fn callbacks<'lua>(
+    &self,
+    ctx: &rlua::Context<'lua>,
+    call: &VirtualCall,
+    post: bool,
+  ) -> Vec<(String, rlua::Function<'lua>)> {
+    self.hooks.values()
+      .filter(|hook| hook.post == post && hook.function == call.function)
+      .filter(|hook| call.classes.contains(&hook.class))
+      .map(|hook| (hook.owner.clone(), ctx.registry_value(&hook.callback).unwrap()))
+      .collect()
+  }
+}
+
+/// Adds a hook. The vtable is patched right away if a map is running,
+/// otherwise once the next one starts.
+pub fn add_hook<'lua>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  owner: &str,
+  class: &str,
+  function: &str,
+  post: bool,
+  callback: rlua::Function<'lua>,
+) -> Result<u64, String> {
+  if !vtable_hooks::is_function(function) {
+    return Err(format!("Unknown virtual function \"{}\"", function));
+  }
+
+  let hooked = is_map_running();
+  if hooked {
+    vtable_hooks::hook(class, function)?;
+  }
+
+  let mut state = state.lock().unwrap();
+  let registry = &mut state.virtual_hooks;
+  let id = registry.next_id;
+  registry.next_id += 1;
+  registry.hooks.insert(id, VirtualHook {
+    owner: owner.to_string(),
+    class: class.to_string(),
+    function: function.to_string(),
+    post,
+    callback: ctx.create_registry_value(callback).unwrap(),
+    hooked,
+  });
+
+  Ok(id)
+}
+
+/// Patches the vtables for the hooks that were added while no map was
+/// running. Hooks of classes the game doesn't know are dropped.
+pub fn hook_pending(state: &Mutex<GlobalState>) {
+  let pending: Vec<(u64, String, String, String)> = state.lock().unwrap()
+    .virtual_hooks.hooks.iter()
+    .filter(|(_, hook)| !hook.hooked)
+    .map(|(&id, hook)| (id, hook.owner.clone(), hook.class.clone(), hook.function.clone()))
+    .collect();
+
+  for (id, owner, class, function) in pending {
+    let result = vtable_hooks::hook(&class, &function);
+    let mut state = state.lock().unwrap();
+    match result {
+      Ok(()) => {
+        if let Some(hook) = state.virtual_hooks.hooks.get_mut(&id) {
+          hook.hooked = true;
+        }
+      }
+      Err(e) => {
+        log_error(format!("Couldn't hook \"{}\" of plugin \"{}\": {}", function, owner, e));
+        state.virtual_hooks.hooks.remove(&id);
+      }
+    }
+  }
+}
+
+/// Calls the hooks of `call` with the entity, the arguments and, for post
+/// hooks, what the function returned. A hook returns a `Luna/Meta` result,
+/// followed by the value to return for `Override` and `Supercede`. The
+/// highest result wins, and so does its value.
+pub fn dispatch<'lua>(
+  ctx: &rlua::Context<'lua>,
+  state: &Mutex<GlobalState>,
+  call: &VirtualCall,
+  post: bool,
+) -> HookResult<VirtualValue> {
+  let callbacks = state.lock().unwrap().virtual_hooks.callbacks(ctx, call, post);
+  if callbacks.is_empty() {
+    return (MetaResult::Ignored, None);
+  }
+
+  let mut params = vec![call.entity.clone().to_lua(*ctx).unwrap()];
+  params.extend(call.args.iter().map(|arg| arg.clone().to_lua(*ctx).unwrap_or(rlua::Nil)));
+  if let Some(original) = &call.original_return {
+    params.push(original.clone().to_lua(*ctx).unwrap_or(rlua::Nil));
+  }
+  let params = rlua::MultiValue::from_vec(params);
+
+  let (mut result, mut value) = (MetaResult::Ignored, None);
+  for (owner, callback) in callbacks {
+    let returned: rlua::MultiValue =
+      match call_plugin_lua(ctx, &owner, &callback, params.clone()) {
+        Ok(returned) => returned,
+        Err(_) => continue,
+      };
+
+    let mut returned = returned.into_iter();
+    let hook_result = match MetaResult::from_lua(returned.next().unwrap_or(rlua::Nil), *ctx) {
+      Ok(hook_result) => hook_result,
+      Err(e) => {
+        print_lua_error(&e);
+        continue;
+      }
+    };
+
+    if hook_result >= result && hook_result >= MetaResult::Override {
+      if let Some(returned_value) = returned.next().filter(|v| !matches!(v, rlua::Value::Nil)) {
+        match VirtualValue::from_lua(call.return_type, returned_value, *ctx) {
+          Ok(returned_value) => value = Some(returned_value),
+          Err(e) => print_lua_error(&e),
+        }
+      }
+    }
+    result = result.max(hook_result);
+  }
+
+  (result, value)
+}
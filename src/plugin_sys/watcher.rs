@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use std::fs;
+
+/// Polls the modification times of plugin files.
+pub struct PluginWatcher {
+  interval: Duration,
+  last_poll: Instant,
+  mtimes: HashMap<PathBuf, SystemTime>,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+  fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+impl PluginWatcher {
+  pub fn new(interval: Duration) -> Self {
+    PluginWatcher {
+      interval,
+      last_poll: Instant::now(),
+      mtimes: HashMap::new(),
+    }
+  }
+
+  /// Whether enough time has passed since the last poll.
+  pub fn should_poll(&mut self) -> bool {
+    if self.last_poll.elapsed() < self.interval {
+      return false;
+    }
+
+    self.last_poll = Instant::now();
+    true
+  }
+
+  /// Returns whether any of `files` changed since they were last seen and
+  /// remembers their current state. Files that weren't tracked before only
+  /// start being tracked.
+  pub fn check<'a>(&mut self, files: impl IntoIterator<Item = &'a PathBuf>) -> bool {
+    let mut changed = false;
+    for file in files {
+      let mtime = match modified(file) {
+        Some(mtime) => mtime,
+        None => continue,
+      };
+
+      match self.mtimes.insert(file.clone(), mtime) {
+        Some(old_mtime) if old_mtime != mtime => changed = true,
+        _ => { }
+      }
+    }
+
+    changed
+  }
+}